@@ -0,0 +1,103 @@
+//! Command loop and printing shared by the debuggers.
+
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::slice;
+use bigint::{U256, H256};
+use sputnikvm::{Memory, Patch, SeqMemory, Stack};
+
+/// Parse a decimal or `0x` prefixed hexadecimal number.
+pub fn parse_number(value: &str) -> Option<usize> {
+    if value.starts_with("0x") {
+        usize::from_str_radix(&value[2..], 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+pub fn hexdump(offset: usize, data: &[u8]) {
+    for (row, chunk) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = chunk.iter()
+            .map(|&byte| if byte >= 0x20 && byte < 0x7f { byte as char } else { '.' })
+            .collect();
+        println!("0x{:06x}: {:<47}  {}", offset + row * 16, hex.join(" "), ascii);
+    }
+}
+
+/// Print the stack, top first.
+pub fn print_stack(stack: &Stack) {
+    for i in 0..stack.len() {
+        println!("{:4}: 0x{:x}", i, H256::from(stack.peek(i).unwrap()));
+    }
+}
+
+/// Print the memory for `memory [offset [len]]`, by default all of
+/// it.
+pub fn print_memory<P: Patch>(memory: &SeqMemory<P>, args: &[&str]) {
+    let offset = args.get(0).and_then(|arg| parse_number(arg)).unwrap_or(0);
+    let len = args.get(1).and_then(|arg| parse_number(arg))
+        .unwrap_or_else(|| memory.len().saturating_sub(offset));
+    hexdump(offset, &memory.read_slice(U256::from(offset), U256::from(len)));
+}
+
+/// Breakpoints, numbered in the order they were set.
+pub struct Breakpoints<B> {
+    breakpoints: Vec<B>,
+}
+
+impl<B> Default for Breakpoints<B> {
+    fn default() -> Self {
+        Breakpoints { breakpoints: Vec::new() }
+    }
+}
+
+impl<B: Display> Breakpoints<B> {
+    pub fn iter(&self) -> slice::Iter<B> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add(&mut self, breakpoint: B) {
+        println!("Breakpoint {}: {}", self.breakpoints.len(), breakpoint);
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Delete the breakpoint numbered by `delete <n>`.
+    pub fn delete(&mut self, args: &[&str]) {
+        match args.get(0).and_then(|arg| parse_number(arg)) {
+            Some(i) if i < self.breakpoints.len() => { self.breakpoints.remove(i); },
+            _ => println!("Usage: delete <breakpoint number>"),
+        }
+    }
+
+    pub fn print(&self) {
+        for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+            println!("{}: {}", i, breakpoint);
+        }
+    }
+}
+
+/// Read commands from stdin until `quit` or the end of input, and
+/// pass each with its arguments to `handle`. An empty line repeats
+/// the last command.
+pub fn run_commands<F: FnMut(&str, &[&str])>(prompt: &str, mut handle: F) {
+    let stdin = io::stdin();
+    let mut last = String::new();
+    loop {
+        print!("{} ", prompt);
+        io::stdout().flush().unwrap();
+        let mut input = String::new();
+        if stdin.lock().read_line(&mut input).unwrap() == 0 {
+            return;
+        }
+        let input = if input.trim().is_empty() { last.clone() } else { input.trim().to_string() };
+        last = input.clone();
+
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words.split_first() {
+            Some((&"q", _)) | Some((&"quit", _)) => return,
+            Some((command, args)) => handle(command, args),
+            None => (),
+        }
+    }
+}
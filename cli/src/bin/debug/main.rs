@@ -0,0 +1,68 @@
+#[macro_use]
+extern crate clap;
+extern crate bigint;
+extern crate hexutil;
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+extern crate serde_json;
+extern crate sha3;
+extern crate gethrpc;
+
+#[path = "../console.rs"]
+mod console;
+mod source;
+mod repl;
+
+use clap::ArgMatches;
+use sputnikvm::Patch;
+use sputnikvm_network_classic::{PatchFn, with_patch};
+use source::{Source, FixtureSource, RPCSource};
+use repl::Debugger;
+
+struct DebugMode<S: Source>(S);
+impl<S: Source> PatchFn for DebugMode<S> {
+    type Output = Result<(), String>;
+    fn call<P: Patch + 'static>(self) -> Result<(), String> {
+        Debugger::<P, S>::new(self.0)?.run();
+        Ok(())
+    }
+}
+
+fn debug<S: Source>(source: S, patch: &str) -> Result<(), String> {
+    with_patch(patch, DebugMode(source)).unwrap_or_else(|| Err(format!("unsupported patch {}", patch)))
+}
+
+/// Print the error with the usage and exit, as clap does for invalid
+/// arguments.
+fn usage_error(matches: &ArgMatches, message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, matches.usage());
+    ::std::process::exit(2);
+}
+
+fn main() {
+    let matches = clap_app!(sputnikvm_debug =>
+        (version: "0.1")
+        (author: "Ethereum Classic Contributors")
+        (about: "Interactive debugger for SputnikVM transactions.")
+        (@arg FIXTURE: --fixture +takes_value "JSON fixture with the transaction, its block and the prestate.")
+        (@arg RECORDS: --records +takes_value "RPC record cache, as written by `regtests --record`.")
+        (@arg TRANSACTION: --transaction +takes_value "Hash of the transaction to debug from the RPC record cache.")
+        (@arg PATCH: --patch +takes_value +required "Patch to be used.")
+    ).get_matches();
+
+    let patch = matches.value_of("PATCH").unwrap();
+    let result = match (matches.value_of("FIXTURE"), matches.value_of("RECORDS")) {
+        (Some(fixture), None) => FixtureSource::load(fixture).and_then(|source| debug(source, patch)),
+        (None, Some(records)) => {
+            let hash = matches.value_of("TRANSACTION").unwrap_or_else(|| {
+                usage_error(&matches, "--transaction is required with --records")
+            });
+            RPCSource::load(records, hash).and_then(|source| debug(source, patch))
+        },
+        _ => usage_error(&matches, "exactly one of --fixture and --records is required"),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        ::std::process::exit(1);
+    }
+}
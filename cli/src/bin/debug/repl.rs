@@ -0,0 +1,317 @@
+use std::fmt;
+use std::str::FromStr;
+use bigint::{Address, U256, M256};
+use sputnikvm::{VM, VMStatus, Patch, Opcode, SeqTransactionVM, State, SeqMemory};
+
+use console::{self, Breakpoints, hexdump, parse_number};
+use source::Source;
+
+/// Number of steps between two snapshots of the VM kept for `back`.
+const SNAPSHOT_INTERVAL: usize = 1000;
+
+enum Breakpoint {
+    /// Before the opcode is run.
+    Opcode(Opcode),
+    /// When a frame of the address is entered.
+    Address(Address),
+    /// Before the storage slot is read or written.
+    Slot(U256),
+    /// After the storage slot is written, at the given address or at
+    /// any address.
+    Watch(U256, Option<Address>),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Opcode(opcode) => write!(f, "opcode {:?}", opcode),
+            Breakpoint::Address(address) => write!(f, "address 0x{:x}", address),
+            Breakpoint::Slot(index) => write!(f, "slot 0x{:x}", index),
+            Breakpoint::Watch(index, Some(address)) => write!(f, "watch 0x{:x} at 0x{:x}", index, address),
+            Breakpoint::Watch(index, None) => write!(f, "watch 0x{:x}", index),
+        }
+    }
+}
+
+/// A storage write that a watchpoint is waiting for.
+struct PendingWrite {
+    address: Address,
+    index: U256,
+    before: Option<M256>,
+}
+
+pub struct Debugger<P: Patch, S: Source> {
+    source: S,
+    vm: SeqTransactionVM<P>,
+    steps: usize,
+    /// Copies of the VM every `SNAPSHOT_INTERVAL` steps, with their
+    /// number of steps.
+    snapshots: Vec<(usize, SeqTransactionVM<P>)>,
+    breakpoints: Breakpoints<Breakpoint>,
+}
+
+fn parse_opcode(name: &str) -> Option<Opcode> {
+    let name = name.to_uppercase();
+    (0..256).map(|byte| Opcode::from(byte as u8))
+        .find(|opcode| format!("{:?}", opcode).replace("(", "").replace(")", "") == name)
+}
+
+impl<P: Patch, S: Source> Debugger<P, S> {
+    pub fn new(mut source: S) -> Result<Self, String> {
+        let vm = source.start()?;
+        Ok(Debugger {
+            source,
+            snapshots: vec![(0, vm.clone())],
+            vm,
+            steps: 0,
+            breakpoints: Breakpoints::default(),
+        })
+    }
+
+    fn running(&self) -> bool {
+        self.vm.status() == VMStatus::Running
+    }
+
+    fn state(&self) -> Option<&State<SeqMemory<P>, P>> {
+        if self.running() { self.vm.current_state() } else { None }
+    }
+
+    fn depth(&self) -> usize {
+        self.state().map(|state| state.depth).unwrap_or(0)
+    }
+
+    /// Run the next instruction, committing what it requires.
+    fn step(&mut self) -> Result<(), String> {
+        if !self.running() {
+            return Ok(());
+        }
+        loop {
+            match self.vm.step() {
+                Ok(()) => break,
+                Err(require) => self.source.commit(&mut self.vm, require)?,
+            }
+        }
+        self.steps += 1;
+        if self.steps % SNAPSHOT_INTERVAL == 0 {
+            self.snapshots.push((self.steps, self.vm.clone()));
+        }
+        Ok(())
+    }
+
+    /// Go back to the given number of steps, from the last snapshot
+    /// before it.
+    fn back(&mut self, steps: usize) -> Result<(), String> {
+        while self.snapshots.len() > 1 && self.snapshots.last().unwrap().0 > steps {
+            self.snapshots.pop();
+        }
+        let &(snapshot_steps, ref snapshot) = self.snapshots.last().unwrap();
+        self.vm = snapshot.clone();
+        self.steps = snapshot_steps;
+        while self.steps < steps && self.running() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Storage slot accessed by the next instruction, if it is
+    /// `SLOAD` or `SSTORE`.
+    fn next_slot(&self) -> Option<(Opcode, Address, U256)> {
+        let state = self.state()?;
+        match self.vm.peek_opcode()? {
+            opcode @ Opcode::SLOAD | opcode @ Opcode::SSTORE =>
+                Some((opcode, state.context.address, state.stack.peek(0).ok()?.into())),
+            _ => None,
+        }
+    }
+
+    fn at_breakpoint(&self, previous: Option<(usize, Address)>) -> bool {
+        let state = match self.state() {
+            Some(state) => state,
+            None => return false,
+        };
+        let opcode = self.vm.peek_opcode();
+        let slot = self.next_slot();
+        let entered = previous.map(|previous| previous != (state.depth, state.context.address)).unwrap_or(true);
+
+        self.breakpoints.iter().any(|breakpoint| match *breakpoint {
+            Breakpoint::Opcode(expected) => opcode == Some(expected),
+            Breakpoint::Address(address) => entered && state.context.address == address,
+            Breakpoint::Slot(index) => slot.map(|(_, _, slot)| slot == index).unwrap_or(false),
+            Breakpoint::Watch(_, _) => false,
+        })
+    }
+
+    /// If the next instruction is a write that a watchpoint waits for,
+    /// make sure the slot is loaded and return its current value.
+    fn pending_write(&mut self) -> Result<Option<PendingWrite>, String> {
+        let (address, index) = match self.next_slot() {
+            Some((Opcode::SSTORE, address, index)) => (address, index),
+            _ => return Ok(None),
+        };
+        let watched = self.breakpoints.iter().any(|breakpoint| match *breakpoint {
+            Breakpoint::Watch(slot, watched) => slot == index && watched.map(|w| w == address).unwrap_or(true),
+            _ => false,
+        });
+        if !watched {
+            return Ok(None);
+        }
+
+        let require = self.state().and_then(|state| state.account_state.require_storage(address, index).err());
+        if let Some(require) = require {
+            self.source.commit(&mut self.vm, require)?;
+        }
+        let before = self.state().and_then(|state| state.account_state.storage_read(address, index).ok());
+        Ok(Some(PendingWrite { address, index, before }))
+    }
+
+    /// Run until `stop` returns true, a breakpoint or watchpoint is
+    /// hit, or the VM exits.
+    fn run_until<F: Fn(&Self) -> bool>(&mut self, stop: F) -> Result<(), String> {
+        loop {
+            let previous = self.state().map(|state| (state.depth, state.context.address));
+            let pending = self.pending_write()?;
+            self.step()?;
+
+            if let Some(pending) = pending {
+                let after = self.vm.current_state()
+                    .and_then(|state| state.account_state.storage_read(pending.address, pending.index).ok());
+                let show = |value: Option<M256>| value.map(|value| format!("0x{:x}", U256::from(value)))
+                    .unwrap_or_else(|| "?".to_string());
+                println!("Watchpoint: 0x{:x} slot 0x{:x}: {} -> {}",
+                         pending.address, pending.index, show(pending.before), show(after));
+                return Ok(());
+            }
+            if !self.running() || stop(self) || self.at_breakpoint(previous) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) {
+        let breakpoint = match (args.get(0).cloned(), args.get(1).cloned()) {
+            (Some("opcode"), Some(name)) => parse_opcode(name).map(Breakpoint::Opcode),
+            (Some("address"), Some(address)) => Address::from_str(address).ok().map(Breakpoint::Address),
+            (Some("slot"), Some(index)) => U256::from_str(index).ok().map(Breakpoint::Slot),
+            _ => None,
+        };
+        match breakpoint {
+            Some(breakpoint) => self.breakpoints.add(breakpoint),
+            None => println!("Usage: break opcode <name> | break address <address> | break slot <index>"),
+        }
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str]) {
+        let index = args.get(0).and_then(|index| U256::from_str(index).ok());
+        let address = args.get(1).map(|address| Address::from_str(address).ok());
+        match (index, address) {
+            (Some(index), None) => self.breakpoints.add(Breakpoint::Watch(index, None)),
+            (Some(index), Some(Some(address))) => self.breakpoints.add(Breakpoint::Watch(index, Some(address))),
+            _ => println!("Usage: watch <index> [address]"),
+        }
+    }
+
+    fn print_position(&self) {
+        match self.state() {
+            Some(state) => {
+                let opcode = self.vm.peek_opcode().map(|opcode| format!("{:?}", opcode))
+                    .unwrap_or_else(|| "STOP".to_string());
+                println!("step {}, depth {}, 0x{:x}, pc 0x{:x}: {}",
+                         self.steps, state.depth, state.context.address, state.position, opcode);
+            },
+            None => {
+                println!("step {}, VM exited: {:?}", self.steps, self.vm.status());
+                println!("Used gas: 0x{:x}, out: 0x{}", self.vm.used_gas(),
+                         self.vm.out().iter().map(|b| format!("{:02x}", b)).collect::<String>());
+            },
+        }
+    }
+
+    fn print_gas(&self, state: &State<SeqMemory<P>, P>) {
+        println!("Gas limit: 0x{:x}", state.context.gas_limit);
+        println!("Used gas: 0x{:x}, of which memory: 0x{:x}", state.total_used_gas(), state.memory_gas());
+        println!("Available gas: 0x{:x}", state.available_gas());
+        println!("Refunded gas: 0x{:x}", state.refunded_gas);
+    }
+
+    fn print_logs(&self, state: &State<SeqMemory<P>, P>) {
+        for (i, log) in state.logs.iter().enumerate() {
+            println!("{}: 0x{:x}", i, log.address);
+            for topic in &log.topics {
+                println!("  topic 0x{:x}", topic);
+            }
+            hexdump(0, &log.data);
+        }
+    }
+
+    fn print(&self, what: &str, args: &[&str]) {
+        let state = match self.state() {
+            Some(state) => state,
+            None => {
+                println!("VM is not running");
+                return;
+            },
+        };
+        match what {
+            "stack" => console::print_stack(&state.stack),
+            "memory" => console::print_memory(&state.memory, args),
+            "returndata" => hexdump(0, &state.ret),
+            "logs" => self.print_logs(state),
+            "gas" => self.print_gas(state),
+            "state" => {
+                self.print_position();
+                self.print_gas(state);
+                println!("Stack:");
+                console::print_stack(&state.stack);
+                println!("Memory:");
+                console::print_memory(&state.memory, &[]);
+                println!("Return data:");
+                hexdump(0, &state.ret);
+                println!("Logs:");
+                self.print_logs(state);
+            },
+            _ => println!("Unknown command: {}", what),
+        }
+    }
+
+    /// Run the debugger until the user quits.
+    pub fn run(&mut self) {
+        println!("Commands: step [n], next, out, back [n], continue, break <opcode|address|slot> <value>, \
+                  watch <index> [address], delete <n>, breakpoints, where, stack, memory [offset [len]], \
+                  returndata, logs, gas, state, quit");
+        self.print_position();
+
+        console::run_commands("(sputnikvm)", |command, args| {
+            let result = match command {
+                "s" | "step" => {
+                    let count = args.get(0).and_then(|arg| parse_number(arg)).unwrap_or(1);
+                    Some((0..count).try_for_each(|_| self.step()))
+                },
+                "n" | "next" => {
+                    let depth = self.depth();
+                    Some(self.run_until(|debugger| debugger.depth() <= depth))
+                },
+                "o" | "out" => {
+                    let depth = self.depth();
+                    Some(self.run_until(|debugger| debugger.depth() < depth))
+                },
+                "back" => {
+                    let count = args.get(0).and_then(|arg| parse_number(arg)).unwrap_or(1);
+                    let steps = self.steps.saturating_sub(count);
+                    Some(self.back(steps))
+                },
+                "c" | "continue" => Some(self.run_until(|_| false)),
+                "b" | "break" => { self.add_breakpoint(args); None },
+                "watch" => { self.add_watchpoint(args); None },
+                "d" | "delete" => { self.breakpoints.delete(args); None },
+                "breakpoints" => { self.breakpoints.print(); None },
+                "w" | "where" => { self.print_position(); None },
+                _ => { self.print(command, args); None },
+            };
+            match result {
+                Some(Ok(())) => self.print_position(),
+                Some(Err(err)) => println!("Error: {}", err),
+                None => (),
+            }
+        });
+    }
+}
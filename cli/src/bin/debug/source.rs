@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::str::FromStr;
+use std::sync::Arc;
+use serde_json::Value;
+use bigint::{Gas, Address, U256, M256, H256};
+use hexutil::read_hex;
+use sputnikvm::{VM, SeqTransactionVM, ValidTransaction, HeaderParams, Patch, AccountCommitment,
+                RequireError, TransactionAction};
+use sputnikvm::errors::CommitError;
+use sha3::{Keccak256, Digest};
+use gethrpc::{GethRPCClient, CachedGethRPCClient, RPCBlock, RPCTransaction};
+
+/// Where the transaction being debugged and its prestate come from.
+/// A source must answer the same requirement the same way every
+/// time, so that the transaction can be replayed.
+pub trait Source {
+    /// Create a VM for the transaction being debugged.
+    fn start<P: Patch>(&mut self) -> Result<SeqTransactionVM<P>, String>;
+    /// Commit what the VM requires from the prestate.
+    fn commit(&mut self, vm: &mut VM, require: RequireError) -> Result<(), String>;
+}
+
+fn fire<S: Source>(source: &mut S, vm: &mut VM) -> Result<(), String> {
+    loop {
+        match vm.fire() {
+            Ok(()) => return Ok(()),
+            Err(require) => source.commit(vm, require)?,
+        }
+    }
+}
+
+fn committed(result: Result<(), CommitError>) -> Result<(), String> {
+    result.map_err(|err| format!("cannot commit: {:?}", err))
+}
+
+fn parse_str<T: FromStr>(value: &str, what: &str) -> Result<T, String> {
+    T::from_str(value).map_err(|_| format!("invalid {} {}", what, value))
+}
+
+fn string<'a>(value: &'a Value, name: &str) -> Result<&'a str, String> {
+    value[name].as_str().ok_or(format!("missing field {}", name))
+}
+
+fn parse<T: FromStr>(value: &Value, name: &str) -> Result<T, String> {
+    T::from_str(string(value, name)?).map_err(|_| format!("invalid field {}", name))
+}
+
+fn parse_or<T: FromStr>(value: &Value, name: &str, default: T) -> Result<T, String> {
+    match value[name].as_str() {
+        Some(_) => parse(value, name),
+        None => Ok(default),
+    }
+}
+
+fn bytes(value: &Value, name: &str) -> Result<Vec<u8>, String> {
+    match value[name].as_str() {
+        Some(hex) => read_hex(hex).map_err(|_| format!("invalid field {}", name)),
+        None => Ok(Vec::new()),
+    }
+}
+
+struct FixtureAccount {
+    nonce: U256,
+    balance: U256,
+    code: Vec<u8>,
+    storage: HashMap<U256, M256>,
+}
+
+/// A transaction with its block and prestate, read from a JSON
+/// fixture of the form
+/// `{ "env": { "currentCoinbase", "currentNumber", ... },
+///    "pre": { address: { "balance", "nonce", "code", "storage" } },
+///    "transaction": { "from", "to", "value", "gasLimit", "gasPrice", "nonce", "data" },
+///    "blockHashes": { number: hash } }`.
+/// Accounts that are not in `pre` do not exist. Without
+/// `blockHashes`, the hash of block `n` is the Keccak-256 hash of its
+/// decimal representation, as in the test suite.
+pub struct FixtureSource {
+    transaction: ValidTransaction,
+    block: HeaderParams,
+    accounts: HashMap<Address, FixtureAccount>,
+    block_hashes: Option<HashMap<U256, H256>>,
+}
+
+impl FixtureSource {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|err| format!("cannot open {}: {}", path, err))?;
+        let fixture: Value = serde_json::from_reader(file).map_err(|err| format!("invalid JSON: {}", err))?;
+
+        let env = &fixture["env"];
+        let block = HeaderParams {
+            beneficiary: parse_or(env, "currentCoinbase", Address::default())?,
+            timestamp: parse_or(env, "currentTimestamp", U256::zero())?.as_u64(),
+            number: parse_or(env, "currentNumber", U256::zero())?,
+            difficulty: parse_or(env, "currentDifficulty", U256::zero())?,
+            gas_limit: parse_or(env, "currentGasLimit", Gas::zero())?,
+        };
+
+        let transaction = &fixture["transaction"];
+        let action = match transaction["to"].as_str() {
+            Some(to) if !to.is_empty() => TransactionAction::Call(parse(transaction, "to")?),
+            _ => TransactionAction::Create,
+        };
+        let transaction = ValidTransaction {
+            caller: Some(parse(transaction, "from")?),
+            action,
+            value: parse_or(transaction, "value", U256::zero())?,
+            gas_limit: parse(transaction, "gasLimit")?,
+            gas_price: parse_or(transaction, "gasPrice", Gas::zero())?,
+            input: Arc::new(bytes(transaction, "data")?),
+            nonce: parse_or(transaction, "nonce", U256::zero())?,
+        };
+
+        let mut accounts = HashMap::new();
+        if let Some(pre) = fixture["pre"].as_object() {
+            for (address, account) in pre {
+                let address = Address::from_str(address).map_err(|_| format!("invalid address {}", address))?;
+                let mut storage = HashMap::new();
+                if let Some(entries) = account["storage"].as_object() {
+                    for (index, value) in entries {
+                        let index = U256::from_str(index).map_err(|_| format!("invalid storage index {}", index))?;
+                        let value = value.as_str().and_then(|value| M256::from_str(value).ok())
+                            .ok_or(format!("invalid storage value at 0x{:x}", index))?;
+                        storage.insert(index, value);
+                    }
+                }
+                accounts.insert(address, FixtureAccount {
+                    nonce: parse_or(account, "nonce", U256::zero())?,
+                    balance: parse_or(account, "balance", U256::zero())?,
+                    code: bytes(account, "code")?,
+                    storage,
+                });
+            }
+        }
+
+        let block_hashes = match fixture["blockHashes"].as_object() {
+            Some(entries) => {
+                let mut block_hashes = HashMap::new();
+                for (number, hash) in entries {
+                    let number = U256::from_str(number).map_err(|_| format!("invalid block number {}", number))?;
+                    let hash = hash.as_str().and_then(|hash| H256::from_str(hash).ok())
+                        .ok_or(format!("invalid hash of block 0x{:x}", number))?;
+                    block_hashes.insert(number, hash);
+                }
+                Some(block_hashes)
+            },
+            None => None,
+        };
+
+        Ok(FixtureSource { transaction, block, accounts, block_hashes })
+    }
+
+    fn block_hash(&self, number: U256) -> Result<H256, String> {
+        match self.block_hashes {
+            Some(ref block_hashes) => block_hashes.get(&number).cloned()
+                .ok_or(format!("no hash of block 0x{:x} in the fixture", number)),
+            None => Ok(H256::from(Keccak256::digest(format!("{}", number).as_bytes()).as_slice())),
+        }
+    }
+}
+
+impl Source for FixtureSource {
+    fn start<P: Patch>(&mut self) -> Result<SeqTransactionVM<P>, String> {
+        Ok(SeqTransactionVM::new(self.transaction.clone(), self.block.clone()))
+    }
+
+    fn commit(&mut self, vm: &mut VM, require: RequireError) -> Result<(), String> {
+        committed(match require {
+            RequireError::Account(address) | RequireError::AccountCode(address) => {
+                match self.accounts.get(&address) {
+                    Some(account) => vm.commit_account(AccountCommitment::Full {
+                        nonce: account.nonce,
+                        address,
+                        balance: account.balance,
+                        code: Arc::new(account.code.clone()),
+                    }),
+                    None => vm.commit_account(AccountCommitment::Nonexist(address)),
+                }
+            },
+            RequireError::AccountStorage(address, index) => {
+                let value = self.accounts.get(&address)
+                    .and_then(|account| account.storage.get(&index).cloned())
+                    .unwrap_or_else(M256::zero);
+                vm.commit_account(AccountCommitment::Storage { address, index, value })
+            },
+            RequireError::Blockhash(number) => {
+                vm.commit_blockhash(number, self.block_hash(number)?)
+            },
+        })
+    }
+}
+
+fn from_rpc_block(block: &RPCBlock) -> Result<HeaderParams, String> {
+    let number = block.number.as_ref().ok_or("block has no number".to_string())?;
+    Ok(HeaderParams {
+        beneficiary: parse_str(&block.miner, "miner")?,
+        timestamp: parse_str::<U256>(&block.timestamp, "timestamp")?.as_u64(),
+        number: parse_str(number, "block number")?,
+        difficulty: parse_str(&block.difficulty, "difficulty")?,
+        gas_limit: parse_str(&block.gas_limit, "gas limit")?,
+    })
+}
+
+fn from_rpc_transaction(transaction: &RPCTransaction) -> Result<ValidTransaction, String> {
+    Ok(ValidTransaction {
+        caller: Some(parse_str(&transaction.from, "sender")?),
+        action: match transaction.to {
+            Some(ref to) => TransactionAction::Call(parse_str(to, "recipient")?),
+            None => TransactionAction::Create,
+        },
+        value: parse_str(&transaction.value, "value")?,
+        gas_limit: parse_str(&transaction.gas, "gas")?,
+        gas_price: parse_str(&transaction.gas_price, "gas price")?,
+        input: Arc::new(read_hex(&transaction.input).map_err(|_| format!("invalid input {}", transaction.input))?),
+        nonce: parse_str(&transaction.nonce, "nonce")?,
+    })
+}
+
+/// A mined transaction, with the state of the previous block, read
+/// from an RPC record cache as written by `regtests --record`. The
+/// transactions before it in the same block are run first.
+pub struct RPCSource {
+    client: CachedGethRPCClient,
+    previous: Vec<ValidTransaction>,
+    transaction: ValidTransaction,
+    block: HeaderParams,
+    last_id: usize,
+}
+
+impl RPCSource {
+    pub fn load(path: &str, hash: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|err| format!("cannot open {}: {}", path, err))?;
+        let cached: Value = serde_json::from_reader(file).map_err(|err| format!("invalid JSON: {}", err))?;
+        let mut client = CachedGethRPCClient::from_value(cached);
+
+        let transaction = client.get_transaction_by_hash(hash).ok_or(format!("transaction {} not found", hash))?;
+        let number = transaction.block_number.clone().ok_or(format!("transaction {} is not mined", hash))?;
+        let block = client.get_block_by_number(&number).ok_or(format!("block {} not found", number))?;
+        let last_id = parse_str::<U256>(&number, "block number")?.as_usize().checked_sub(1)
+            .ok_or("the genesis block has no previous state".to_string())?;
+
+        let mut previous = Vec::new();
+        for previous_hash in block.transactions.iter().take_while(|previous_hash| previous_hash.as_str() != hash) {
+            let previous_transaction = client.get_transaction_by_hash(previous_hash)
+                .ok_or(format!("transaction {} not found", previous_hash))?;
+            previous.push(from_rpc_transaction(&previous_transaction)?);
+        }
+
+        Ok(RPCSource {
+            previous,
+            transaction: from_rpc_transaction(&transaction)?,
+            block: from_rpc_block(&block)?,
+            last_id,
+            client,
+        })
+    }
+}
+
+impl Source for RPCSource {
+    fn start<P: Patch>(&mut self) -> Result<SeqTransactionVM<P>, String> {
+        let mut last: Option<SeqTransactionVM<P>> = None;
+        for transaction in self.previous.clone() {
+            let mut vm = match last {
+                Some(ref last) => SeqTransactionVM::with_previous(transaction, self.block.clone(), last),
+                None => SeqTransactionVM::new(transaction, self.block.clone()),
+            };
+            fire(self, &mut vm)?;
+            last = Some(vm);
+        }
+
+        Ok(match last {
+            Some(ref last) => SeqTransactionVM::with_previous(self.transaction.clone(), self.block.clone(), last),
+            None => SeqTransactionVM::new(self.transaction.clone(), self.block.clone()),
+        })
+    }
+
+    fn commit(&mut self, vm: &mut VM, require: RequireError) -> Result<(), String> {
+        let last_number = format!("0x{:x}", self.last_id);
+        committed(match require {
+            RequireError::Account(address) => {
+                let address_hex = format!("0x{:x}", address);
+                if !self.client.account_exist(&address_hex, self.last_id) {
+                    vm.commit_account(AccountCommitment::Nonexist(address))
+                } else {
+                    let nonce = parse_str(&self.client.get_transaction_count(&address_hex, &last_number), "nonce")?;
+                    let balance = parse_str(&self.client.get_balance(&address_hex, &last_number), "balance")?;
+                    let code = self.client.get_code(&address_hex, &last_number);
+                    let code = read_hex(&code).map_err(|_| format!("invalid code {}", code))?;
+                    vm.commit_account(AccountCommitment::Full {
+                        nonce,
+                        address,
+                        balance,
+                        code: Arc::new(code),
+                    })
+                }
+            },
+            RequireError::AccountStorage(address, index) => {
+                let value = parse_str(&self.client.get_storage_at(&format!("0x{:x}", address),
+                                                                  &format!("0x{:x}", index),
+                                                                  &last_number), "storage value")?;
+                vm.commit_account(AccountCommitment::Storage { address, index, value })
+            },
+            RequireError::AccountCode(address) => {
+                let code = self.client.get_code(&format!("0x{:x}", address), &last_number);
+                let code = read_hex(&code).map_err(|_| format!("invalid code {}", code))?;
+                vm.commit_account(AccountCommitment::Code {
+                    address,
+                    code: Arc::new(code),
+                })
+            },
+            RequireError::Blockhash(number) => {
+                let block = self.client.get_block_by_number(&format!("0x{:x}", number))
+                    .ok_or(format!("block 0x{:x} not found", number))?;
+                let hash = block.hash.ok_or(format!("block 0x{:x} has no hash", number))?;
+                vm.commit_blockhash(number, parse_str(&hash, "block hash")?)
+            },
+        })
+    }
+}
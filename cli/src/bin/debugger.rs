@@ -0,0 +1,208 @@
+use std::fmt;
+use bigint::U256;
+use sputnikvm::{VM, VMStatus, Patch, SeqContextVM};
+use std::str::FromStr;
+
+use console::{self, Breakpoints, parse_number};
+use sourcemap::{SourceMap, SourceLocation};
+use handle_step_without_rpc;
+
+enum Breakpoint {
+    Pc(usize),
+    Line(String, usize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Pc(pc) => write!(f, "pc 0x{:x}", pc),
+            Breakpoint::Line(ref file, line) => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+pub struct Debugger<P: Patch> {
+    vm: SeqContextVM<P>,
+    source_map: Option<SourceMap>,
+    breakpoints: Breakpoints<Breakpoint>,
+}
+
+impl<P: Patch> Debugger<P> {
+    pub fn new(vm: SeqContextVM<P>, source_map: Option<SourceMap>) -> Self {
+        Debugger {
+            vm,
+            source_map,
+            breakpoints: Breakpoints::default(),
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.vm.status() == VMStatus::Running
+    }
+
+    /// Source location of the current pc, if the current frame runs
+    /// the mapped contract.
+    fn location(&self) -> Option<SourceLocation> {
+        let source_map = self.source_map.as_ref()?;
+        let state = self.vm.current_state();
+        if !source_map.matches(&state.context.code) {
+            return None;
+        }
+        source_map.location(state.position)
+    }
+
+    fn print_position(&self) {
+        if !self.running() {
+            println!("VM exited: {:?}", self.vm.status());
+            return;
+        }
+        let state = self.vm.current_state();
+        let opcode = self.vm.peek_opcode().map(|opcode| format!("{:?}", opcode))
+            .unwrap_or_else(|| "STOP".to_string());
+        println!("depth {}, 0x{:x}, pc 0x{:x}: {}", state.depth, state.context.address, state.position, opcode);
+        if let Some(location) = self.location() {
+            let text = self.source_map.as_ref().unwrap().line_text(&location.file, location.line).unwrap_or("");
+            match location.function {
+                Some(ref function) => println!("{}:{} in {}", location.file, location.line, function),
+                None => println!("{}:{}", location.file, location.line),
+            }
+            println!("  {}", text.trim());
+        }
+    }
+
+    fn at_breakpoint(&self, previous: &Option<SourceLocation>) -> bool {
+        let position = self.vm.current_state().position;
+        let location = self.location();
+        self.breakpoints.iter().any(|breakpoint| match *breakpoint {
+            Breakpoint::Pc(pc) => pc == position,
+            Breakpoint::Line(ref file, line) => match location {
+                Some(ref location) => location.file == *file && location.line == line &&
+                    previous.as_ref().map(|previous| previous.file != *file || previous.line != line)
+                    .unwrap_or(true),
+                None => false,
+            },
+        })
+    }
+
+    fn step(&mut self) {
+        if self.running() {
+            handle_step_without_rpc(&mut self.vm);
+        }
+    }
+
+    fn step_line(&mut self) {
+        if self.source_map.is_none() {
+            return self.step();
+        }
+        let start = self.location();
+        loop {
+            self.step();
+            if !self.running() {
+                return;
+            }
+            let location = self.location();
+            if location.is_some() && location != start {
+                return;
+            }
+        }
+    }
+
+    fn resume(&mut self) {
+        loop {
+            let previous = self.location();
+            self.step();
+            if !self.running() || self.at_breakpoint(&previous) {
+                return;
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, target: &str) {
+        if let Some(pc) = parse_number(target) {
+            self.breakpoints.add(Breakpoint::Pc(pc));
+            return;
+        }
+
+        let split = match target.rfind(':') {
+            Some(split) => split,
+            None => {
+                println!("Breakpoints are given as a pc or file:line");
+                return;
+            },
+        };
+        let line = match parse_number(&target[(split + 1)..]) {
+            Some(line) => line,
+            None => {
+                println!("Invalid line: {}", &target[(split + 1)..]);
+                return;
+            },
+        };
+        match self.source_map.as_ref().and_then(|source_map| source_map.resolve_file(&target[..split])) {
+            Some(file) => self.breakpoints.add(Breakpoint::Line(file, line)),
+            None => println!("Unknown source file: {}", &target[..split]),
+        }
+    }
+
+    fn print_storage(&self, args: &[&str]) {
+        let state = self.vm.current_state();
+        let address = state.context.address;
+        let indexes: Vec<U256> = match args.get(0) {
+            Some(index) => match U256::from_str(index) {
+                Ok(index) => vec![index],
+                Err(_) => {
+                    println!("Invalid storage index: {}", index);
+                    return;
+                },
+            },
+            None => {
+                let mut indexes: Vec<U256> = state.account_state.used_storage().get(&address)
+                    .map(|indexes| indexes.iter().cloned().collect())
+                    .unwrap_or_else(Vec::new);
+                indexes.sort();
+                indexes
+            },
+        };
+        for index in indexes {
+            match state.account_state.storage_read(address, index) {
+                Ok(value) => println!("0x{:x}: 0x{:x}", index, U256::from(value)),
+                Err(_) => println!("0x{:x}: not loaded", index),
+            }
+        }
+    }
+
+    /// Run the debugger until the VM exited and the user quits.
+    pub fn run(&mut self) {
+        println!("Commands: step [n], line, continue, break <pc|file:line>, delete <n>, breakpoints, \
+                  where, stack, memory [offset [len]], storage [index], quit");
+        self.print_position();
+
+        console::run_commands("(debug)", |command, args| match command {
+            "s" | "step" => {
+                let count = args.get(0).and_then(|arg| parse_number(arg)).unwrap_or(1);
+                for _ in 0..count {
+                    self.step();
+                }
+                self.print_position();
+            },
+            "l" | "line" => {
+                self.step_line();
+                self.print_position();
+            },
+            "c" | "continue" => {
+                self.resume();
+                self.print_position();
+            },
+            "b" | "break" => match args.get(0) {
+                Some(target) => self.add_breakpoint(target),
+                None => println!("Usage: break <pc|file:line>"),
+            },
+            "d" | "delete" => self.breakpoints.delete(args),
+            "breakpoints" => self.breakpoints.print(),
+            "w" | "where" => self.print_position(),
+            "stack" => console::print_stack(&self.vm.current_state().stack),
+            "memory" => console::print_memory(&self.vm.current_state().memory, args),
+            "storage" => self.print_storage(args),
+            _ => println!("Unknown command: {}", command),
+        });
+    }
+}
@@ -4,25 +4,32 @@ extern crate bigint;
 extern crate hexutil;
 extern crate sputnikvm;
 extern crate sputnikvm_network_classic;
+#[macro_use]
 extern crate serde_json;
 extern crate gethrpc;
 extern crate flame;
 
 mod profiler;
+mod sourcemap;
+mod console;
+mod debugger;
 
 use std::fs::File;
+use std::io::Write;
 
 use profiler::Profiler;
+use sourcemap::SourceMap;
+use debugger::Debugger;
 use bigint::{Gas, Address, U256, M256, H256};
 use hexutil::read_hex;
 use sputnikvm::{HeaderParams, Context, SeqTransactionVM, ValidTransaction, VM,
                 AccountCommitment, RequireError, TransactionAction, VMStatus,
-                SeqContextVM};
-use sputnikvm_network_classic::{MainnetFrontierPatch, MainnetHomesteadPatch, MainnetEIP150Patch, MainnetEIP160Patch};
+                SeqContextVM, Patch, CallTracer, CallFrame, GasProfiler, GasProfile};
+use sputnikvm_network_classic::{PatchFn, with_patch};
 use gethrpc::{GethRPCClient, NormalGethRPCClient, RPCBlock};
 use std::str::FromStr;
-use std::ops::DerefMut;
-use std::rc::Rc;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
 fn from_rpc_block(block: &RPCBlock) -> HeaderParams {
     HeaderParams {
@@ -71,6 +78,158 @@ fn profile_fire_without_rpc(vm: &mut VM) {
     }
 }
 
+fn call_tree_fire_without_rpc<P: Patch>(vm: &mut SeqContextVM<P>) -> Option<CallFrame> {
+    let mut tracer = CallTracer::new(false);
+    loop {
+        tracer.record(vm.current_machine());
+        match vm.status() {
+            VMStatus::Running => handle_step_without_rpc(vm),
+            VMStatus::ExitedOk | VMStatus::ExitedErr(_) |
+            VMStatus::ExitedNotSupported(_) => return tracer.finish(),
+        }
+    }
+}
+
+fn gas_profile_fire_without_rpc<P: Patch>(vm: &mut SeqContextVM<P>) -> GasProfile {
+    let mut profiler = GasProfiler::new();
+    loop {
+        profiler.record(vm.current_machine());
+        match vm.status() {
+            VMStatus::Running => handle_step_without_rpc(vm),
+            VMStatus::ExitedOk | VMStatus::ExitedErr(_) |
+            VMStatus::ExitedNotSupported(_) => return profiler.finish(),
+        }
+    }
+}
+
+fn gas_as_u64(gas: Gas) -> u64 {
+    let gas: U256 = gas.into();
+    gas.as_u64()
+}
+
+fn write_folded_stacks(profile: &GasProfile, path: &str) {
+    let mut file = File::create(path).unwrap();
+    for (&(ref addresses, opcode), gas) in &profile.stacks {
+        let frames: Vec<String> = addresses.iter().map(|address| format!("0x{:x}", address)).collect();
+        writeln!(file, "{};{:?} {}", frames.join(";"), opcode, gas_as_u64(*gas)).unwrap();
+    }
+}
+
+fn write_gas_report(profile: &GasProfile, path: &str) {
+    let mut contracts = serde_json::Map::new();
+    for (code_hash, contract) in &profile.contracts {
+        let mut instructions = serde_json::Map::new();
+        for (position, instruction) in &contract.instructions {
+            instructions.insert(position.to_string(), json!({
+                "opcode": format!("{:?}", instruction.opcode),
+                "count": instruction.count,
+                "gas": gas_as_u64(instruction.gas),
+                "memoryGas": gas_as_u64(instruction.memory_gas),
+            }));
+        }
+        let addresses: Vec<String> = contract.addresses.iter().map(|address| format!("0x{:x}", address)).collect();
+        contracts.insert(format!("0x{:x}", code_hash), json!({
+            "addresses": addresses,
+            "count": contract.count,
+            "gas": gas_as_u64(contract.gas),
+            "memoryGas": gas_as_u64(contract.memory_gas),
+            "sloads": contract.sloads,
+            "sstores": contract.sstores,
+            "instructions": instructions,
+        }));
+    }
+    let report = json!({
+        "totalGas": gas_as_u64(profile.total_gas()),
+        "contracts": contracts,
+    });
+    serde_json::to_writer_pretty(File::create(path).unwrap(), &report).unwrap();
+}
+
+fn gas_profile<P: Patch>(context: Context, block: HeaderParams,
+                         folded: Option<&str>, report: Option<&str>) {
+    let mut vm = SeqContextVM::<P>::new(context, block);
+    let profile = gas_profile_fire_without_rpc(&mut vm);
+    print_result(&vm);
+    if let Some(folded) = folded {
+        write_folded_stacks(&profile, folded);
+    }
+    if let Some(report) = report {
+        write_gas_report(&profile, report);
+    }
+}
+
+fn debug<P: Patch>(context: Context, block: HeaderParams, source_map: Option<SourceMap>) {
+    let mut debugger = Debugger::new(SeqContextVM::<P>::new(context, block), source_map);
+    debugger.run();
+}
+
+fn print_call_frame(frame: &CallFrame, indent: usize) {
+    let result = match frame.revert_reason {
+        Some(ref reason) => format!("{}", reason),
+        None => match frame.error {
+            Some(ref error) => format!("{:?}", error),
+            None => "ok".to_string(),
+        },
+    };
+    println!("{}{} 0x{:x} -> 0x{:x}, value: 0x{:x}, gas used: 0x{:x}/0x{:x}, {}",
+             "  ".repeat(indent), if frame.create { "CREATE" } else { "CALL" },
+             frame.caller, frame.address, frame.value, frame.gas_used, frame.gas_limit, result);
+    for call in &frame.calls {
+        print_call_frame(call, indent + 1);
+    }
+}
+
+fn call_tree<P: Patch>(context: Context, block: HeaderParams) {
+    let mut vm = SeqContextVM::<P>::new(context, block);
+    let root = call_tree_fire_without_rpc(&mut vm);
+    print_result(&vm);
+    if let Some(root) = root {
+        println!("Call tree:");
+        print_call_frame(&root, 1);
+    }
+}
+
+struct CallTreeMode(Context, HeaderParams);
+impl PatchFn for CallTreeMode {
+    type Output = ();
+    fn call<P: Patch + 'static>(self) { call_tree::<P>(self.0, self.1) }
+}
+
+struct DebugMode(Context, HeaderParams, Option<SourceMap>);
+impl PatchFn for DebugMode {
+    type Output = ();
+    fn call<P: Patch + 'static>(self) { debug::<P>(self.0, self.1, self.2) }
+}
+
+struct GasProfileMode<'a>(Context, HeaderParams, Option<&'a str>, Option<&'a str>);
+impl<'a> PatchFn for GasProfileMode<'a> {
+    type Output = ();
+    fn call<P: Patch + 'static>(self) { gas_profile::<P>(self.0, self.1, self.2, self.3) }
+}
+
+struct NewContextVM(Context, HeaderParams);
+impl PatchFn for NewContextVM {
+    type Output = Box<VM>;
+    fn call<P: Patch + 'static>(self) -> Box<VM> { Box::new(SeqContextVM::<P>::new(self.0, self.1)) }
+}
+
+struct NewTransactionVM(ValidTransaction, HeaderParams);
+impl PatchFn for NewTransactionVM {
+    type Output = Box<VM>;
+    fn call<P: Patch + 'static>(self) -> Box<VM> { Box::new(SeqTransactionVM::<P>::new(self.0, self.1)) }
+}
+
+fn print_result(vm: &VM) {
+    println!("VM returned: {:?}", vm.status());
+    println!("VM out: {:?}", vm.out());
+    if let Some(reason) = vm.revert_reason() {
+        println!("VM revert reason: {}", reason);
+    }
+    for account in vm.accounts() {
+        println!("{:?}", account);
+    }
+}
+
 fn handle_fire_without_rpc(vm: &mut VM) {
     loop {
         match vm.fire() {
@@ -113,7 +272,7 @@ fn handle_fire_with_rpc<T: GethRPCClient>(client: &mut T, vm: &mut VM, block_num
                         nonce,
                         address,
                         balance,
-                        code: Rc::new(code),
+                        code: Arc::new(code),
                     }).unwrap();
                 }
             },
@@ -132,7 +291,7 @@ fn handle_fire_with_rpc<T: GethRPCClient>(client: &mut T, vm: &mut VM, block_num
                                                      &block_number)).unwrap();
                 vm.commit_account(AccountCommitment::Code {
                     address,
-                    code: Rc::new(code),
+                    code: Arc::new(code),
                 }).unwrap();
             },
             Err(RequireError::Blockhash(number)) => {
@@ -154,6 +313,13 @@ fn main() {
         (@arg CREATE: --create "Execute a CreateContract transaction instead of message call.")
         (@arg PROFILE: --profile "Whether to output a profiling result for the execution.")
         (@arg PROFILE_DUMP: --profile_dump +takes_value "Dump profiler result as HTML.")
+        (@arg CALL_TREE: --call_tree "Print the call tree of the execution. Not available with RPC.")
+        (@arg GAS_PROFILE: --gas_profile +takes_value "Write gas used per call path and opcode as folded stacks. Not available with RPC.")
+        (@arg GAS_PROFILE_JSON: --gas_profile_json +takes_value "Write gas statistics per contract and pc as JSON. Not available with RPC.")
+        (@arg DEBUG: --debug "Run the code in an interactive step debugger. Not available with RPC.")
+        (@arg STANDARD_JSON: --standard_json +takes_value "solc standard-JSON output used by the debugger to map pcs to sources.")
+        (@arg CONTRACT: --contract +takes_value "Contract of the standard-JSON output, as file:Name.")
+        (@arg SOURCE_ROOT: --source_root +takes_value "Directory the sources of the standard-JSON output are read from.")
         (@arg CODE: --code +takes_value +required "Code to be executed.")
         (@arg RPC: --rpc +takes_value "Indicate this EVM should be run on an actual blockchain.")
         (@arg DATA: --data +takes_value "Data associated with this transaction.")
@@ -175,6 +341,7 @@ fn main() {
     let gas_price = Gas::from_str(matches.value_of("GAS_PRICE").unwrap_or("0x0")).unwrap();
     let is_create = matches.is_present("CREATE");
     let block_number = matches.value_of("BLOCK").unwrap_or("0x0");
+    let patch = matches.value_of("PATCH").unwrap();
 
     let block = if matches.is_present("RPC") {
         let mut client = NormalGethRPCClient::new(matches.value_of("RPC").unwrap());
@@ -198,26 +365,41 @@ fn main() {
     let mut vm: Box<VM> = if matches.is_present("CODE") {
         let context = Context {
             address, caller, gas_limit, gas_price, value,
-            code: Rc::new(code),
-            data: Rc::new(data),
+            code: Arc::new(code),
+            data: Arc::new(data),
             origin: caller,
             apprent_value: value,
             is_system: false,
             is_static: false,
         };
 
-        match matches.value_of("PATCH") {
-            Some("frontier") => Box::new(SeqContextVM::<MainnetFrontierPatch>::new(context, block)),
-            Some("homestead") => Box::new(SeqContextVM::<MainnetHomesteadPatch>::new(context, block)),
-            Some("eip150") => Box::new(SeqContextVM::<MainnetEIP150Patch>::new(context, block)),
-            Some("eip160") => Box::new(SeqContextVM::<MainnetEIP160Patch>::new(context, block)),
-            _ => panic!("Unsupported patch."),
+        if matches.is_present("CALL_TREE") && client.is_none() {
+            with_patch(patch, CallTreeMode(context, block)).expect("Unsupported patch.");
+            return;
+        }
+
+        if matches.is_present("DEBUG") && client.is_none() {
+            let source_map = matches.value_of("STANDARD_JSON").map(|path| {
+                let contract = matches.value_of("CONTRACT").expect("--contract is required with --standard_json");
+                SourceMap::load(path, contract, matches.value_of("SOURCE_ROOT").unwrap_or(".")).unwrap()
+            });
+            with_patch(patch, DebugMode(context, block, source_map)).expect("Unsupported patch.");
+            return;
+        }
+
+        if (matches.is_present("GAS_PROFILE") || matches.is_present("GAS_PROFILE_JSON")) && client.is_none() {
+            let folded = matches.value_of("GAS_PROFILE");
+            let report = matches.value_of("GAS_PROFILE_JSON");
+            with_patch(patch, GasProfileMode(context, block, folded, report)).expect("Unsupported patch.");
+            return;
         }
+
+        with_patch(patch, NewContextVM(context, block)).expect("Unsupported patch.")
     } else {
         let transaction = ValidTransaction {
             caller: Some(caller),
             value, gas_limit, gas_price,
-            input: Rc::new(data),
+            input: Arc::new(data),
             nonce: match client {
                 Some(ref mut client) => {
                     U256::from_str(&client.get_transaction_count(&format!("0x{:x}", caller),
@@ -232,13 +414,7 @@ fn main() {
             },
         };
 
-        match matches.value_of("PATCH") {
-            Some("frontier") => Box::new(SeqTransactionVM::<MainnetFrontierPatch>::new(transaction, block)),
-            Some("homestead") => Box::new(SeqTransactionVM::<MainnetHomesteadPatch>::new(transaction, block)),
-            Some("eip150") => Box::new(SeqTransactionVM::<MainnetEIP150Patch>::new(transaction, block)),
-            Some("eip160") => Box::new(SeqTransactionVM::<MainnetEIP160Patch>::new(transaction, block)),
-            _ => panic!("Unsupported patch."),
-        }
+        with_patch(patch, NewTransactionVM(transaction, block)).expect("Unsupported patch.")
     };
     match client {
         Some(ref mut client) => {
@@ -261,9 +437,5 @@ fn main() {
         },
     }
 
-    println!("VM returned: {:?}", vm.status());
-    println!("VM out: {:?}", vm.out());
-    for account in vm.accounts() {
-        println!("{:?}", account);
-    }
+    print_result(vm.deref());
 }
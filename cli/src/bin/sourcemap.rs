@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use serde_json::Value;
+use hexutil::read_hex;
+use sputnikvm::Opcode;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub function: Option<String>,
+}
+
+struct Source {
+    name: String,
+    content: String,
+    line_starts: Vec<usize>,
+    /// Functions as start, length and name.
+    functions: Vec<(usize, usize, String)>,
+}
+
+impl Source {
+    fn new(name: String, content: String, ast: Option<&Value>) -> Self {
+        let mut line_starts = vec![0];
+        for (i, c) in content.bytes().enumerate() {
+            if c == b'\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        let mut functions = Vec::new();
+        if let Some(ast) = ast {
+            collect_functions(ast, &mut functions);
+        }
+
+        Source { name, content, line_starts, functions }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line + 1,
+            Err(line) => line,
+        }
+    }
+
+    fn line_text(&self, line: usize) -> Option<&str> {
+        self.content.lines().nth(line.checked_sub(1)?)
+    }
+
+    /// The innermost function containing the range.
+    fn function_of(&self, start: usize, length: usize) -> Option<String> {
+        self.functions.iter()
+            .filter(|&&(fstart, flength, _)| fstart <= start && start + length <= fstart + flength)
+            .min_by_key(|&&(_, flength, _)| flength)
+            .map(|&(_, _, ref name)| name.clone())
+    }
+}
+
+fn parse_src(src: &str) -> Option<(usize, usize)> {
+    let mut parts = src.split(':');
+    let start = parts.next()?.parse().ok()?;
+    let length = parts.next()?.parse().ok()?;
+    Some((start, length))
+}
+
+fn collect_functions(node: &Value, functions: &mut Vec<(usize, usize, String)>) {
+    match *node {
+        Value::Object(ref map) => {
+            let node_type = map.get("nodeType").and_then(|t| t.as_str());
+            if node_type == Some("FunctionDefinition") || node_type == Some("ModifierDefinition") {
+                let name = match map.get("name").and_then(|n| n.as_str()) {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => map.get("kind").and_then(|k| k.as_str()).unwrap_or("fallback").to_string(),
+                };
+                if let Some((start, length)) = map.get("src").and_then(|s| s.as_str()).and_then(parse_src) {
+                    functions.push((start, length, name));
+                }
+            }
+            for value in map.values() {
+                collect_functions(value, functions);
+            }
+        },
+        Value::Array(ref values) => {
+            for value in values {
+                collect_functions(value, functions);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Decompress a solc source map into start, length and file index of
+/// every instruction.
+fn parse_source_map(source_map: &str) -> Vec<(usize, usize, i64)> {
+    let mut entries = Vec::new();
+    let (mut start, mut length, mut file) = (0usize, 0usize, -1i64);
+    for entry in source_map.split(';') {
+        let mut fields = entry.split(':');
+        if let Some(value) = fields.next().and_then(|v| v.parse().ok()) {
+            start = value;
+        }
+        if let Some(value) = fields.next().and_then(|v| v.parse().ok()) {
+            length = value;
+        }
+        if let Some(value) = fields.next().and_then(|v| v.parse().ok()) {
+            file = value;
+        }
+        entries.push((start, length, file));
+    }
+    entries
+}
+
+/// Maps pcs of a contract's runtime bytecode back to its sources.
+pub struct SourceMap {
+    code: Vec<u8>,
+    /// Source map entry of every pc, as start, length and file index.
+    pcs: HashMap<usize, (usize, usize, i64)>,
+    sources: HashMap<i64, Source>,
+}
+
+impl SourceMap {
+    /// Load the contract, given as `file:Name`, from solc's
+    /// standard-JSON output. Source files are read relative to
+    /// `source_root`.
+    pub fn load(path: &str, contract: &str, source_root: &str) -> Result<SourceMap, String> {
+        let mut content = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut content))
+            .map_err(|err| format!("cannot read {}: {}", path, err))?;
+        let output: Value = serde_json::from_str(&content).map_err(|err| format!("invalid JSON: {}", err))?;
+
+        let split = contract.rfind(':').ok_or("contract must be given as file:Name")?;
+        let (file, name) = (&contract[..split], &contract[(split + 1)..]);
+        let bytecode = &output["contracts"][file][name]["evm"]["deployedBytecode"];
+        let object = bytecode["object"].as_str().ok_or(format!("no deployed bytecode for {}", contract))?;
+        let source_map = bytecode["sourceMap"].as_str().ok_or(format!("no source map for {}", contract))?;
+        let code = read_hex(object).map_err(|_| "invalid deployed bytecode".to_string())?;
+
+        let mut sources = HashMap::new();
+        if let Some(map) = output["sources"].as_object() {
+            for (name, source) in map {
+                let id = match source["id"].as_i64() {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let mut content = String::new();
+                File::open(Path::new(source_root).join(name))
+                    .and_then(|mut file| file.read_to_string(&mut content))
+                    .map_err(|err| format!("cannot read source {}: {}", name, err))?;
+                sources.insert(id, Source::new(name.clone(), content, source.get("ast")));
+            }
+        }
+
+        let entries = parse_source_map(source_map);
+        let mut pcs = HashMap::new();
+        let mut pc = 0;
+        for entry in entries {
+            if pc >= code.len() {
+                break;
+            }
+            pcs.insert(pc, entry);
+            pc += match Opcode::from(code[pc]) {
+                Opcode::PUSH(len) => len + 1,
+                _ => 1,
+            };
+        }
+
+        Ok(SourceMap { code, pcs, sources })
+    }
+
+    /// Whether the source map applies to the given code.
+    pub fn matches(&self, code: &[u8]) -> bool {
+        self.code.as_slice() == code
+    }
+
+    /// Source location of the instruction at `pc`.
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        let &(start, length, file) = self.pcs.get(&pc)?;
+        let source = self.sources.get(&file)?;
+        Some(SourceLocation {
+            file: source.name.clone(),
+            line: source.line_of(start),
+            function: source.function_of(start, length),
+        })
+    }
+
+    /// Text of a source line.
+    pub fn line_text(&self, file: &str, line: usize) -> Option<&str> {
+        self.sources.values().find(|source| source.name == file)?.line_text(line)
+    }
+
+    /// Resolve a file name, which may be a suffix of the full source
+    /// name.
+    pub fn resolve_file(&self, file: &str) -> Option<String> {
+        self.sources.values()
+            .find(|source| source.name == file || source.name.ends_with(&format!("/{}", file)))
+            .map(|source| source.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_source_map() {
+        assert_eq!(parse_source_map("1:2:1;:9;2:1:2;;"),
+                   vec![(1, 2, 1), (1, 9, 1), (2, 1, 2), (2, 1, 2), (2, 1, 2)]);
+    }
+
+    #[test]
+    fn lines_and_functions() {
+        let content = "contract A {\n  function f() {\n    x = 1;\n  }\n}\n".to_string();
+        let ast = json!({
+            "nodeType": "SourceUnit",
+            "nodes": [{ "nodeType": "FunctionDefinition", "name": "f", "src": "15:33:0" }],
+        });
+        let source = Source::new("A.sol".to_string(), content, Some(&ast));
+        assert_eq!(source.line_of(0), 1);
+        assert_eq!(source.line_of(35), 3);
+        assert_eq!(source.line_text(3), Some("    x = 1;"));
+        assert_eq!(source.function_of(35, 6), Some("f".to_string()));
+        assert_eq!(source.function_of(0, 1), None);
+    }
+}
@@ -0,0 +1,245 @@
+#[macro_use]
+extern crate clap;
+extern crate rand;
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+extern crate sputnikvm_fuzz;
+
+use clap::ArgMatches;
+use rand::{Rng, SeedableRng, StdRng};
+use sputnikvm::Patch;
+use sputnikvm_network_classic::{PatchFn, with_patch};
+use sputnikvm_fuzz::*;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+fn rng(matches: &ArgMatches) -> StdRng {
+    match matches.value_of("SEED") {
+        Some(seed) => StdRng::from_seed(&[seed.parse::<usize>().unwrap()][..]),
+        None => StdRng::new().unwrap(),
+    }
+}
+
+fn seeds(matches: &ArgMatches) -> Vec<FuzzInput> {
+    match matches.value_of("SEEDS") {
+        Some(path) => load_seeds(Path::new(path)).unwrap(),
+        None => Vec::new(),
+    }
+}
+
+fn runs(matches: &ArgMatches) -> usize {
+    matches.value_of("RUNS").unwrap_or("1000").parse().unwrap()
+}
+
+fn scratch() -> PathBuf {
+    env::temp_dir().join(format!("sputnikvm-fuzz-{}.json", process::id()))
+}
+
+/// Run the input through both SputnikVM and the reference, and return
+/// the reference steps if they diverge. Fails if the reference EVM
+/// cannot be run on the input.
+fn diverges<P: Patch>(reference: &str, input: &FuzzInput) -> io::Result<Option<Vec<Step>>> {
+    let path = scratch();
+    save_fixture(&path, input, None)?;
+    let steps = run_reference(reference, &path)?;
+
+    Ok(match compare(&execute::<P>(input), &steps, None) {
+        Some(_) => Some(steps),
+        None => None,
+    })
+}
+
+/// Minimize a diverging input. Candidates the reference EVM fails on
+/// are not kept, and are added to `errors`.
+fn shrink<P: Patch>(reference: &str, name: String, input: &FuzzInput, errors: &mut usize) -> CorpusEntry {
+    let input = minimize(input, |candidate| match diverges::<P>(reference, candidate) {
+        Ok(steps) => steps.is_some(),
+        Err(_) => {
+            *errors += 1;
+            false
+        },
+    });
+    let steps = match diverges::<P>(reference, &input) {
+        Ok(steps) => steps.unwrap_or_default(),
+        Err(_) => {
+            *errors += 1;
+            Vec::new()
+        },
+    };
+    CorpusEntry { name, input, steps, post: None }
+}
+
+fn check<P: Patch>(matches: &ArgMatches) -> bool {
+    let corpus = load_corpus(Path::new(matches.value_of("CORPUS").unwrap())).unwrap();
+    let mut passed = true;
+
+    for entry in &corpus {
+        match check_entry::<P>(entry) {
+            Some(divergence) => {
+                println!("{}: {:?}", entry.name, divergence);
+                passed = false;
+            },
+            None => println!("{}: ok", entry.name),
+        }
+    }
+
+    passed
+}
+
+fn fuzz<P: Patch>(matches: &ArgMatches) -> bool {
+    let reference = matches.value_of("REFERENCE").unwrap();
+    let out = Path::new(matches.value_of("OUT").unwrap());
+    let seeds = seeds(matches);
+    let mut rng = rng(matches);
+    let mut passed = true;
+    let mut errors = 0;
+
+    for run in 0..runs(matches) {
+        let input = generate(&mut rng, &seeds);
+        match diverges::<P>(reference, &input) {
+            Ok(Some(_)) => {
+                let entry = shrink::<P>(reference, format!("divergence-{}-{}", process::id(), run), &input,
+                                        &mut errors);
+                save_entry(out, &entry).unwrap();
+                println!("run {}: divergence saved as {}", run, entry.name);
+                passed = false;
+            },
+            Ok(None) => (),
+            Err(err) => {
+                println!("run {}: {}", run, err);
+                errors += 1;
+            },
+        }
+    }
+
+    if errors > 0 {
+        println!("the reference EVM failed {} times", errors);
+        passed = false;
+    }
+    passed
+}
+
+fn minimize_fixture<P: Patch>(matches: &ArgMatches) -> bool {
+    let reference = matches.value_of("REFERENCE").unwrap();
+    let path = Path::new(matches.value_of("FIXTURE").unwrap());
+    let input = load_fixture(path).unwrap();
+
+    match diverges::<P>(reference, &input) {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            println!("{}: no divergence", path.display());
+            return true;
+        },
+        Err(err) => {
+            println!("{}: {}", path.display(), err);
+            return false;
+        },
+    }
+
+    let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let mut errors = 0;
+    let entry = shrink::<P>(reference, format!("{}-min", name), &input, &mut errors);
+    save_entry(Path::new(matches.value_of("OUT").unwrap()), &entry).unwrap();
+    println!("{}: minimized as {}", path.display(), entry.name);
+    if errors > 0 {
+        println!("the reference EVM failed on {} candidates", errors);
+    }
+    false
+}
+
+fn generate_fixtures(matches: &ArgMatches) -> bool {
+    let out = Path::new(matches.value_of("OUT").unwrap());
+    let seeds = seeds(matches);
+    let mut rng = rng(matches);
+    fs::create_dir_all(out).unwrap();
+
+    for run in 0..runs(matches) {
+        let input = generate(&mut rng, &seeds);
+        let name = format!("generated-{:08x}.json", rng.gen::<u32>());
+        save_fixture(&out.join(name), &input, None).unwrap();
+        println!("generated fixture {}", run);
+    }
+
+    true
+}
+
+fn fuzz_precompileds(matches: &ArgMatches) -> bool {
+    let mut rng = rng(matches);
+    let mut passed = true;
+
+    for _ in 0..runs(matches) {
+        for (name, precompiled) in precompileds() {
+            let data = random_precompiled_input(&mut rng, name);
+            let extra = random_extension(&mut rng);
+            if let Err(violation) = check_precompiled(precompiled, &data, &extra) {
+                println!("{}: {:?}", name, violation);
+                println!("  data:  0x{}", data.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+                println!("  extra: 0x{}", extra.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+                passed = false;
+            }
+        }
+    }
+
+    passed
+}
+
+fn run<P: Patch>(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        ("check", Some(matches)) => check::<P>(matches),
+        ("fuzz", Some(matches)) => fuzz::<P>(matches),
+        ("minimize", Some(matches)) => minimize_fixture::<P>(matches),
+        ("generate", Some(matches)) => generate_fixtures(matches),
+        ("precompiled", Some(matches)) => fuzz_precompileds(matches),
+        _ => panic!("Unsupported subcommand!"),
+    }
+}
+
+struct Run<'a, 'b: 'a>(&'a ArgMatches<'b>);
+impl<'a, 'b: 'a> PatchFn for Run<'a, 'b> {
+    type Output = bool;
+    fn call<P: Patch + 'static>(self) -> bool { run::<P>(self.0) }
+}
+
+fn main() {
+    let matches = clap_app!(sputnikvm_fuzz =>
+        (version: "0.1")
+        (author: "Ethereum Classic Contributors")
+        (about: "Differential fuzzing of SputnikVM against reference EVM traces.")
+        (@arg PATCH: --patch +takes_value "Patch to be used, defaults to eip160.")
+        (@subcommand check =>
+            (about: "Compare SputnikVM against the reference traces in the corpus.")
+            (@arg CORPUS: +required "Corpus directory."))
+        (@subcommand fuzz =>
+            (about: "Fuzz SputnikVM against a reference EVM run on the fly.")
+            (@arg REFERENCE: --reference +takes_value +required "Command printing the trace of a fixture.")
+            (@arg SEEDS: --seeds +takes_value "Directory of jsontests fixtures used as seeds.")
+            (@arg RUNS: --runs +takes_value "Number of runs.")
+            (@arg SEED: --seed +takes_value "Seed of the random generator.")
+            (@arg OUT: --out +takes_value +required "Directory where minimized divergences are saved."))
+        (@subcommand minimize =>
+            (about: "Shrink a fixture that diverges from the reference EVM.")
+            (@arg REFERENCE: --reference +takes_value +required "Command printing the trace of a fixture.")
+            (@arg OUT: --out +takes_value +required "Directory where the minimized fixture is saved.")
+            (@arg FIXTURE: +required "Fixture to minimize."))
+        (@subcommand generate =>
+            (about: "Generate fixtures to be traced by a reference EVM offline.")
+            (@arg SEEDS: --seeds +takes_value "Directory of jsontests fixtures used as seeds.")
+            (@arg RUNS: --runs +takes_value "Number of fixtures.")
+            (@arg SEED: --seed +takes_value "Seed of the random generator.")
+            (@arg OUT: +required "Output directory."))
+        (@subcommand precompiled =>
+            (about: "Fuzz precompiled contracts and check their invariants.")
+            (@arg RUNS: --runs +takes_value "Number of runs for each precompiled contract.")
+            (@arg SEED: --seed +takes_value "Seed of the random generator."))
+    ).get_matches();
+
+    let passed = with_patch(matches.value_of("PATCH").unwrap_or("eip160"), Run(&matches))
+        .expect("Unsupported patch.");
+
+    if !passed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,140 @@
+//! Seeds from `jsontests` fixtures, and the checked-in corpus of
+//! reference traces.
+//!
+//! A corpus entry named `name` consists of the fixture `name.json`,
+//! with the input and optionally the expected post-state, and the
+//! reference trace `name.trace` with one step per line.
+
+use serde_json::{self, Value};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+use input::{FuzzInput, FuzzAccounts};
+use trace::{Step, read_trace, write_trace, fixture_post};
+
+#[derive(Debug, Clone)]
+/// An input together with its reference trace.
+pub struct CorpusEntry {
+    /// Name of the entry.
+    pub name: String,
+    /// The input.
+    pub input: FuzzInput,
+    /// Steps of the reference trace.
+    pub steps: Vec<Step>,
+    /// Post-state of the reference, if recorded.
+    pub post: Option<FuzzAccounts>,
+}
+
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut ret = String::new();
+    File::open(path)?.read_to_string(&mut ret)?;
+    Ok(ret)
+}
+
+fn read_json(path: &Path) -> io::Result<Value> {
+    serde_json::from_str(&read_file(path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn invalid_data(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a fuzz fixture", path.display()))
+}
+
+/// Load all fixtures with `env`, `exec` and `pre` under the path, such
+/// as the `VMTests` of `jsontests`, as seeds. Fixtures of other
+/// formats are skipped.
+pub fn load_seeds(path: &Path) -> io::Result<Vec<FuzzInput>> {
+    let mut seeds = Vec::new();
+
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.path());
+        for entry in entries {
+            seeds.extend(load_seeds(&entry.path())?);
+        }
+    } else if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+        if let Value::Object(tests) = read_json(path)? {
+            for (_, test) in tests {
+                seeds.extend(FuzzInput::from_json(&test));
+            }
+        }
+    }
+
+    Ok(seeds)
+}
+
+/// Load an input from a fixture, either a corpus fixture or the first
+/// test of a `jsontests` fixture.
+pub fn load_fixture(path: &Path) -> io::Result<FuzzInput> {
+    let fixture = read_json(path)?;
+    FuzzInput::from_json(&fixture)
+        .or_else(|| fixture.as_object()?.values().filter_map(FuzzInput::from_json).next())
+        .ok_or_else(|| invalid_data(path))
+}
+
+/// Load the corpus entry with the given fixture path.
+pub fn load_entry(path: &Path) -> io::Result<CorpusEntry> {
+    let fixture = read_json(path)?;
+    let input = FuzzInput::from_json(&fixture).ok_or_else(|| invalid_data(path))?;
+    let steps = read_trace(&read_file(&path.with_extension("trace"))?);
+
+    Ok(CorpusEntry {
+        name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+        input,
+        steps,
+        post: fixture_post(&fixture),
+    })
+}
+
+/// Load all entries in the corpus directory.
+pub fn load_corpus(path: &Path) -> io::Result<Vec<CorpusEntry>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths.iter().map(|path| load_entry(path)).collect()
+}
+
+/// Write the input as a fixture.
+pub fn save_fixture(path: &Path, input: &FuzzInput, post: Option<&FuzzAccounts>) -> io::Result<()> {
+    let fixture = serde_json::to_string_pretty(&input.to_json(post))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut file = File::create(path)?;
+    file.write_all(fixture.as_bytes())?;
+    file.write_all(b"\n")
+}
+
+/// Write a corpus entry into the corpus directory.
+pub fn save_entry(path: &Path, entry: &CorpusEntry) -> io::Result<()> {
+    let fixture = path.join(format!("{}.json", entry.name));
+    save_fixture(&fixture, &entry.input, entry.post.as_ref())?;
+    File::create(fixture.with_extension("trace"))?.write_all(write_trace(&entry.steps).as_bytes())
+}
+
+/// Run a reference EVM on the fixture. The command is run through the
+/// shell with the fixture path appended, and must print the trace to
+/// standard output or standard error with one step per line. Fails if
+/// the command cannot be run or exits with an error.
+pub fn run_reference(command: &str, fixture: &Path) -> io::Result<Vec<Step>> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} {}", command, fixture.display()))
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!(
+            "reference EVM exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    let mut steps = read_trace(&String::from_utf8_lossy(&output.stdout));
+    if steps.is_empty() {
+        steps = read_trace(&String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(steps)
+}
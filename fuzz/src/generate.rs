@@ -0,0 +1,141 @@
+//! Random inputs, either from scratch or by mutating seeds.
+
+use bigint::{Gas, M256, U256, Address};
+use rand::Rng;
+
+use input::{FuzzInput, FuzzAccount};
+
+/// Opcodes picked when inserting instructions, excluding `PUSH*`
+/// which are generated together with their immediates.
+const OPCODES: &[u8] = &[
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b,
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a,
+    0x20,
+    0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c,
+    0x3d, 0x3e,
+    0x40, 0x41, 0x42, 0x43, 0x44, 0x45,
+    0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x5b,
+    0x80, 0x81, 0x82, 0x83, 0x8f, 0x90, 0x91, 0x92, 0x9f,
+    0xa0, 0xa1, 0xa2, 0xa3, 0xa4,
+    0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xfa, 0xfd, 0xfe, 0xff,
+];
+
+/// Maximum length of generated code.
+const MAX_CODE: usize = 256;
+
+fn random_word<R: Rng>(rng: &mut R) -> U256 {
+    match rng.gen_range(0, 4) {
+        0 => U256::zero(),
+        1 => U256::from(rng.gen_range(0u64, 64)),
+        2 => U256::max_value(),
+        _ => {
+            let bytes: Vec<u8> = (0..32).map(|_| rng.gen()).collect();
+            U256::from(&bytes[..])
+        },
+    }
+}
+
+/// Generate a single instruction, with immediates for `PUSH*`.
+pub fn random_instruction<R: Rng>(rng: &mut R) -> Vec<u8> {
+    if rng.gen_weighted_bool(3) {
+        let size = if rng.gen() { 1 } else { rng.gen_range(1, 33) };
+        let mut ret = vec![0x5f + size as u8];
+        for _ in 0..size {
+            ret.push(if rng.gen() { rng.gen_range(0, 32) } else { rng.gen() });
+        }
+        ret
+    } else {
+        vec![*rng.choose(OPCODES).unwrap()]
+    }
+}
+
+/// Generate code from scratch.
+pub fn random_code<R: Rng>(rng: &mut R) -> Vec<u8> {
+    let mut code = Vec::new();
+    let count = rng.gen_range(1, 64);
+    for _ in 0..count {
+        code.extend(random_instruction(rng));
+    }
+    code.truncate(MAX_CODE);
+    code
+}
+
+fn mutate_bytes<R: Rng>(rng: &mut R, bytes: &mut Vec<u8>, code: bool) {
+    match rng.gen_range(0, 4) {
+        0 if !bytes.is_empty() => {
+            let i = rng.gen_range(0, bytes.len());
+            bytes[i] = rng.gen();
+        },
+        1 if !bytes.is_empty() => {
+            let start = rng.gen_range(0, bytes.len());
+            let end = rng.gen_range(start, bytes.len()) + 1;
+            bytes.drain(start..end);
+        },
+        2 if code => {
+            let i = rng.gen_range(0, bytes.len() + 1);
+            let instruction = random_instruction(rng);
+            for (j, byte) in instruction.into_iter().enumerate() {
+                bytes.insert(i + j, byte);
+            }
+        },
+        _ => {
+            let i = rng.gen_range(0, bytes.len() + 1);
+            bytes.insert(i, rng.gen());
+        },
+    }
+    if code {
+        bytes.truncate(MAX_CODE);
+    }
+}
+
+/// Mutate the input in place, changing its code, calldata, value or
+/// pre-state.
+pub fn mutate<R: Rng>(rng: &mut R, input: &mut FuzzInput) {
+    match rng.gen_range(0, 8) {
+        0 => input.code = random_code(rng),
+        1 | 2 | 3 => mutate_bytes(rng, &mut input.code, true),
+        4 => mutate_bytes(rng, &mut input.data, false),
+        5 => input.value = if rng.gen() { U256::zero() } else { random_word(rng) },
+        6 => input.gas = Gas::from(rng.gen_range(0u64, 1_000_000)),
+        _ => {
+            let address = if rng.gen() {
+                input.address
+            } else {
+                Address::from(M256::from(rng.gen_range(1u64, 16)))
+            };
+            let account = input.pre.entry(address).or_insert_with(FuzzAccount::default);
+            match rng.gen_range(0, 3) {
+                0 => account.balance = random_word(rng),
+                1 => account.code = random_code(rng),
+                _ => {
+                    let index = U256::from(rng.gen_range(0u64, 8));
+                    let value = M256::from(random_word(rng));
+                    if value.is_zero() {
+                        account.storage.remove(&index);
+                    } else {
+                        account.storage.insert(index, value);
+                    }
+                },
+            }
+        },
+    }
+}
+
+/// Generate an input by mutating one of the seeds a few times, or a
+/// fresh input if there are no seeds.
+pub fn generate<R: Rng>(rng: &mut R, seeds: &[FuzzInput]) -> FuzzInput {
+    let mut input = match rng.choose(seeds) {
+        Some(seed) => seed.clone(),
+        None => {
+            let mut input = FuzzInput::default();
+            input.code = random_code(rng);
+            input
+        },
+    };
+
+    let count = rng.gen_range(1, 5);
+    for _ in 0..count {
+        mutate(rng, &mut input);
+    }
+    input
+}
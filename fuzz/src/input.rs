@@ -0,0 +1,211 @@
+//! Fuzz inputs, stored as fixtures in the shape of `VMTests`.
+
+use bigint::{Gas, M256, U256, Address};
+use hexutil::{read_hex, to_hex};
+use jsontests::read_u256;
+use serde_json::{Value, Map};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// An account in the pre-state or the post-state of a fixture.
+pub struct FuzzAccount {
+    /// Balance of the account.
+    pub balance: U256,
+    /// Nonce of the account.
+    pub nonce: U256,
+    /// Code of the account.
+    pub code: Vec<u8>,
+    /// Non-zero storage values of the account.
+    pub storage: BTreeMap<U256, M256>,
+}
+
+impl FuzzAccount {
+    /// Whether the account is indistinguishable from a nonexisting
+    /// one.
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_zero() && self.nonce.is_zero() && self.code.is_empty() &&
+            self.storage.values().all(|value| value.is_zero())
+    }
+}
+
+/// Accounts of a pre-state or a post-state.
+pub type FuzzAccounts = BTreeMap<Address, FuzzAccount>;
+
+#[derive(Debug, Clone)]
+/// Input of a single fuzz run. It describes a message call of `code`
+/// on top of the pre-state, the same way `VMTests` does.
+pub struct FuzzInput {
+    /// Beneficiary of the block.
+    pub coinbase: Address,
+    /// Number of the block.
+    pub number: U256,
+    /// Timestamp of the block.
+    pub timestamp: u64,
+    /// Difficulty of the block.
+    pub difficulty: U256,
+    /// Gas limit of the block.
+    pub block_gas_limit: Gas,
+
+    /// Address the code is executed at.
+    pub address: Address,
+    /// Caller of the code.
+    pub caller: Address,
+    /// Origin of the transaction.
+    pub origin: Address,
+    /// Code to execute.
+    pub code: Vec<u8>,
+    /// Calldata.
+    pub data: Vec<u8>,
+    /// Gas limit of the call.
+    pub gas: Gas,
+    /// Gas price of the transaction.
+    pub gas_price: Gas,
+    /// Value transferred with the call.
+    pub value: U256,
+
+    /// Accounts existing before the call.
+    pub pre: FuzzAccounts,
+}
+
+impl Default for FuzzInput {
+    fn default() -> FuzzInput {
+        FuzzInput {
+            coinbase: Address::from_str("0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba").unwrap(),
+            number: U256::zero(),
+            timestamp: 1,
+            difficulty: U256::from(0x100),
+            block_gas_limit: Gas::from(10_000_000u64),
+
+            address: Address::from_str("0x0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6").unwrap(),
+            caller: Address::from_str("0xcd1722f3947def4cf144679da39c4c32bdc35681").unwrap(),
+            origin: Address::from_str("0xcd1722f3947def4cf144679da39c4c32bdc35681").unwrap(),
+            code: Vec::new(),
+            data: Vec::new(),
+            gas: Gas::from(100_000u64),
+            gas_price: Gas::from(1u64),
+            value: U256::zero(),
+
+            pre: FuzzAccounts::new(),
+        }
+    }
+}
+
+fn u256_to_json<T: Into<U256>>(value: T) -> Value {
+    Value::String(format!("0x{:x}", value.into()))
+}
+
+fn address_to_json(value: Address) -> Value {
+    Value::String(format!("0x{:x}", value))
+}
+
+fn read_address(value: &Value) -> Option<Address> {
+    Address::from_str(value.as_str()?).ok()
+}
+
+fn read_number(value: &Value) -> Option<U256> {
+    Some(read_u256(value.as_str()?))
+}
+
+fn read_bytes(value: &Value) -> Option<Vec<u8>> {
+    read_hex(value.as_str()?).ok()
+}
+
+/// Read accounts in the `pre` or `post` format of `VMTests`.
+pub fn accounts_from_json(value: &Value) -> Option<FuzzAccounts> {
+    let mut accounts = FuzzAccounts::new();
+    for (address, data) in value.as_object()? {
+        let address = Address::from_str(address).ok()?;
+        let mut storage = BTreeMap::new();
+        for (index, value) in data["storage"].as_object()? {
+            let value = M256::from(read_number(value)?);
+            if !value.is_zero() {
+                storage.insert(read_u256(index), value);
+            }
+        }
+
+        accounts.insert(address, FuzzAccount {
+            balance: read_number(&data["balance"])?,
+            nonce: read_number(&data["nonce"])?,
+            code: read_bytes(&data["code"])?,
+            storage,
+        });
+    }
+    Some(accounts)
+}
+
+/// Write accounts in the `pre` or `post` format of `VMTests`.
+pub fn accounts_to_json(accounts: &FuzzAccounts) -> Value {
+    let mut ret = Map::new();
+    for (address, account) in accounts {
+        let mut storage = Map::new();
+        for (index, value) in &account.storage {
+            storage.insert(format!("0x{:x}", index), u256_to_json(*value));
+        }
+
+        ret.insert(format!("0x{:x}", address), json!({
+            "balance": u256_to_json(account.balance),
+            "nonce": u256_to_json(account.nonce),
+            "code": to_hex(&account.code),
+            "storage": storage,
+        }));
+    }
+    Value::Object(ret)
+}
+
+impl FuzzInput {
+    /// Read the input from a fixture with `env`, `exec` and `pre`
+    /// fields. Returns `None` if the fixture is of another format.
+    pub fn from_json(value: &Value) -> Option<FuzzInput> {
+        let env = &value["env"];
+        let exec = &value["exec"];
+
+        Some(FuzzInput {
+            coinbase: read_address(&env["currentCoinbase"])?,
+            number: read_number(&env["currentNumber"])?,
+            timestamp: read_number(&env["currentTimestamp"])?.as_u64(),
+            difficulty: read_number(&env["currentDifficulty"])?,
+            block_gas_limit: Gas::from(read_number(&env["currentGasLimit"])?),
+
+            address: read_address(&exec["address"])?,
+            caller: read_address(&exec["caller"])?,
+            origin: read_address(&exec["origin"])?,
+            code: read_bytes(&exec["code"])?,
+            data: read_bytes(&exec["data"])?,
+            gas: Gas::from(read_number(&exec["gas"])?),
+            gas_price: Gas::from(read_number(&exec["gasPrice"])?),
+            value: read_number(&exec["value"])?,
+
+            pre: accounts_from_json(&value["pre"])?,
+        })
+    }
+
+    /// Write the input as a fixture, with the expected post-state if
+    /// it is known.
+    pub fn to_json(&self, post: Option<&FuzzAccounts>) -> Value {
+        let mut ret = json!({
+            "env": {
+                "currentCoinbase": address_to_json(self.coinbase),
+                "currentNumber": u256_to_json(self.number),
+                "currentTimestamp": u256_to_json(self.timestamp),
+                "currentDifficulty": u256_to_json(self.difficulty),
+                "currentGasLimit": u256_to_json(self.block_gas_limit),
+            },
+            "exec": {
+                "address": address_to_json(self.address),
+                "caller": address_to_json(self.caller),
+                "origin": address_to_json(self.origin),
+                "code": to_hex(&self.code),
+                "data": to_hex(&self.data),
+                "gas": u256_to_json(self.gas),
+                "gasPrice": u256_to_json(self.gas_price),
+                "value": u256_to_json(self.value),
+            },
+            "pre": accounts_to_json(&self.pre),
+        });
+        if let Some(post) = post {
+            ret["post"] = accounts_to_json(post);
+        }
+        ret
+    }
+}
@@ -0,0 +1,40 @@
+//! Differential fuzzing of SputnikVM. Inputs are run through
+//! `SeqContextVM`, and the step trace and the post-state are compared
+//! against the ones of a reference EVM, either recorded offline in the
+//! corpus or produced by running the reference on the fly.
+//!
+//! Precompiled contracts are fuzzed separately with inputs following
+//! their byte layouts, checking invariants instead of a reference.
+
+extern crate bigint;
+extern crate hexutil;
+extern crate sputnikvm;
+extern crate sputnikvm_precompiled_modexp;
+extern crate sputnikvm_precompiled_bn128;
+extern crate jsontests;
+extern crate rand;
+#[macro_use]
+extern crate serde_json;
+
+mod input;
+mod trace;
+mod generate;
+mod minimize;
+mod corpus;
+mod precompiled;
+
+pub use self::input::{FuzzInput, FuzzAccount, FuzzAccounts, accounts_from_json, accounts_to_json};
+pub use self::trace::{Step, Trace, Divergence, execute, compare, read_trace, write_trace, fixture_post};
+pub use self::generate::{generate, mutate, random_code, random_instruction};
+pub use self::minimize::minimize;
+pub use self::corpus::{CorpusEntry, load_seeds, load_fixture, load_entry, load_corpus, save_fixture, save_entry,
+                       run_reference};
+pub use self::precompiled::{PRECOMPILED_GAS_LIMIT, Violation, precompileds, check_precompiled,
+                            random_precompiled_input, random_extension};
+
+use sputnikvm::Patch;
+
+/// Run the corpus entry and compare it against its reference trace.
+pub fn check_entry<P: Patch>(entry: &CorpusEntry) -> Option<Divergence> {
+    compare(&execute::<P>(&entry.input), &entry.steps, entry.post.as_ref())
+}
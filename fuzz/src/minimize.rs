@@ -0,0 +1,100 @@
+//! Shrinking of failing inputs into small fixtures.
+
+use bigint::U256;
+
+use input::FuzzInput;
+
+/// Remove chunks of bytes, halving the chunk size each round, as long
+/// as the candidate still fails.
+fn shrink_bytes<F: FnMut(&[u8]) -> bool>(bytes: &[u8], mut fails: F) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    let mut chunk = bytes.len();
+
+    while chunk > 0 {
+        let mut start = 0;
+        while start < bytes.len() {
+            let end = ::std::cmp::min(start + chunk, bytes.len());
+            let mut candidate = bytes[..start].to_vec();
+            candidate.extend_from_slice(&bytes[end..]);
+
+            if fails(&candidate) {
+                bytes = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+
+    bytes
+}
+
+/// Shrink a failing input. `fails` must return `true` for the input
+/// given, and it is only called with candidates derived from it. The
+/// result is the smallest failing input found.
+pub fn minimize<F: FnMut(&FuzzInput) -> bool>(input: &FuzzInput, mut fails: F) -> FuzzInput {
+    let mut input = input.clone();
+
+    loop {
+        let before = input.clone();
+
+        let addresses: Vec<_> = input.pre.keys().cloned().collect();
+        for address in addresses {
+            let mut candidate = input.clone();
+            candidate.pre.remove(&address);
+            if fails(&candidate) {
+                input = candidate;
+            }
+        }
+
+        let addresses: Vec<_> = input.pre.keys().cloned().collect();
+        for address in addresses {
+            let indexes: Vec<_> = input.pre[&address].storage.keys().cloned().collect();
+            for index in indexes {
+                let mut candidate = input.clone();
+                candidate.pre.get_mut(&address).unwrap().storage.remove(&index);
+                if fails(&candidate) {
+                    input = candidate;
+                }
+            }
+
+            let code = input.pre[&address].code.clone();
+            let code = shrink_bytes(&code, |code| {
+                let mut candidate = input.clone();
+                candidate.pre.get_mut(&address).unwrap().code = code.to_vec();
+                fails(&candidate)
+            });
+            input.pre.get_mut(&address).unwrap().code = code;
+        }
+
+        let code = input.code.clone();
+        let code = shrink_bytes(&code, |code| {
+            let mut candidate = input.clone();
+            candidate.code = code.to_vec();
+            fails(&candidate)
+        });
+        input.code = code;
+
+        let data = input.data.clone();
+        let data = shrink_bytes(&data, |data| {
+            let mut candidate = input.clone();
+            candidate.data = data.to_vec();
+            fails(&candidate)
+        });
+        input.data = data;
+
+        if !input.value.is_zero() {
+            let mut candidate = input.clone();
+            candidate.value = U256::zero();
+            if fails(&candidate) {
+                input = candidate;
+            }
+        }
+
+        if input.code == before.code && input.data == before.data &&
+            input.pre == before.pre && input.value == before.value
+        {
+            return input;
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Structured fuzzing of precompiled contracts.
+
+use bigint::Gas;
+use rand::Rng;
+use sputnikvm::{Precompiled, ECREC_PRECOMPILED, SHA256_PRECOMPILED, RIP160_PRECOMPILED,
+                ID_PRECOMPILED};
+use sputnikvm::errors::RuntimeError;
+use sputnikvm_precompiled_modexp::MODEXP_PRECOMPILED;
+use sputnikvm_precompiled_bn128::{BN128_ADD_PRECOMPILED, BN128_MUL_PRECOMPILED,
+                                  BN128_PAIRING_PRECOMPILED};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+/// Gas limit given to precompiled contracts. It bounds the work done
+/// for a single input.
+pub const PRECOMPILED_GAS_LIMIT: u64 = 10_000_000;
+
+/// All precompiled contracts, with their names.
+pub fn precompileds() -> Vec<(&'static str, &'static Precompiled)> {
+    vec![
+        ("ecrec", &ECREC_PRECOMPILED),
+        ("sha256", &SHA256_PRECOMPILED),
+        ("rip160", &RIP160_PRECOMPILED),
+        ("id", &ID_PRECOMPILED),
+        ("modexp", &MODEXP_PRECOMPILED),
+        ("bn128_add", &BN128_ADD_PRECOMPILED),
+        ("bn128_mul", &BN128_MUL_PRECOMPILED),
+        ("bn128_pairing", &BN128_PAIRING_PRECOMPILED),
+    ]
+}
+
+#[derive(Debug, Clone)]
+/// Invariant of a precompiled contract broken by an input.
+pub enum Violation {
+    /// The contract panicked.
+    Panic(String),
+    /// Output was returned with gas used above the gas limit, which
+    /// are given in this order.
+    OutputOverLimit(Gas, Gas),
+    /// The gas used differs between two gas limits that are both
+    /// enough.
+    InconsistentGas(Gas, Gas),
+    /// Extending the input decreased the gas used, from the first to
+    /// the second.
+    NonMonotonicGas(Gas, Gas),
+}
+
+type Outcome = Result<(Gas, Arc<Vec<u8>>), RuntimeError>;
+
+fn call(precompiled: &Precompiled, data: &[u8], gas_limit: Gas) -> Result<Outcome, Violation> {
+    panic::catch_unwind(AssertUnwindSafe(|| precompiled.gas_and_step(data, gas_limit)))
+        .map_err(|err| {
+            let message = err.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| err.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Violation::Panic(message)
+        })
+}
+
+fn check_limits(precompiled: &Precompiled, data: &[u8], outcome: &Outcome) -> Result<(), Violation> {
+    let limit = Gas::from(PRECOMPILED_GAS_LIMIT);
+    let (gas, output) = match *outcome {
+        Ok((gas, ref output)) => (gas, output.clone()),
+        Err(_) => return Ok(()),
+    };
+
+    if gas > limit {
+        return Err(Violation::OutputOverLimit(gas, limit));
+    }
+    if gas > Gas::zero() {
+        let below = gas - Gas::from(1u64);
+        if let Ok((used, _)) = call(precompiled, data, below)? {
+            return Err(Violation::OutputOverLimit(used, below));
+        }
+    }
+    match call(precompiled, data, gas)? {
+        Ok((used, ref exact)) if used == gas && exact == &output => Ok(()),
+        Ok((used, _)) => Err(Violation::InconsistentGas(gas, used)),
+        Err(_) => Err(Violation::InconsistentGas(gas, Gas::zero())),
+    }
+}
+
+/// Check the invariants of the precompiled contract on `data`, and on
+/// `data` extended with `extra`.
+pub fn check_precompiled(precompiled: &Precompiled, data: &[u8], extra: &[u8]) -> Result<(), Violation> {
+    let limit = Gas::from(PRECOMPILED_GAS_LIMIT);
+    let mut extended = data.to_vec();
+    extended.extend_from_slice(extra);
+
+    let short = call(precompiled, data, limit)?;
+    check_limits(precompiled, data, &short)?;
+    let long = call(precompiled, &extended, limit)?;
+    check_limits(precompiled, &extended, &long)?;
+
+    match (short, long) {
+        (Ok((short, _)), Ok((long, _))) if long < short =>
+            Err(Violation::NonMonotonicGas(short, long)),
+        _ => Ok(()),
+    }
+}
+
+fn random_bytes<R: Rng>(rng: &mut R, len: usize) -> Vec<u8> {
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+/// A 32-byte word that is often a small number, so that lengths and
+/// field elements stay in range.
+fn random_word<R: Rng>(rng: &mut R) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    match rng.gen_range(0, 4) {
+        0 => (),
+        1 => word[31] = rng.gen(),
+        2 => {
+            word[30] = rng.gen_range(0, 2);
+            word[31] = rng.gen();
+        },
+        _ => for byte in word.iter_mut() {
+            *byte = rng.gen();
+        },
+    }
+    word
+}
+
+/// Coordinates of the generator of G1.
+fn g1_generator() -> Vec<u8> {
+    let mut ret = vec![0u8; 64];
+    ret[31] = 1;
+    ret[63] = 2;
+    ret
+}
+
+fn random_g1<R: Rng>(rng: &mut R) -> Vec<u8> {
+    match rng.gen_range(0, 3) {
+        0 => vec![0u8; 64],
+        1 => g1_generator(),
+        _ => {
+            let mut ret = random_word(rng).to_vec();
+            ret.extend_from_slice(&random_word(rng));
+            ret
+        },
+    }
+}
+
+/// Generate an input following the byte layout of the named
+/// precompiled contract, sometimes truncated or with garbage.
+pub fn random_precompiled_input<R: Rng>(rng: &mut R, name: &str) -> Vec<u8> {
+    let mut data = match name {
+        "ecrec" => {
+            let mut data = random_bytes(rng, 32);
+            let mut v = [0u8; 32];
+            v[31] = *rng.choose(&[27u8, 28, 0, 1, 29]).unwrap();
+            data.extend_from_slice(&v);
+            data.extend_from_slice(&random_word(rng));
+            data.extend_from_slice(&random_word(rng));
+            data
+        },
+        "modexp" => {
+            let lengths: Vec<usize> = (0..3).map(|_| rng.gen_range(0, 65)).collect();
+            let mut data = Vec::new();
+            for length in &lengths {
+                if rng.gen_weighted_bool(8) {
+                    data.extend_from_slice(&random_word(rng));
+                } else {
+                    let mut word = [0u8; 32];
+                    word[31] = *length as u8;
+                    data.extend_from_slice(&word);
+                }
+            }
+            let body: usize = lengths.iter().sum();
+            data.extend(random_bytes(rng, body));
+            data
+        },
+        "bn128_add" => {
+            let mut data = random_g1(rng);
+            data.extend(random_g1(rng));
+            data
+        },
+        "bn128_mul" => {
+            let mut data = random_g1(rng);
+            data.extend_from_slice(&random_word(rng));
+            data
+        },
+        "bn128_pairing" => {
+            let mut data = Vec::new();
+            for _ in 0..rng.gen_range(0, 3) {
+                data.extend(random_g1(rng));
+                if rng.gen() {
+                    data.extend(vec![0u8; 128]);
+                } else {
+                    for _ in 0..4 {
+                        data.extend_from_slice(&random_word(rng));
+                    }
+                }
+            }
+            data
+        },
+        _ => {
+            let len = rng.gen_range(0, 300);
+            random_bytes(rng, len)
+        },
+    };
+
+    if rng.gen_weighted_bool(4) && !data.is_empty() {
+        let len = rng.gen_range(0, data.len());
+        data.truncate(len);
+    }
+    data
+}
+
+/// Generate bytes used to extend an input when checking that gas is
+/// monotonic in input length.
+pub fn random_extension<R: Rng>(rng: &mut R) -> Vec<u8> {
+    let len = rng.gen_range(0, 97);
+    random_bytes(rng, len)
+}
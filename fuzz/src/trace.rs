@@ -0,0 +1,272 @@
+//! Step traces of SputnikVM and of reference EVMs.
+
+use bigint::{Gas, M256, U256, H256, Address};
+use sputnikvm::{VM, SeqContextVM, Context, HeaderParams, Patch, MachineStatus,
+                AccountCommitment, AccountChange, RequireError, VMStatus};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use jsontests::read_u256;
+
+use input::{FuzzInput, FuzzAccount, FuzzAccounts};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// State of the EVM right before an instruction is executed. This
+/// follows the JSON trace format of EIP-3155.
+pub struct Step {
+    /// Position of the instruction.
+    pub pc: usize,
+    /// Opcode of the instruction. Past the end of the code, this is
+    /// the implicit `STOP`.
+    pub op: u8,
+    /// Gas available before the instruction.
+    pub gas: Gas,
+    /// Call depth, starting at one.
+    pub depth: usize,
+    /// Stack, from the bottom to the top.
+    pub stack: Vec<U256>,
+}
+
+impl Step {
+    /// Read a step from a trace line. Returns `None` for lines that
+    /// are not steps, such as the summary printed after execution.
+    pub fn from_json(value: &Value) -> Option<Step> {
+        let mut stack = Vec::new();
+        for item in value.get("stack")?.as_array()? {
+            stack.push(read_u256(item.as_str()?));
+        }
+
+        Some(Step {
+            pc: value.get("pc")?.as_u64()? as usize,
+            op: value.get("op")?.as_u64()? as u8,
+            gas: Gas::from(read_u256(value.get("gas")?.as_str()?)),
+            depth: value.get("depth")?.as_u64()? as usize,
+            stack,
+        })
+    }
+
+    /// Write the step as a trace line.
+    pub fn to_json(&self) -> Value {
+        let gas: U256 = self.gas.into();
+        json!({
+            "pc": self.pc,
+            "op": self.op,
+            "gas": format!("0x{:x}", gas),
+            "depth": self.depth,
+            "stack": self.stack.iter().map(|item| format!("0x{:x}", item)).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Read a trace with one JSON object per line, skipping lines that are
+/// not steps.
+pub fn read_trace(trace: &str) -> Vec<Step> {
+    trace.lines()
+        .filter_map(|line| ::serde_json::from_str::<Value>(line).ok())
+        .filter_map(|value| Step::from_json(&value))
+        .collect()
+}
+
+/// Write a trace with one JSON object per line.
+pub fn write_trace(steps: &[Step]) -> String {
+    let mut ret = String::new();
+    for step in steps {
+        ret.push_str(&step.to_json().to_string());
+        ret.push('\n');
+    }
+    ret
+}
+
+#[derive(Debug, Clone)]
+/// Result of running an input through SputnikVM.
+pub struct Trace {
+    /// All steps executed.
+    pub steps: Vec<Step>,
+    /// Status of the VM after execution.
+    pub status: VMStatus,
+    /// Accounts after execution.
+    pub post: FuzzAccounts,
+}
+
+fn commitment(pre: &FuzzAccounts, require: RequireError) -> AccountCommitment {
+    match require {
+        RequireError::Account(address) => match pre.get(&address) {
+            Some(account) => AccountCommitment::Full {
+                nonce: account.nonce,
+                address,
+                balance: account.balance,
+                code: Arc::new(account.code.clone()),
+            },
+            None => AccountCommitment::Nonexist(address),
+        },
+        RequireError::AccountCode(address) => AccountCommitment::Code {
+            address,
+            code: Arc::new(pre.get(&address).map(|account| account.code.clone())
+                          .unwrap_or_default()),
+        },
+        RequireError::AccountStorage(address, index) => AccountCommitment::Storage {
+            address,
+            index,
+            value: pre.get(&address).and_then(|account| account.storage.get(&index).cloned())
+                .unwrap_or(M256::zero()),
+        },
+        RequireError::Blockhash(_) => unreachable!(),
+    }
+}
+
+fn apply(post: &mut FuzzAccounts, change: &AccountChange) {
+    match *change {
+        AccountChange::Full { address, balance, nonce, ref code, ref changing_storage } => {
+            let account = post.entry(address).or_insert_with(FuzzAccount::default);
+            account.balance = balance;
+            account.nonce = nonce;
+            account.code = code.as_ref().clone();
+            let changing_storage: HashMap<U256, M256> = changing_storage.clone().into();
+            for (index, value) in changing_storage {
+                account.storage.insert(index, value);
+            }
+        },
+        AccountChange::Create { address, balance, nonce, ref code, ref storage } => {
+            let storage: HashMap<U256, M256> = storage.clone().into();
+            post.insert(address, FuzzAccount {
+                balance,
+                nonce,
+                code: code.as_ref().clone(),
+                storage: storage.into_iter().collect(),
+            });
+        },
+        AccountChange::IncreaseBalance(address, topup) => {
+            let account = post.entry(address).or_insert_with(FuzzAccount::default);
+            account.balance = account.balance + topup;
+        },
+        AccountChange::Nonexist(address) => {
+            post.remove(&address);
+        },
+    }
+}
+
+/// Normalize accounts so that two equivalent states compare equal.
+fn normalize(accounts: &FuzzAccounts) -> FuzzAccounts {
+    accounts.iter()
+        .filter(|&(_, account)| !account.is_empty())
+        .map(|(address, account)| {
+            let mut account = account.clone();
+            account.storage = account.storage.into_iter()
+                .filter(|&(_, value)| !value.is_zero())
+                .collect();
+            (*address, account)
+        })
+        .collect()
+}
+
+/// Run the input through `SeqContextVM` and record every step.
+pub fn execute<P: Patch>(input: &FuzzInput) -> Trace {
+    let context = Context {
+        address: input.address,
+        caller: input.caller,
+        code: Arc::new(input.code.clone()),
+        data: Arc::new(input.data.clone()),
+        gas_limit: input.gas,
+        gas_price: input.gas_price,
+        origin: input.origin,
+        value: input.value,
+        apprent_value: input.value,
+        is_system: false,
+        is_static: false,
+    };
+    let header = HeaderParams {
+        beneficiary: input.coinbase,
+        timestamp: input.timestamp,
+        number: input.number,
+        difficulty: input.difficulty,
+        gas_limit: input.block_gas_limit,
+    };
+
+    let mut vm = SeqContextVM::<P>::new(context, header);
+    let mut steps = Vec::new();
+
+    while let VMStatus::Running = vm.status() {
+        let step = match vm.current_machine().status() {
+            MachineStatus::Running => {
+                let state = vm.current_state();
+                let stack = &state.stack;
+                Some(Step {
+                    pc: state.position,
+                    op: state.context.code.get(state.position).cloned().unwrap_or(0x00),
+                    gas: state.available_gas(),
+                    depth: state.depth,
+                    stack: (0..stack.len()).rev()
+                        .map(|i| stack.peek(i).unwrap().into())
+                        .collect(),
+                })
+            },
+            _ => None,
+        };
+
+        match vm.step() {
+            Ok(()) => steps.extend(step),
+            Err(RequireError::Blockhash(number)) => {
+                vm.commit_blockhash(number, H256::default()).unwrap();
+            },
+            Err(require) => {
+                vm.commit_account(commitment(&input.pre, require)).unwrap();
+            },
+        }
+    }
+
+    let mut post = input.pre.clone();
+    for change in vm.accounts() {
+        apply(&mut post, change);
+    }
+
+    Trace {
+        steps,
+        status: vm.status(),
+        post: normalize(&post),
+    }
+}
+
+#[derive(Debug, Clone)]
+/// First difference found between SputnikVM and the reference.
+pub enum Divergence {
+    /// The step at the index differs. A missing step means that one
+    /// of the traces ended earlier.
+    Step(usize, Option<Step>, Option<Step>),
+    /// The account differs in the post-state. A missing account is
+    /// empty on that side.
+    Account(Address, Option<FuzzAccount>, Option<FuzzAccount>),
+}
+
+/// Compare the trace against the reference steps and, if given, the
+/// reference post-state.
+pub fn compare(trace: &Trace, steps: &[Step], post: Option<&FuzzAccounts>) -> Option<Divergence> {
+    for i in 0..::std::cmp::max(trace.steps.len(), steps.len()) {
+        let ours = trace.steps.get(i);
+        let theirs = steps.get(i);
+        if ours != theirs {
+            return Some(Divergence::Step(i, ours.cloned(), theirs.cloned()));
+        }
+    }
+
+    if let Some(post) = post {
+        let post = normalize(post);
+        let mut addresses: Vec<Address> = trace.post.keys().chain(post.keys()).cloned().collect();
+        addresses.sort();
+        addresses.dedup();
+
+        for address in addresses {
+            let ours = trace.post.get(&address);
+            let theirs = post.get(&address);
+            if ours != theirs {
+                return Some(Divergence::Account(address, ours.cloned(), theirs.cloned()));
+            }
+        }
+    }
+
+    None
+}
+
+/// Read the expected post-state stored in a fixture, if any.
+pub fn fixture_post(fixture: &Value) -> Option<FuzzAccounts> {
+    ::input::accounts_from_json(fixture.get("post")?)
+}
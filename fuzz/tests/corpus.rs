@@ -0,0 +1,59 @@
+extern crate sputnikvm_network_classic;
+extern crate sputnikvm_fuzz;
+extern crate rand;
+extern crate bigint;
+
+use sputnikvm_network_classic::MainnetEIP160Patch;
+use sputnikvm_fuzz::*;
+use rand::{SeedableRng, StdRng};
+use bigint::Gas;
+use std::path::Path;
+
+#[test]
+fn corpus_matches_reference() {
+    let corpus = load_corpus(&Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus")).unwrap();
+    assert!(!corpus.is_empty());
+
+    for entry in &corpus {
+        if let Some(divergence) = check_entry::<MainnetEIP160Patch>(entry) {
+            panic!("{}: {:?}", entry.name, divergence);
+        }
+    }
+}
+
+#[test]
+fn divergence_in_gas_is_found() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus/add-sstore.json");
+    let mut entry = load_entry(&path).unwrap();
+    entry.steps[3].gas = entry.steps[3].gas + Gas::from(1u64);
+
+    match check_entry::<MainnetEIP160Patch>(&entry) {
+        Some(Divergence::Step(3, _, _)) => (),
+        divergence => panic!("unexpected divergence {:?}", divergence),
+    }
+}
+
+#[test]
+fn generated_inputs_roundtrip() {
+    let mut rng = StdRng::from_seed(&[42usize][..]);
+    for _ in 0..50 {
+        let input = generate(&mut rng, &[]);
+        let fixture = input.to_json(None);
+        let parsed = FuzzInput::from_json(&fixture).unwrap();
+        assert_eq!(parsed.code, input.code);
+        assert_eq!(parsed.data, input.data);
+        assert_eq!(parsed.pre, input.pre);
+        assert_eq!(parsed.to_json(None), fixture);
+    }
+}
+
+#[test]
+fn minimize_keeps_failing_byte() {
+    let mut input = FuzzInput::default();
+    input.code = vec![0x60, 0x01, 0x60, 0x02, 0x01, 0x60, 0x00, 0x55, 0x00];
+    input.data = vec![0xaa; 16];
+
+    let minimized = minimize(&input, |candidate| candidate.code.contains(&0x55));
+    assert_eq!(minimized.code, vec![0x55]);
+    assert!(minimized.data.is_empty());
+}
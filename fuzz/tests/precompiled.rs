@@ -0,0 +1,29 @@
+extern crate sputnikvm_fuzz;
+extern crate rand;
+
+use sputnikvm_fuzz::*;
+use rand::{SeedableRng, StdRng};
+
+#[test]
+fn precompileds_keep_invariants() {
+    let mut rng = StdRng::from_seed(&[7usize][..]);
+    for _ in 0..100 {
+        for (name, precompiled) in precompileds() {
+            let data = random_precompiled_input(&mut rng, name);
+            let extra = random_extension(&mut rng);
+            if let Err(violation) = check_precompiled(precompiled, &data, &extra) {
+                panic!("{}: {:?} with data {:?} and extra {:?}", name, violation, data, extra);
+            }
+        }
+    }
+}
+
+#[test]
+fn modexp_with_huge_exponent_length() {
+    let (_, modexp) = precompileds().into_iter().find(|&(name, _)| name == "modexp").unwrap();
+    let mut data = vec![0u8; 96];
+    for byte in &mut data[32..64] {
+        *byte = 0xff;
+    }
+    check_precompiled(modexp, &data, &[0x01]).unwrap();
+}
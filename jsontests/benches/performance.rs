@@ -4,10 +4,14 @@
 extern crate jsontests_derive;
 extern crate jsontests;
 extern crate sputnikvm;
+extern crate bigint;
 #[macro_use]
 extern crate criterion;
 
+use bigint::{U256, M256, Gas, Address};
 use criterion::Criterion;
+use sputnikvm::{VM, Memory, SeqMemory, SeqContextVM, Context, HeaderParams, EmbeddedPatch};
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(JsonTests)]
@@ -17,6 +21,60 @@ use std::time::Duration;
 #[criterion_config = "criterion_cfg"]
 struct Performance;
 
+const COPY_LEN: usize = 0x10000;
+
+fn words(c: &mut Criterion) {
+    c.bench_function("seq_memory_words", |b| {
+        b.iter(|| {
+            let mut memory = SeqMemory::<EmbeddedPatch>::default();
+            memory.expand(U256::from(1024u64 * 32)).unwrap();
+            for i in 0..1024u64 {
+                memory.write(U256::from(i * 32), M256::from(i)).unwrap();
+            }
+            let mut sum = M256::zero();
+            for i in 0..1024u64 {
+                sum = sum + memory.read(U256::from(i * 32));
+            }
+            sum
+        })
+    });
+}
+
+fn copies(c: &mut Criterion) {
+    // CALLDATACOPY, SHA3 and RETURN of 64 KiB of call data.
+    let code = vec![0x62, 0x01, 0x00, 0x00, 0x60, 0x00, 0x60, 0x00, 0x37,
+                    0x62, 0x01, 0x00, 0x00, 0x60, 0x00, 0x20, 0x50,
+                    0x62, 0x01, 0x00, 0x00, 0x60, 0x00, 0xf3];
+    let context = Context {
+        address: Address::default(),
+        caller: Address::default(),
+        code: Arc::new(code),
+        data: Arc::new(vec![0xab; COPY_LEN]),
+        gas_limit: Gas::from(1_000_000u64),
+        gas_price: Gas::zero(),
+        origin: Address::default(),
+        value: U256::zero(),
+        apprent_value: U256::zero(),
+        is_system: false,
+        is_static: false,
+    };
+    let block = HeaderParams {
+        beneficiary: Address::default(),
+        timestamp: 0,
+        number: U256::zero(),
+        difficulty: U256::zero(),
+        gas_limit: Gas::zero(),
+    };
+
+    c.bench_function("context_vm_copies", move |b| {
+        b.iter_with_large_setup(|| {
+            SeqContextVM::<EmbeddedPatch>::new(context.clone(), block.clone())
+        }, |mut vm| {
+            vm.fire().unwrap();
+            assert_eq!(vm.out().len(), COPY_LEN);
+        })
+    });
+}
 
 pub fn criterion_cfg() -> Criterion {
     // Due to poor SputnikVM performance, there's no chance to get a lot of measurements
@@ -26,3 +84,17 @@ pub fn criterion_cfg() -> Criterion {
         .measurement_time(Duration::from_secs(10))
         .noise_threshold(0.07)
 }
+
+pub fn memory_criterion_cfg() -> Criterion {
+    Criterion::default()
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(10))
+        .noise_threshold(0.07)
+}
+
+criterion_group! {
+    name = memory;
+    config = memory_criterion_cfg();
+    targets = words, copies
+}
+criterion_main!(json_tests, memory);
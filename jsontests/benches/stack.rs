@@ -0,0 +1,119 @@
+//! Stack benchmarks: pushing and popping, DUPn and SWAPn without
+//! checks, and a stack-heavy loop run by the VM. Run them with
+//! `cargo bench --bench stack`.
+//!
+//! `stack_push_pop` and `context_vm_stack_heavy` only use the checked
+//! stack API, so they also build against the `Vec` backed stack from
+//! before the fixed capacity one, with `STACK_LIMIT` replaced by
+//! `1024`. Compare those two to measure the change.
+//! `stack_dup_swap` uses the unchecked accessors and has no earlier
+//! counterpart.
+
+extern crate sputnikvm;
+extern crate bigint;
+#[macro_use]
+extern crate criterion;
+
+use bigint::{U256, M256, Gas, Address};
+use criterion::Criterion;
+use sputnikvm::{VM, Stack, STACK_LIMIT, SeqContextVM, Context, HeaderParams, EmbeddedPatch};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LOOPS: u64 = 2000;
+
+fn push_pop(c: &mut Criterion) {
+    c.bench_function("stack_push_pop", |b| {
+        b.iter(|| {
+            let mut stack = Stack::default();
+            for i in 0..STACK_LIMIT {
+                stack.push(M256::from(i as u64)).unwrap();
+            }
+            let mut sum = M256::zero();
+            for _ in 0..STACK_LIMIT {
+                sum = sum + stack.pop().unwrap();
+            }
+            sum
+        })
+    });
+}
+
+fn dup_swap(c: &mut Criterion) {
+    c.bench_function("stack_dup_swap", |b| {
+        b.iter(|| {
+            let mut stack = Stack::default();
+            for i in 0..16u64 {
+                stack.push(M256::from(i)).unwrap();
+            }
+            // The stack holds at least 16 values and never more than
+            // `STACK_LIMIT`.
+            for _ in 0..STACK_LIMIT - 16 {
+                unsafe {
+                    stack.dup_unchecked(16);
+                    stack.swap_unchecked(16);
+                }
+            }
+            stack.len()
+        })
+    });
+}
+
+fn stack_heavy(c: &mut Criterion) {
+    // Pushes 16 items, then loops `LOOPS` times over DUP16, SWAP16
+    // and POP before decrementing the counter at the bottom.
+    let mut code = vec![0x61, (LOOPS >> 8) as u8, LOOPS as u8];
+    for _ in 0..15 {
+        code.extend_from_slice(&[0x60, 0x01]);
+    }
+    let loop_start = code.len() as u8;
+    code.push(0x5b);
+    for _ in 0..8 {
+        code.extend_from_slice(&[0x8f, 0x9f, 0x50]);
+    }
+    // DUP16 of the counter, decrement and swap it back.
+    code.extend_from_slice(&[0x8f, 0x60, 0x01, 0x90, 0x03, 0x9f, 0x50,
+                             0x8f, 0x60, loop_start, 0x57, 0x00]);
+
+    let context = Context {
+        address: Address::default(),
+        caller: Address::default(),
+        code: Arc::new(code),
+        data: Arc::new(Vec::new()),
+        gas_limit: Gas::from(10_000_000u64),
+        gas_price: Gas::zero(),
+        origin: Address::default(),
+        value: U256::zero(),
+        apprent_value: U256::zero(),
+        is_system: false,
+        is_static: false,
+    };
+    let block = HeaderParams {
+        beneficiary: Address::default(),
+        timestamp: 0,
+        number: U256::zero(),
+        difficulty: U256::zero(),
+        gas_limit: Gas::zero(),
+    };
+
+    c.bench_function("context_vm_stack_heavy", move |b| {
+        b.iter_with_large_setup(|| {
+            SeqContextVM::<EmbeddedPatch>::new(context.clone(), block.clone())
+        }, |mut vm| {
+            vm.fire().unwrap();
+        })
+    });
+}
+
+pub fn criterion_cfg() -> Criterion {
+    Criterion::default()
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(10))
+        .noise_threshold(0.07)
+}
+
+criterion_group! {
+    name = stack;
+    config = criterion_cfg();
+    targets = push_pop, dup_swap, stack_heavy
+}
+criterion_main!(stack);
@@ -12,6 +12,7 @@ pub struct Config {
     pub bench_with: Option<ExternalRef>,
     pub criterion_config: Option<ExternalRef>,
     pub patch: Option<ExternalRef>,
+    pub fork: Option<String>,
     pub skip: bool,
     pub should_panic: bool,
 }
@@ -43,17 +44,18 @@ impl From<String> for ExternalRef {
 }
 
 pub fn extract_attrs(ast: &syn::DeriveInput) -> Result<Config, Error> {
-    const ERROR_MSG: &str = "expected 2 attributes and 3 optional\n\n\
+    const ERROR_MSG: &str = "expected 2 attributes and 5 optional\n\n\
                 #[derive(JsonTests)]\n\
                 #[directory = \"../tests/testset\"]\n\
                 #[test_with = \"test::test_function\"]\n\
                 #[bench_wuth = \"test::bench_function\"] (Optional)\n\
                 #[patch = \"CustomTestPatch\" (Optional)\n\
+                #[fork = \"Byzantium\"] (Optional)\n\
                 #[skip] (Optional)\n\
                 #[should_panic] (Optional)\n\
                 struct TestSet;";
 
-    if ast.attrs.len() < 2 || ast.attrs.len() > 6 {
+    if ast.attrs.len() < 2 || ast.attrs.len() > 7 {
         bail!(ERROR_MSG);
     }
 
@@ -66,6 +68,7 @@ pub fn extract_attrs(ast: &syn::DeriveInput) -> Result<Config, Error> {
                     "bench_with" => Config { bench_with: Some(ExternalRef::from(value.clone())), ..config },
                     "criterion_config" => Config { criterion_config: Some(ExternalRef::from(value.clone())), ..config },
                     "patch" => Config { patch: Some(ExternalRef::from(value.clone())), ..config },
+                    "fork" => Config { fork: Some(value.clone()), ..config },
                     _ => panic!("{}", ERROR_MSG),
                 }
             },
@@ -84,6 +87,9 @@ pub fn extract_attrs(ast: &syn::DeriveInput) -> Result<Config, Error> {
     if config.should_panic && config.bench_with.is_some() {
         panic!("#[should_panic] is incompatible with benchmark tests");
     }
+    if config.fork.is_some() && config.bench_with.is_some() {
+        panic!("#[fork] is incompatible with benchmark tests");
+    }
 
     Ok(config)
 }
@@ -21,7 +21,7 @@ use itertools::Itertools;
 use syn::Ident;
 use proc_macro::TokenStream;
 
-#[proc_macro_derive(JsonTests, attributes(directory, test_with, bench_with, criterion_config, skip, should_panic, patch))]
+#[proc_macro_derive(JsonTests, attributes(directory, test_with, bench_with, criterion_config, skip, should_panic, patch, fork))]
 pub fn json_tests(input: TokenStream) -> TokenStream {
     // Construct a string representation of the type definition
     let s = input.to_string();
@@ -110,21 +110,53 @@ fn generate_test(config: &Config, test_name: &Ident, data: &str, tokens: &mut qu
     let test_func_path = &config.test_with.path;
     let test_func_name = &config.test_with.name;
     let test_name_str = test_name.as_ref();
-    let (patch_name, patch_path) = derive_patch(config);
 
     tokens.append(quote!{#[test]});
     if config.should_panic {
         tokens.append(quote!{#[should_panic]});
     }
 
-    tokens.append(quote! {
-        fn #test_name() {
-            use #test_func_path;
-            use #patch_path;
-            let data = #data;
-            #test_func_name::<#patch_name>(#test_name_str, data);
-        }
-    });
+    match (derive_patch(config), config.fork.as_ref()) {
+        (Some((patch_name, patch_path)), Some(fork)) => {
+            tokens.append(quote! {
+                fn #test_name() {
+                    use #test_func_path;
+                    use #patch_path;
+                    let data = #data;
+                    #test_func_name::<#patch_name>(#test_name_str, #fork, data);
+                }
+            });
+        },
+        (Some((patch_name, patch_path)), None) => {
+            tokens.append(quote! {
+                fn #test_name() {
+                    use #test_func_path;
+                    use #patch_path;
+                    let data = #data;
+                    #test_func_name::<#patch_name>(#test_name_str, data);
+                }
+            });
+        },
+        (None, Some(fork)) => {
+            // The patch of the fork is chosen by the table in jsontests.
+            let message = format!("no patch known for fork {}, specify one with #[patch]", fork);
+            tokens.append(quote! {
+                fn #test_name() {
+                    struct Test;
+                    impl ::jsontests::util::ForkFn for Test {
+                        type Output = ();
+                        fn call<P: ::sputnikvm::Patch>(self) {
+                            use #test_func_path;
+                            let data = #data;
+                            #test_func_name::<P>(#test_name_str, #fork, data);
+                        }
+                    }
+                    ::jsontests::util::with_fork_patch(#fork, Test).expect(#message);
+                }
+            });
+        },
+        (None, None) => unreachable!(),
+    }
 }
 
 fn generate_bench(config: &Config, test_name: &Ident, data: &str, tokens: &mut quote::Tokens) -> Option<Ident> {
@@ -139,7 +171,8 @@ fn generate_bench(config: &Config, test_name: &Ident, data: &str, tokens: &mut q
     let bench_name = format!("bench_{}", test_name.as_ref());
     let bench_ident = Ident::from(bench_name.as_ref());
 
-    let (patch_name, patch_path) = derive_patch(config);
+    // Forks are not allowed with benchmarks, so the patch is known.
+    let (patch_name, patch_path) = derive_patch(config).unwrap();
 
     tokens.append(quote! {
         pub fn #bench_ident(c: &mut Criterion) {
@@ -163,7 +196,7 @@ fn generate_criterion_macros(config: &Config, benches: &[Ident], tokens: &mut qu
             .unwrap_or_else(|| Ident::from("Criterion::default"));
         let template = quote! {
             criterion_group! {
-                name = main;
+                name = json_tests;
                 config = #config();
                 targets = TARGETS
             };
@@ -172,13 +205,17 @@ fn generate_criterion_macros(config: &Config, benches: &[Ident], tokens: &mut qu
     }
 }
 
-fn derive_patch(config: &Config) -> (Ident, Ident) {
+/// The patch given with `#[patch]`, or the VM test patch without a
+/// fork. With a fork, the patch is chosen when the test runs.
+fn derive_patch(config: &Config) -> Option<(Ident, Ident)> {
     if let Some(patch) = config.patch.as_ref() {
-        (patch.name.clone(), patch.path.clone())
+        Some((patch.name.clone(), patch.path.clone()))
+    } else if config.fork.is_some() {
+        None
     } else {
-        (
+        Some((
             Ident::from("VMTestPatch"),
             Ident::from("sputnikvm::VMTestPatch")
-        )
+        ))
     }
 }
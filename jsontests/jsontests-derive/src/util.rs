@@ -7,8 +7,14 @@ pub fn open_directory_module(config: &Config, tokens: &mut quote::Tokens) -> Str
     // get the leaf directory name
     let dirname = config.directory.rsplit('/').next().unwrap();
 
+    // the same directory may be run for several forks
+    let dirname = match config.fork {
+        Some(ref fork) => format!("{}_{}", dirname, fork),
+        None => dirname.to_string(),
+    };
+
     // create identifier
-    let dirname = sanitize_ident(dirname);
+    let dirname = sanitize_ident(&dirname);
     let dirname_ident = Ident::from(dirname.as_ref());
 
     open_module(dirname_ident, tokens);
@@ -0,0 +1,73 @@
+//! Fill `GeneralStateTests` fixtures from compact YAML or JSON specs.
+//!
+//! Usage: `jsontests-filler <spec> <output> <fork>...`, where the spec
+//! maps test names to specs in the format described in
+//! `jsontests::fill`, and each fork is one of `Frontier`, `Homestead`,
+//! `EIP150`, `EIP158` and `Byzantium`.
+
+extern crate jsontests;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate sputnikvm;
+
+use jsontests::{fill, FillError};
+use jsontests::util::{ForkFn, with_fork_patch};
+use serde_json::{Value, Map};
+use sputnikvm::Patch;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process;
+
+struct Fill<'a>(&'a str, &'a Value);
+impl<'a> ForkFn for Fill<'a> {
+    type Output = Result<Value, FillError>;
+    fn call<P: Patch>(self) -> Result<Value, FillError> { fill::<P>(self.0, self.1) }
+}
+
+fn fill_fork(fork: &str, spec: &Value) -> Result<Value, FillError> {
+    with_fork_patch(fork, Fill(fork, spec)).unwrap_or_else(|| panic!("no patch known for fork {}", fork))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 4 {
+        eprintln!("usage: {} <spec> <output> <fork>...", args[0]);
+        process::exit(2);
+    }
+    let (spec_path, output_path, forks) = (&args[1], &args[2], &args[3..]);
+
+    let mut content = String::new();
+    File::open(spec_path).unwrap().read_to_string(&mut content).unwrap();
+    let specs: Value = if spec_path.ends_with(".yml") || spec_path.ends_with(".yaml") {
+        serde_yaml::from_str(&content).unwrap()
+    } else {
+        serde_json::from_str(&content).unwrap()
+    };
+
+    let mut tests = Map::new();
+    for (name, spec) in specs.as_object().expect("spec must map test names to specs") {
+        let mut test: Option<Value> = None;
+        for fork in forks {
+            let filled = match fill_fork(fork, spec) {
+                Ok(filled) => filled,
+                Err(err) => {
+                    eprintln!("{}: filling for {} failed: {:?}", name, fork, err);
+                    process::exit(1);
+                },
+            };
+            match test {
+                Some(ref mut test) => {
+                    test["post"][fork.as_str()] = filled["post"][fork.as_str()].clone();
+                },
+                None => test = Some(filled),
+            }
+        }
+        tests.insert(name.clone(), test.unwrap());
+        println!("{}: filled", name);
+    }
+
+    let mut output = File::create(output_path).unwrap();
+    output.write_all(serde_json::to_string_pretty(&Value::Object(tests)).unwrap().as_bytes()).unwrap();
+    output.write_all(b"\n").unwrap();
+}
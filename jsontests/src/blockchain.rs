@@ -2,14 +2,19 @@ use bigint::{Gas, M256, U256, H256, Address};
 use hexutil::*;
 use sputnikvm::{Log, Context,
                 AccountChange, AccountCommitment,
-                HeaderParams};
+                HeaderParams, SeqMemory, Patch};
+use sputnikvm_stateful::{MemoryStateful, RewardPatch};
+use block::{Block, Header};
+use trie::{Database, MemoryDatabase};
+use state::pre_state;
 
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::cmp::{min, max};
 use std::str::FromStr;
-use std::rc::Rc;
+use std::sync::Arc;
 use read_u256;
-use rlp;
+use rlp::{self, UntrustedRlp};
 use sha3::Keccak256;
 use sha3::Digest;
 
@@ -49,7 +54,7 @@ impl JSONBlock {
         AccountCommitment::Full {
             address,
             balance,
-            code: Rc::new(code.into()),
+            code: Arc::new(code.into()),
             nonce
         }
     }
@@ -75,7 +80,7 @@ impl JSONBlock {
 
         AccountCommitment::Code {
             address,
-            code: Rc::new(code.clone()),
+            code: Arc::new(code.clone()),
         }
     }
 
@@ -268,8 +273,8 @@ pub fn create_context(v: &Value) -> Context {
     Context {
         address,
         caller,
-        code: Rc::new(code),
-        data: Rc::new(data),
+        code: Arc::new(code),
+        data: Arc::new(data),
         gas_limit,
         gas_price,
         origin,
@@ -279,3 +284,369 @@ pub fn create_context(v: &Value) -> Context {
         is_static: false,
     }
 }
+
+/// Rewards of a fork as used in the test suite, where each fork is
+/// active from the genesis block.
+pub struct ForkRewardPatch<F: Fork>(::std::marker::PhantomData<F>);
+
+pub trait Fork {
+    fn block_reward() -> U256;
+    fn has_receipt_status() -> bool;
+    fn difficulty_rule() -> DifficultyRule;
+}
+
+/// How the difficulty of a block follows from its parent.
+pub enum DifficultyRule {
+    /// Raise the difficulty if the block came within 13 seconds.
+    Frontier,
+    /// EIP-2: adjust the difficulty by `max(1 - elapsed / 10, -99)`
+    /// steps.
+    Homestead,
+    /// EIP-100: also account for the parent's ommers, with the
+    /// difficulty bomb delayed by the given number of blocks.
+    Byzantium(U256),
+}
+
+pub struct FrontierFork;
+impl Fork for FrontierFork {
+    fn block_reward() -> U256 { U256::from(5_000_000_000_000_000_000u64) }
+    fn has_receipt_status() -> bool { false }
+    fn difficulty_rule() -> DifficultyRule { DifficultyRule::Frontier }
+}
+
+pub struct HomesteadFork;
+impl Fork for HomesteadFork {
+    fn block_reward() -> U256 { U256::from(5_000_000_000_000_000_000u64) }
+    fn has_receipt_status() -> bool { false }
+    fn difficulty_rule() -> DifficultyRule { DifficultyRule::Homestead }
+}
+
+pub struct ByzantiumFork;
+impl Fork for ByzantiumFork {
+    fn block_reward() -> U256 { U256::from(3_000_000_000_000_000_000u64) }
+    fn has_receipt_status() -> bool { true }
+    fn difficulty_rule() -> DifficultyRule { DifficultyRule::Byzantium(U256::from(3_000_000u64)) }
+}
+
+pub struct ConstantinopleFork;
+impl Fork for ConstantinopleFork {
+    fn block_reward() -> U256 { U256::from(2_000_000_000_000_000_000u64) }
+    fn has_receipt_status() -> bool { true }
+    fn difficulty_rule() -> DifficultyRule { DifficultyRule::Byzantium(U256::from(5_000_000u64)) }
+}
+
+impl<F: Fork> RewardPatch for ForkRewardPatch<F> {
+    fn block_reward(_number: U256) -> U256 {
+        F::block_reward()
+    }
+
+    fn uncle_reward(number: U256, uncle_number: U256) -> U256 {
+        if uncle_number + U256::from(8u64) < number {
+            return U256::zero();
+        }
+        (uncle_number + U256::from(8u64) - number) * F::block_reward() / U256::from(8u64)
+    }
+
+    fn has_receipt_status(_number: U256) -> bool {
+        F::has_receipt_status()
+    }
+}
+
+fn header_hash(header: &Header) -> H256 {
+    H256::from(Keccak256::digest(&rlp::encode(header)[..]).as_slice())
+}
+
+const MINIMUM_DIFFICULTY: u64 = 131_072;
+const DIFFICULTY_BOUND_DIVISOR: u64 = 2048;
+const EXPONENTIAL_PERIOD: u64 = 100_000;
+const MINIMUM_GAS_LIMIT: u64 = 5000;
+const GAS_LIMIT_BOUND_DIVISOR: u64 = 1024;
+const MAXIMUM_EXTRA_DATA: usize = 32;
+const MAXIMUM_OMMERS: usize = 2;
+/// Number of ancestors an ommer's parent may be found in.
+const OMMER_GENERATIONS: usize = 7;
+
+/// Hash of the RLP of an empty list, the ommers hash of a block
+/// without ommers.
+fn empty_list_hash() -> H256 {
+    H256::from(Keccak256::digest(&[0xc0]).as_slice())
+}
+
+fn transactions_root(block: &Block) -> H256 {
+    let database = MemoryDatabase::new();
+    let mut trie = database.create_empty();
+
+    for (i, transaction) in block.transactions.iter().enumerate() {
+        trie.insert_raw(rlp::encode(&U256::from(i)).to_vec(), rlp::encode(transaction).to_vec());
+    }
+
+    trie.root()
+}
+
+/// Length of the extra data field as encoded in the header.
+fn extra_data_len(header: &Header) -> usize {
+    let encoded = rlp::encode(header);
+    UntrustedRlp::new(&encoded).at(12).and_then(|rlp| rlp.data().map(|data| data.len())).unwrap_or(0)
+}
+
+/// Difficulty of a block with the given header on top of `parent`.
+fn difficulty<F: Fork>(parent: &Header, header: &Header) -> U256 {
+    let step = parent.difficulty / U256::from(DIFFICULTY_BOUND_DIVISOR);
+    let elapsed = header.timestamp - parent.timestamp;
+
+    let (difficulty, number) = match F::difficulty_rule() {
+        DifficultyRule::Frontier => {
+            let difficulty = if elapsed < 13 { parent.difficulty + step } else { parent.difficulty - step };
+            (difficulty, header.number)
+        },
+        DifficultyRule::Homestead => {
+            // EIP-2: max(1 - elapsed / 10, -99)
+            let periods = elapsed / 10;
+            let difficulty = if periods <= 1 {
+                parent.difficulty + step * U256::from(1 - periods)
+            } else {
+                parent.difficulty - step * U256::from(min(periods - 1, 99))
+            };
+            (difficulty, header.number)
+        },
+        DifficultyRule::Byzantium(delay) => {
+            // EIP-100: max((2 if the parent has ommers else 1) - elapsed / 9, -99)
+            let target = if parent.ommers_hash == empty_list_hash() { 1 } else { 2 };
+            let periods = elapsed / 9;
+            let difficulty = if periods <= target {
+                parent.difficulty + step * U256::from(target - periods)
+            } else {
+                parent.difficulty - step * U256::from(min(periods - target, 99))
+            };
+            let number = if header.number > delay { header.number - delay } else { U256::zero() };
+            (difficulty, number)
+        },
+    };
+
+    let difficulty = max(difficulty, U256::from(MINIMUM_DIFFICULTY));
+    let period = (number / U256::from(EXPONENTIAL_PERIOD)).as_usize();
+    if period > 1 {
+        difficulty + (U256::one() << (period - 2))
+    } else {
+        difficulty
+    }
+}
+
+/// Check a header, of a block or an ommer, against its parent.
+fn validate_header<F: Fork>(parent: &Header, header: &Header) -> Result<(), String> {
+    if header.parent_hash != header_hash(parent) {
+        return Err("parent hash mismatch".to_string());
+    }
+    if header.number != parent.number + U256::from(1u64) {
+        return Err("block number mismatch".to_string());
+    }
+    if header.timestamp <= parent.timestamp {
+        return Err("timestamp is not after the parent".to_string());
+    }
+    if extra_data_len(header) > MAXIMUM_EXTRA_DATA {
+        return Err("extra data is too long".to_string());
+    }
+    if header.gas_used > header.gas_limit {
+        return Err("gas used exceeds gas limit".to_string());
+    }
+
+    let bound = parent.gas_limit / Gas::from(GAS_LIMIT_BOUND_DIVISOR);
+    let change = if header.gas_limit > parent.gas_limit {
+        header.gas_limit - parent.gas_limit
+    } else {
+        parent.gas_limit - header.gas_limit
+    };
+    if change >= bound || header.gas_limit < Gas::from(MINIMUM_GAS_LIMIT) {
+        return Err("gas limit is out of bounds".to_string());
+    }
+
+    if header.difficulty != difficulty::<F>(parent, header) {
+        return Err("difficulty mismatch".to_string());
+    }
+
+    Ok(())
+}
+
+/// Headers of the imported blocks, and hashes of the ommers they
+/// included.
+struct Chain {
+    headers: Vec<Header>,
+    ommers: HashSet<H256>,
+}
+
+/// Check the ommers of a block: at most two, all distinct, not
+/// already included, and each a valid header on top of one of the
+/// recent ancestors other than the block's parent.
+fn validate_ommers<F: Fork>(chain: &Chain, block: &Block) -> Result<(), String> {
+    if block.ommers.len() > MAXIMUM_OMMERS {
+        return Err("too many ommers".to_string());
+    }
+
+    let ancestors: Vec<&Header> = chain.headers.iter().rev().take(OMMER_GENERATIONS).collect();
+    let ancestor_hashes: Vec<H256> = ancestors.iter().map(|ancestor| header_hash(ancestor)).collect();
+    let mut seen = HashSet::new();
+
+    for ommer in &block.ommers {
+        let hash = header_hash(ommer);
+        if !seen.insert(hash) || chain.ommers.contains(&hash) {
+            return Err("duplicate ommer".to_string());
+        }
+        if chain.headers.iter().any(|header| header_hash(header) == hash) {
+            return Err("ommer is an ancestor".to_string());
+        }
+        if ommer.parent_hash == block.header.parent_hash {
+            return Err("ommer is a sibling".to_string());
+        }
+        let parent = ancestor_hashes.iter().position(|ancestor| *ancestor == ommer.parent_hash)
+            .map(|i| ancestors[i])
+            .ok_or("ommer parent is not a recent ancestor".to_string())?;
+        validate_header::<F>(parent, ommer).map_err(|err| format!("invalid ommer: {}", err))?;
+    }
+
+    Ok(())
+}
+
+/// Import a block on top of the chain, returning the reason if the
+/// block is rejected.
+fn import_block<P: Patch, F: Fork>(
+    stateful: &mut MemoryStateful, chain: &mut Chain, block: &Block
+) -> Result<(), String> {
+    let header = &block.header;
+    validate_header::<F>(chain.headers.last().unwrap(), header)?;
+
+    if header.transactions_root != transactions_root(block) {
+        return Err("transactions root mismatch".to_string());
+    }
+    let ommers_hash = H256::from(Keccak256::digest(&rlp::encode_list(&block.ommers)[..]).as_slice());
+    if header.ommers_hash != ommers_hash {
+        return Err("ommers hash mismatch".to_string());
+    }
+    validate_ommers::<F>(chain, block)?;
+
+    let count = min(header.number, U256::from(256u64)).as_usize();
+    let mut hashes = vec![H256::default()];
+    for i in 1..(count + 1) {
+        hashes.push(header_hash(&chain.headers[chain.headers.len() - i]));
+    }
+
+    let mut parallel = MemoryStateful::new(stateful.database(), stateful.root());
+    let expected = stateful.execute_block::<SeqMemory<P>, P, ForkRewardPatch<F>>(block, &hashes, None)
+        .map_err(|err| format!("{:?}", err))?;
+
+    let (result, _) = parallel.apply_block_parallel::<SeqMemory<P>, P, ForkRewardPatch<F>>(block, &hashes, 4, None)
+        .map_err(|err| format!("parallel execution failed: {:?}", err))?;
+    if result.state_root != expected.state_root || result.receipts_root != expected.receipts_root ||
+        result.gas_used != expected.gas_used
+    {
+        return Err("parallel execution result mismatch".to_string());
+    }
+
+    chain.headers.push(header.clone());
+    chain.ommers.extend(block.ommers.iter().map(header_hash));
+    Ok(())
+}
+
+fn run_blockchain<P: Patch, F: Fork>(name: &str, v: &Value, debug: bool) -> bool {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    pre_state(&mut stateful, &v["pre"]);
+
+    let genesis_rlp = read_hex(v["genesisRLP"].as_str().unwrap()).unwrap();
+    let genesis: Block = UntrustedRlp::new(&genesis_rlp).as_val().unwrap();
+    if genesis.header.state_root != stateful.root() {
+        if debug {
+            println!();
+            println!("{}: genesis state root check failed", name);
+            println!("Expected: 0x{:x}", genesis.header.state_root);
+            println!("Actual:   0x{:x}", stateful.root());
+        }
+        return false;
+    }
+    let mut chain = Chain {
+        headers: vec![genesis.header],
+        ommers: HashSet::new(),
+    };
+
+    for (i, data) in v["blocks"].as_array().unwrap().iter().enumerate() {
+        let expect_invalid = data["blockHeader"].is_null() ||
+            data.as_object().unwrap().keys().any(|key| key.starts_with("expectException"));
+
+        let result = read_hex(data["rlp"].as_str().unwrap()).map_err(|err| format!("{:?}", err))
+            .and_then(|value| UntrustedRlp::new(&value).as_val::<Block>()
+                      .map_err(|err| format!("{:?}", err)))
+            .and_then(|block| import_block::<P, F>(&mut stateful, &mut chain, &block));
+
+        match (result, expect_invalid) {
+            (Ok(()), true) => {
+                if debug {
+                    println!();
+                    println!("{}: block {} is invalid but was imported", name, i);
+                }
+                return false;
+            },
+            (Err(err), false) => {
+                if debug {
+                    println!();
+                    println!("{}: block {} was rejected: {}", name, i, err);
+                }
+                return false;
+            },
+            _ => (),
+        }
+    }
+
+    let expected_root = if let Some(hash) = v["postStateHash"].as_str() {
+        H256::from_str(hash).unwrap()
+    } else {
+        let database = MemoryDatabase::default();
+        let mut expected = MemoryStateful::empty(&database);
+        pre_state(&mut expected, &v["postState"]);
+        expected.root()
+    };
+    if expected_root != stateful.root() {
+        if debug {
+            println!();
+            println!("{}: post state root check failed", name);
+            println!("Expected: 0x{:x}", expected_root);
+            println!("Actual:   0x{:x}", stateful.root());
+        }
+        return false;
+    }
+
+    if let Some(hash) = v["lastblockhash"].as_str() {
+        let expected = H256::from_str(hash).unwrap();
+        let actual = header_hash(chain.headers.last().unwrap());
+        if expected != actual {
+            if debug {
+                println!();
+                println!("{}: last block hash check failed", name);
+                println!("Expected: 0x{:x}", expected);
+                println!("Actual:   0x{:x}", actual);
+            }
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Run a blockchain test if it targets `fork`.
+pub fn test_blockchain<P: Patch>(name: &str, fork: &str, v: &Value, debug: bool) -> bool {
+    if v["network"].as_str().map(|network| network != fork).unwrap_or(false) {
+        return true;
+    }
+
+    match fork {
+        "Frontier" => run_blockchain::<P, FrontierFork>(name, v, debug),
+        "Homestead" | "EIP150" | "EIP158" => run_blockchain::<P, HomesteadFork>(name, v, debug),
+        "Byzantium" => run_blockchain::<P, ByzantiumFork>(name, v, debug),
+        "Constantinople" => run_blockchain::<P, ConstantinopleFork>(name, v, debug),
+        _ => {
+            if debug {
+                println!();
+                println!("{}: unknown fork {}", name, fork);
+            }
+            false
+        },
+    }
+}
@@ -0,0 +1,287 @@
+//! Filler generating `GeneralStateTests` fixtures from compact specs.
+//!
+//! A spec has the shape of a state test filler: `env`, `pre` and
+//! `transaction` as in the fixture, except that `data`, `gasLimit`
+//! and `value` may be single values, and numbers may be integers,
+//! decimal strings or hex strings. Conditions on the post-state are
+//! listed in `expect`, each with optional `indexes` and `network`
+//! selecting the executions it applies to, and a `result` mapping
+//! addresses to the expected `balance`, `nonce`, `code`, `storage`,
+//! or `shouldnotexist`.
+
+use bigint::{M256, U256, H256, Address};
+use hexutil::read_hex;
+use sputnikvm::Patch;
+use sputnikvm_stateful::MemoryStateful;
+use trie::MemoryDatabase;
+use serde_json::{Value, Map};
+use std::str::FromStr;
+use state::{pre_state, apply_transaction};
+
+#[derive(Debug, Clone)]
+/// Errors when filling a spec.
+pub enum FillError {
+    /// A field of the spec is missing or invalid.
+    InvalidSpec(String),
+    /// An expectation does not hold for the execution selected by the
+    /// data, gas and value indexes.
+    ExpectationFailed {
+        /// Indexes of data, gas limit and value.
+        indexes: (usize, usize, usize),
+        /// Account checked.
+        address: Address,
+        /// Description of the mismatch.
+        reason: String,
+    },
+}
+
+fn invalid(field: &str) -> FillError {
+    FillError::InvalidSpec(field.to_string())
+}
+
+fn read_number(value: &Value, field: &str) -> Result<U256, FillError> {
+    match *value {
+        Value::Number(ref number) => number.as_u64().map(U256::from).ok_or_else(|| invalid(field)),
+        Value::String(ref string) => {
+            let string = string.trim();
+            if string.starts_with("0x") {
+                U256::from_str(string).map_err(|_| invalid(field))
+            } else {
+                U256::from_dec_str(string).map_err(|_| invalid(field))
+            }
+        },
+        _ => Err(invalid(field)),
+    }
+}
+
+fn number(value: &Value, field: &str) -> Result<Value, FillError> {
+    Ok(Value::String(format!("0x{:x}", read_number(value, field)?)))
+}
+
+fn read_bytes(value: &Value, field: &str) -> Result<Vec<u8>, FillError> {
+    let string = value.as_str().ok_or_else(|| invalid(field))?.trim();
+    if string.is_empty() || string == "0x" {
+        return Ok(Vec::new());
+    }
+    let string = if string.starts_with("0x") { string.to_string() } else { format!("0x{}", string) };
+    read_hex(&string).map_err(|_| invalid(field))
+}
+
+fn bytes(value: &Value, field: &str) -> Result<Value, FillError> {
+    let mut ret = String::from("0x");
+    for byte in read_bytes(value, field)? {
+        ret.push_str(&format!("{:02x}", byte));
+    }
+    Ok(Value::String(ret))
+}
+
+fn read_address(value: &str, field: &str) -> Result<Address, FillError> {
+    let value = if value.starts_with("0x") { value.to_string() } else { format!("0x{}", value) };
+    Address::from_str(&value).map_err(|_| invalid(field))
+}
+
+fn address(value: &Value, field: &str) -> Result<Value, FillError> {
+    let value = value.as_str().ok_or_else(|| invalid(field))?;
+    Ok(Value::String(format!("0x{:x}", read_address(value, field)?)))
+}
+
+/// Values of a field that may be either a single value or a list.
+fn list(value: &Value) -> Vec<Value> {
+    match *value {
+        Value::Array(ref values) => values.clone(),
+        Value::Null => Vec::new(),
+        ref value => vec![value.clone()],
+    }
+}
+
+fn normalize_env(env: &Value) -> Result<Value, FillError> {
+    Ok(json!({
+        "currentCoinbase": address(&env["currentCoinbase"], "env.currentCoinbase")?,
+        "currentDifficulty": number(&env["currentDifficulty"], "env.currentDifficulty")?,
+        "currentGasLimit": number(&env["currentGasLimit"], "env.currentGasLimit")?,
+        "currentNumber": number(&env["currentNumber"], "env.currentNumber")?,
+        "currentTimestamp": number(&env["currentTimestamp"], "env.currentTimestamp")?,
+    }))
+}
+
+fn normalize_pre(pre: &Value) -> Result<Value, FillError> {
+    let mut ret = Map::new();
+    for (key, account) in pre.as_object().ok_or_else(|| invalid("pre"))? {
+        let field = format!("pre.{}", key);
+        let mut storage = Map::new();
+        if let Some(entries) = account["storage"].as_object() {
+            for (index, value) in entries {
+                let index = read_number(&Value::String(index.clone()), &field)?;
+                storage.insert(format!("0x{:x}", index), number(value, &field)?);
+            }
+        }
+
+        let address = format!("0x{:x}", read_address(key, &field)?);
+        ret.insert(address, json!({
+            "balance": number(&account["balance"], &field)?,
+            "nonce": number(&account["nonce"], &field)?,
+            "code": bytes(&account["code"], &field)?,
+            "storage": storage,
+        }));
+    }
+    Ok(Value::Object(ret))
+}
+
+fn normalize_transaction(transaction: &Value) -> Result<Value, FillError> {
+    let mut ret = json!({
+        "data": list(&transaction["data"]).iter()
+            .map(|data| bytes(data, "transaction.data")).collect::<Result<Vec<_>, _>>()?,
+        "gasLimit": list(&transaction["gasLimit"]).iter()
+            .map(|gas| number(gas, "transaction.gasLimit")).collect::<Result<Vec<_>, _>>()?,
+        "value": list(&transaction["value"]).iter()
+            .map(|value| number(value, "transaction.value")).collect::<Result<Vec<_>, _>>()?,
+        "gasPrice": number(&transaction["gasPrice"], "transaction.gasPrice")?,
+        "nonce": number(&transaction["nonce"], "transaction.nonce")?,
+        "to": match transaction["to"].as_str() {
+            None | Some("") => Value::String(String::new()),
+            Some(to) => Value::String(format!("0x{:x}", read_address(to, "transaction.to")?)),
+        },
+    });
+
+    for field in &["data", "gasLimit", "value"] {
+        if ret[*field].as_array().unwrap().is_empty() {
+            return Err(invalid(&format!("transaction.{}", field)));
+        }
+    }
+
+    if !transaction["sender"].is_null() {
+        ret["sender"] = address(&transaction["sender"], "transaction.sender")?;
+    } else if !transaction["secretKey"].is_null() {
+        ret["secretKey"] = bytes(&transaction["secretKey"], "transaction.secretKey")?;
+    } else {
+        return Err(invalid("transaction.sender"));
+    }
+
+    Ok(ret)
+}
+
+/// Whether the `indexes` selector of an expectation matches `index`.
+/// A missing selector or `-1` matches all.
+fn index_matches(selector: &Value, index: usize) -> bool {
+    match *selector {
+        Value::Null => true,
+        Value::Number(ref number) => number.as_i64() == Some(-1) || number.as_u64() == Some(index as u64),
+        Value::Array(ref items) => items.iter().any(|item| index_matches(item, index)),
+        _ => false,
+    }
+}
+
+fn expectation_applies(expect: &Value, fork: &str, indexes: (usize, usize, usize)) -> bool {
+    let network = list(&expect["network"]);
+    let network_matches = network.is_empty() ||
+        network.iter().any(|network| network.as_str() == Some(fork));
+
+    network_matches &&
+        index_matches(&expect["indexes"]["data"], indexes.0) &&
+        index_matches(&expect["indexes"]["gas"], indexes.1) &&
+        index_matches(&expect["indexes"]["value"], indexes.2)
+}
+
+fn check_account(
+    stateful: &MemoryStateful, indexes: (usize, usize, usize), key: &str, expected: &Value
+) -> Result<(), FillError> {
+    let field = format!("expect.result.{}", key);
+    let address = read_address(key, &field)?;
+    let fail = |reason: String| FillError::ExpectationFailed { indexes, address, reason };
+
+    let account = stateful.state().get(&address);
+    if expected["shouldnotexist"].as_u64() == Some(1) || expected["shouldnotexist"].as_bool() == Some(true) {
+        return match account {
+            Some(_) => Err(fail("account exists".to_string())),
+            None => Ok(()),
+        };
+    }
+    let account = account.ok_or_else(|| fail("account does not exist".to_string()))?;
+
+    if !expected["balance"].is_null() {
+        let balance = read_number(&expected["balance"], &field)?;
+        if account.balance != balance {
+            return Err(fail(format!("balance is 0x{:x}, expected 0x{:x}", account.balance, balance)));
+        }
+    }
+    if !expected["nonce"].is_null() {
+        let nonce = read_number(&expected["nonce"], &field)?;
+        if account.nonce != nonce {
+            return Err(fail(format!("nonce is 0x{:x}, expected 0x{:x}", account.nonce, nonce)));
+        }
+    }
+    if !expected["code"].is_null() {
+        let code = read_bytes(&expected["code"], &field)?;
+        if stateful.code(account.code_hash).unwrap_or_default() != code {
+            return Err(fail("code differs".to_string()));
+        }
+    }
+    if let Some(storage) = expected["storage"].as_object() {
+        let trie = stateful.storage_state_of(account.storage_root);
+        for (index, value) in storage {
+            let index = read_number(&Value::String(index.clone()), &field)?;
+            let value = M256::from(read_number(value, &field)?);
+            let actual = trie.get(&H256::from(index)).unwrap_or_else(M256::zero);
+            if actual != value {
+                let (actual, value): (U256, U256) = (actual.into(), value.into());
+                return Err(fail(format!("storage 0x{:x} is 0x{:x}, expected 0x{:x}",
+                                        index, actual, value)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fill a state test from the spec for `fork`, executing it with the
+/// patch `P`. Returns the test with the post-state of every
+/// combination of data, gas limit and value under `post.<fork>`.
+pub fn fill<P: Patch>(fork: &str, spec: &Value) -> Result<Value, FillError> {
+    let mut test = json!({
+        "env": normalize_env(&spec["env"])?,
+        "pre": normalize_pre(&spec["pre"])?,
+        "transaction": normalize_transaction(&spec["transaction"])?,
+    });
+    let expects = list(&spec["expect"]);
+
+    let mut posts = Vec::new();
+    {
+        let transaction = &test["transaction"];
+        let count = |field: &str| transaction[field].as_array().unwrap().len();
+
+        for data in 0..count("data") {
+            for gas in 0..count("gasLimit") {
+                for value in 0..count("value") {
+                    let indexes = json!({ "data": data, "gas": gas, "value": value });
+
+                    let database = MemoryDatabase::default();
+                    let mut stateful = MemoryStateful::empty(&database);
+                    pre_state(&mut stateful, &test["pre"]);
+                    let logs = apply_transaction::<P>(&mut stateful, &test, &indexes, false);
+
+                    for expect in &expects {
+                        if !expectation_applies(expect, fork, (data, gas, value)) {
+                            continue;
+                        }
+                        let result = expect["result"].as_object()
+                            .ok_or_else(|| invalid("expect.result"))?;
+                        for (key, expected) in result {
+                            check_account(&stateful, (data, gas, value), key, expected)?;
+                        }
+                    }
+
+                    posts.push(json!({
+                        "hash": format!("0x{:x}", stateful.root()),
+                        "logs": format!("0x{:x}", logs),
+                        "indexes": indexes,
+                    }));
+                }
+            }
+        }
+    }
+
+    let mut post = Map::new();
+    post.insert(fork.to_string(), Value::Array(posts));
+    test["post"] = Value::Object(post);
+    Ok(test)
+}
@@ -1,4 +1,5 @@
 extern crate sputnikvm;
+#[macro_use]
 extern crate serde_json;
 extern crate hexutil;
 extern crate bigint;
@@ -6,20 +7,32 @@ extern crate env_logger;
 extern crate sha3;
 extern crate rlp;
 extern crate criterion;
+extern crate sputnikvm_stateful;
+extern crate trie;
+extern crate block;
+extern crate secp256k1;
+extern crate sputnikvm_network_foundation;
 
 mod blockchain;
+mod state;
+mod transaction;
+mod filler;
 pub mod util;
 
-pub use self::blockchain::{JSONBlock, create_block, create_context};
+pub use self::blockchain::{JSONBlock, create_block, create_context, test_blockchain};
+pub use self::state::test_state;
+pub use self::transaction::test_signed_transaction;
+pub use self::filler::{fill, FillError};
 
 use serde_json::Value;
 use std::str::FromStr;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
 use bigint::{Gas, M256, U256, H256, Address};
 use hexutil::*;
 use sputnikvm::errors::RequireError;
-use sputnikvm::{VM, SeqContextVM, Context, VMStatus, Patch};
+use sputnikvm::{VM, SeqContextVM, Context, VMStatus, Patch, ExecutionMode, AccountChange};
 
 pub fn fire_with_block<P: Patch>(machine: &mut SeqContextVM<P>, block: &JSONBlock) {
     loop {
@@ -256,6 +269,31 @@ pub fn test_machine<P: Patch>(v: &Value, machine: &SeqContextVM<P>, block: &JSON
     true
 }
 
+/// Run the test again with per basic block checks, and check that it
+/// gives the same results.
+fn test_basic_block_mode<P: Patch>(v: &Value, machine: &SeqContextVM<P>) {
+    let block = create_block(v);
+    let mut block_machine = create_machine::<P>(v, &block);
+    block_machine.set_execution_mode(ExecutionMode::BasicBlock);
+    fire_with_block(&mut block_machine, &block);
+    assert_same_result(machine, &block_machine);
+}
+
+fn account_changes<V: VM>(vm: &V) -> HashMap<Address, AccountChange> {
+    vm.accounts().map(|account| (account.address(), account.clone())).collect()
+}
+
+/// Assert that two VMs running the same code, such as in different
+/// execution modes, exited the same way with the same changes.
+pub fn assert_same_result<A: VM, B: VM>(expected: &A, actual: &B) {
+    assert_eq!(actual.status(), expected.status());
+    assert_eq!(actual.used_gas(), expected.used_gas());
+    assert_eq!(actual.refunded_gas(), expected.refunded_gas());
+    assert_eq!(actual.out(), expected.out());
+    assert_eq!(actual.logs(), expected.logs());
+    assert_eq!(account_changes(actual), account_changes(expected));
+}
+
 fn is_ok(status: &VMStatus) -> bool {
     match *status {
         VMStatus::ExitedOk => true,
@@ -274,6 +312,7 @@ pub fn test_transaction<P: Patch>(_name: &str, v: &Value, debug: bool) -> Result
         history_closure.lock().unwrap().push(context.clone());
     });
     fire_with_block(&mut machine, &block);
+    test_basic_block_mode(v, &machine);
     apply_to_block(&machine, &mut block);
 
     if debug {
@@ -0,0 +1,199 @@
+//! Runner for the `GeneralStateTests` format.
+
+use bigint::{Gas, M256, U256, H256, Address};
+use hexutil::*;
+use sputnikvm::{VM, SeqMemory, SeqTransactionVM, HeaderParams, Patch, AccountCommitment,
+                UntrustedTransaction, TransactionAction, Log, VMStatus, ExecutionMode};
+use sputnikvm::errors::RequireError;
+use sputnikvm_stateful::{MemoryStateful, LiteralAccount};
+use secp256k1::SECP256K1;
+use secp256k1::key::{SecretKey, PublicKey};
+use trie::MemoryDatabase;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::cmp::min;
+use std::sync::Arc;
+use {read_u256, assert_same_result};
+use rlp;
+use sha3::{Digest, Keccak256};
+
+fn sender(transaction: &Value) -> Address {
+    if let Some(sender) = transaction["sender"].as_str() {
+        return Address::from_str(sender).unwrap();
+    }
+
+    let secret = read_hex(transaction["secretKey"].as_str().unwrap()).unwrap();
+    let secret = SecretKey::from_slice(&SECP256K1, &secret).unwrap();
+    let public = PublicKey::from_secret_key(&SECP256K1, &secret).unwrap();
+    let public = public.serialize_vec(&SECP256K1, false);
+
+    Address::from(&Keccak256::digest(&public[1..65])[12..])
+}
+
+fn header(env: &Value) -> HeaderParams {
+    HeaderParams {
+        beneficiary: Address::from_str(env["currentCoinbase"].as_str().unwrap()).unwrap(),
+        timestamp: read_u256(env["currentTimestamp"].as_str().unwrap()).into(),
+        number: read_u256(env["currentNumber"].as_str().unwrap()),
+        difficulty: read_u256(env["currentDifficulty"].as_str().unwrap()),
+        gas_limit: Gas::from(read_u256(env["currentGasLimit"].as_str().unwrap())),
+    }
+}
+
+/// Block hashes as defined by the test suite, where the hash of block
+/// `n` is the Keccak-256 hash of its decimal representation.
+fn block_hashes(number: U256) -> Vec<H256> {
+    let count = min(number, U256::from(256)).as_usize();
+    let mut hashes = vec![H256::default()];
+    for i in 1..(count + 1) {
+        let n = number - U256::from(i);
+        hashes.push(H256::from(Keccak256::digest(format!("{}", n).as_bytes()).as_slice()));
+    }
+    hashes
+}
+
+fn logs_hash(logs: &[Log]) -> H256 {
+    let encoded = rlp::encode_list(logs);
+    H256::from(Keccak256::digest(&encoded[..]).as_slice())
+}
+
+pub fn pre_state(stateful: &mut MemoryStateful, pre: &Value) {
+    let mut accounts = Vec::new();
+
+    for (address, data) in pre.as_object().unwrap() {
+        let address = Address::from_str(address.as_str()).unwrap();
+        let mut storage = HashMap::new();
+        for (index, value) in data["storage"].as_object().unwrap() {
+            storage.insert(read_u256(index.as_str()),
+                           M256::from(read_u256(value.as_str().unwrap())));
+        }
+
+        accounts.push((address, LiteralAccount {
+            nonce: read_u256(data["nonce"].as_str().unwrap()),
+            balance: read_u256(data["balance"].as_str().unwrap()),
+            storage,
+            code: read_hex(data["code"].as_str().unwrap()).unwrap(),
+        }));
+    }
+
+    stateful.sets(&accounts);
+}
+
+/// Execute the transaction selected by `indexes` on top of the state,
+/// and return the logs hash. An invalid transaction leaves the state
+/// unchanged.
+pub fn apply_transaction<P: Patch>(
+    stateful: &mut MemoryStateful, v: &Value, indexes: &Value, debug: bool
+) -> H256 {
+    let transaction = &v["transaction"];
+    let header = header(&v["env"]);
+
+    let data = &transaction["data"][indexes["data"].as_u64().unwrap() as usize];
+    let gas_limit = &transaction["gasLimit"][indexes["gas"].as_u64().unwrap() as usize];
+    let value = &transaction["value"][indexes["value"].as_u64().unwrap() as usize];
+
+    let caller = sender(transaction);
+    let action = match transaction["to"].as_str().unwrap() {
+        "" => TransactionAction::Create,
+        to => TransactionAction::Call(Address::from_str(to).unwrap()),
+    };
+    let untrusted = UntrustedTransaction {
+        caller: stateful.require_to_commit(RequireError::Account(caller), None),
+        gas_price: Gas::from(read_u256(transaction["gasPrice"].as_str().unwrap())),
+        gas_limit: Gas::from(read_u256(gas_limit.as_str().unwrap())),
+        action,
+        value: read_u256(value.as_str().unwrap()),
+        input: Arc::new(read_hex(data.as_str().unwrap()).unwrap()),
+    };
+
+    let nonce = read_u256(transaction["nonce"].as_str().unwrap());
+    let valid = match untrusted.caller {
+        AccountCommitment::Full { nonce: account_nonce, .. } if account_nonce == nonce => {
+            untrusted.to_valid::<P>().ok()
+        },
+        _ => None,
+    };
+    let valid = valid.and_then(|valid| {
+        if valid.gas_limit > header.gas_limit { None } else { Some(valid) }
+    });
+
+    match valid {
+        Some(valid) => {
+            let hashes = block_hashes(header.number);
+            let mut block_vm = SeqTransactionVM::<P>::new(valid.clone(), header.clone());
+            block_vm.set_execution_mode(ExecutionMode::BasicBlock);
+            while block_vm.status() == VMStatus::Running {
+                stateful.step(&mut block_vm, header.number, &hashes);
+            }
+
+            let vm = stateful.execute::<SeqMemory<P>, P>(valid, &header, &hashes);
+            assert_same_result(&vm, &block_vm);
+            if debug {
+                println!("status: {:?}", vm.status());
+            }
+            logs_hash(vm.logs())
+        },
+        None => {
+            if debug {
+                println!("transaction is invalid");
+            }
+            logs_hash(&[])
+        },
+    }
+}
+
+/// Execute the transaction selected by `indexes` on top of the
+/// pre-state, and return the post-state root and logs hash.
+fn execute<P: Patch>(v: &Value, indexes: &Value, debug: bool) -> (H256, H256) {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    pre_state(&mut stateful, &v["pre"]);
+
+    let logs = apply_transaction::<P>(&mut stateful, v, indexes, debug);
+    (stateful.root(), logs)
+}
+
+/// Run all post-state checks of `fork` in a state test.
+pub fn test_state<P: Patch>(name: &str, fork: &str, v: &Value, debug: bool) -> bool {
+    let posts = match v["post"][fork].as_array() {
+        Some(posts) => posts,
+        None => {
+            if debug {
+                println!("{}: no post-state for fork {}", name, fork);
+            }
+            return true;
+        },
+    };
+
+    let mut passed = true;
+    for (i, post) in posts.iter().enumerate() {
+        let (root, logs) = execute::<P>(v, &post["indexes"], debug);
+        let expected_root = H256::from_str(post["hash"].as_str().unwrap()).unwrap();
+
+        if root != expected_root {
+            if debug {
+                println!();
+                println!("{}: state root check failed for post {} of fork {}", name, i, fork);
+                println!("Expected: 0x{:x}", expected_root);
+                println!("Actual:   0x{:x}", root);
+            }
+            passed = false;
+        }
+
+        if let Some(expected_logs) = post["logs"].as_str() {
+            let expected_logs = H256::from_str(expected_logs).unwrap();
+            if logs != expected_logs {
+                if debug {
+                    println!();
+                    println!("{}: logs check failed for post {} of fork {}", name, i, fork);
+                    println!("Expected: 0x{:x}", expected_logs);
+                    println!("Actual:   0x{:x}", logs);
+                }
+                passed = false;
+            }
+        }
+    }
+
+    passed
+}
@@ -0,0 +1,101 @@
+//! Runner for the `TransactionTests` format.
+
+use bigint::{U256, H256, Address};
+use hexutil::*;
+use sputnikvm::{Patch, AccountCommitment, UntrustedTransaction};
+use block::Transaction;
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Arc;
+use rlp::UntrustedRlp;
+use sha3::{Digest, Keccak256};
+
+/// Decode and validate a signed transaction, returning its sender.
+fn validate<P: Patch>(raw: &[u8]) -> Result<Address, String> {
+    let rlp = UntrustedRlp::new(raw);
+    let total = rlp.payload_info().map_err(|err| format!("{:?}", err))?.total();
+    if total != raw.len() {
+        return Err("trailing bytes after transaction".to_string());
+    }
+    let transaction: Transaction = rlp.as_val().map_err(|err| format!("{:?}", err))?;
+    let caller = UntrustedTransaction::caller_of::<P>(&transaction).map_err(|err| format!("{:?}", err))?;
+
+    // The fixtures have no state, so the sender is assumed to hold
+    // enough balance and the expected nonce.
+    let untrusted = UntrustedTransaction {
+        caller: AccountCommitment::Full {
+            nonce: transaction.nonce,
+            address: caller,
+            balance: U256::max_value(),
+            code: Arc::new(Vec::new()),
+        },
+        gas_price: transaction.gas_price,
+        gas_limit: transaction.gas_limit,
+        action: transaction.action,
+        value: transaction.value,
+        input: Arc::new(transaction.input.clone()),
+    };
+    untrusted.to_valid::<P>().map_err(|err| format!("{:?}", err))?;
+
+    Ok(caller)
+}
+
+/// Run a transaction test for `fork`.
+pub fn test_signed_transaction<P: Patch>(name: &str, fork: &str, v: &Value, debug: bool) -> bool {
+    let expected = if v["result"].is_object() { &v["result"][fork] } else { &v[fork] };
+    if expected.is_null() {
+        if debug {
+            println!("{}: no result for fork {}", name, fork);
+        }
+        return true;
+    }
+
+    let raw = read_hex(v["rlp"].as_str().unwrap()).map_err(|err| format!("{:?}", err));
+    let result = raw.clone().and_then(|raw| validate::<P>(&raw));
+
+    match (expected["sender"].as_str(), result) {
+        (Some(sender), Ok(caller)) => {
+            let sender = Address::from_str(sender).unwrap();
+            if sender != caller {
+                if debug {
+                    println!();
+                    println!("{}: sender check failed", name);
+                    println!("Expected: 0x{:x}", sender);
+                    println!("Actual:   0x{:x}", caller);
+                }
+                return false;
+            }
+
+            if let Some(hash) = expected["hash"].as_str() {
+                let hash = H256::from_str(hash).unwrap();
+                let actual = H256::from(Keccak256::digest(&raw.unwrap()).as_slice());
+                if hash != actual {
+                    if debug {
+                        println!();
+                        println!("{}: hash check failed", name);
+                        println!("Expected: 0x{:x}", hash);
+                        println!("Actual:   0x{:x}", actual);
+                    }
+                    return false;
+                }
+            }
+
+            true
+        },
+        (Some(_), Err(err)) => {
+            if debug {
+                println!();
+                println!("{}: valid transaction was rejected: {}", name, err);
+            }
+            false
+        },
+        (None, Ok(caller)) => {
+            if debug {
+                println!();
+                println!("{}: invalid transaction was accepted with sender 0x{:x}", name, caller);
+            }
+            false
+        },
+        (None, Err(_)) => true,
+    }
+}
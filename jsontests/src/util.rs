@@ -1,14 +1,56 @@
 use serde_json::Value;
 use serde_json as json;
 use test_transaction;
+use test_state;
+use test_blockchain;
+use test_signed_transaction;
 use bench_transaction;
 use sputnikvm::Patch;
+use sputnikvm_network_foundation::{FrontierPatch, HomesteadPatch, EIP150Patch,
+                                   SpuriousDragonPatch, ByzantiumPatch};
+
+/// A function generic over the patch, called by `with_fork_patch`
+/// with the patch of a fork.
+pub trait ForkFn {
+    /// Result of the function.
+    type Output;
+    /// Call the function with the patch.
+    fn call<P: Patch>(self) -> Self::Output;
+}
+
+/// Call the function with the patch of the fork, as named in the
+/// fixtures. Returns `None` if no patch is known for the fork.
+pub fn with_fork_patch<F: ForkFn>(fork: &str, f: F) -> Option<F::Output> {
+    match fork {
+        "Frontier" => Some(f.call::<FrontierPatch>()),
+        "Homestead" => Some(f.call::<HomesteadPatch>()),
+        "EIP150" => Some(f.call::<EIP150Patch>()),
+        "EIP158" => Some(f.call::<SpuriousDragonPatch>()),
+        "Byzantium" => Some(f.call::<ByzantiumPatch>()),
+        _ => None,
+    }
+}
 
 pub fn run_test<P: Patch>(name: &str, test: &str) {
     let test: Value = json::from_str(test).unwrap();
     assert_eq!(test_transaction::<P>(name, &test, true), Ok(true));
 }
 
+pub fn run_state_test<P: Patch>(name: &str, fork: &str, test: &str) {
+    let test: Value = json::from_str(test).unwrap();
+    assert!(test_state::<P>(name, fork, &test, true));
+}
+
+pub fn run_blockchain_test<P: Patch>(name: &str, fork: &str, test: &str) {
+    let test: Value = json::from_str(test).unwrap();
+    assert!(test_blockchain::<P>(name, fork, &test, true));
+}
+
+pub fn run_transaction_test<P: Patch>(name: &str, fork: &str, test: &str) {
+    let test: Value = json::from_str(test).unwrap();
+    assert!(test_signed_transaction::<P>(name, fork, &test, true));
+}
+
 use criterion::Criterion;
 
 pub fn run_bench<P: Patch>(c: &mut Criterion, name: &'static str, test: &str) {
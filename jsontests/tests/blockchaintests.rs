@@ -0,0 +1,26 @@
+#![allow(non_snake_case)]
+#![allow(unused)]
+
+#[macro_use]
+extern crate jsontests_derive;
+extern crate jsontests;
+extern crate sputnikvm;
+extern crate sputnikvm_network_foundation;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/BlockchainTests/bcValidBlockTest"]
+#[test_with = "jsontests::util::run_blockchain_test"]
+#[fork = "Byzantium"]
+struct ValidBlock;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/BlockchainTests/bcStateTests"]
+#[test_with = "jsontests::util::run_blockchain_test"]
+#[fork = "Byzantium"]
+struct State;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/BlockchainTests/bcInvalidHeaderTest"]
+#[test_with = "jsontests::util::run_blockchain_test"]
+#[fork = "Byzantium"]
+struct InvalidHeader;
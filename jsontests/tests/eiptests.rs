@@ -68,6 +68,9 @@ impl Patch for EIP1283Patch {
     fn err_on_call_with_more_gas() -> bool { true }
     fn call_create_l64_after_gas() -> bool { false }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { false }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { None }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &EMBEDDED_PRECOMPILEDS }
 }
@@ -0,0 +1,50 @@
+#![allow(non_snake_case)]
+#![allow(unused)]
+
+#[macro_use]
+extern crate jsontests_derive;
+extern crate jsontests;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate sputnikvm;
+extern crate sputnikvm_network_foundation;
+
+use jsontests::{fill, test_state, FillError};
+use serde_json::Value;
+use sputnikvm_network_foundation::ByzantiumPatch;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/FilledStateTests"]
+#[test_with = "jsontests::util::run_state_test"]
+#[fork = "Byzantium"]
+struct Filled;
+
+fn spec() -> Value {
+    let specs: Value = serde_yaml::from_str(include_str!("../res/fillers/sstoreFiller.yml")).unwrap();
+    specs["sstoreCallValue"].clone()
+}
+
+#[test]
+fn filled_test_passes() {
+    let test = fill::<ByzantiumPatch>("Byzantium", &spec()).unwrap();
+    assert_eq!(test["post"]["Byzantium"].as_array().unwrap().len(), 4);
+    assert!(test_state::<ByzantiumPatch>("sstoreCallValue", "Byzantium", &test, true));
+}
+
+#[test]
+fn filled_fixture_is_up_to_date() {
+    let fixture: Value = serde_json::from_str(include_str!("../res/files/FilledStateTests/sstore.json")).unwrap();
+    assert_eq!(fill::<ByzantiumPatch>("Byzantium", &spec()).unwrap(), fixture["sstoreCallValue"]);
+}
+
+#[test]
+fn failed_expectation_is_reported() {
+    let mut spec = spec();
+    spec["expect"][0]["result"]["0x1000000000000000000000000000000000000000"]["balance"] =
+        Value::from(8);
+
+    match fill::<ByzantiumPatch>("Byzantium", &spec) {
+        Err(FillError::ExpectationFailed { indexes: (0, 0, 1), .. }) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
@@ -0,0 +1,32 @@
+#![allow(non_snake_case)]
+#![allow(unused)]
+
+#[macro_use]
+extern crate jsontests_derive;
+extern crate jsontests;
+extern crate sputnikvm;
+extern crate sputnikvm_network_foundation;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/GeneralStateTests/stExample"]
+#[test_with = "jsontests::util::run_state_test"]
+#[fork = "Byzantium"]
+struct Example;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/GeneralStateTests/stSStoreTest"]
+#[test_with = "jsontests::util::run_state_test"]
+#[fork = "Byzantium"]
+struct SStore;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/GeneralStateTests/stTransactionTest"]
+#[test_with = "jsontests::util::run_state_test"]
+#[fork = "Byzantium"]
+struct Transaction;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/GeneralStateTests/stCallCodes"]
+#[test_with = "jsontests::util::run_state_test"]
+#[fork = "EIP150"]
+struct CallCodes;
@@ -0,0 +1,44 @@
+#![allow(non_snake_case)]
+#![allow(unused)]
+
+#[macro_use]
+extern crate jsontests_derive;
+extern crate jsontests;
+extern crate sputnikvm;
+extern crate sputnikvm_network_foundation;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/TransactionTests/ttSignature"]
+#[test_with = "jsontests::util::run_transaction_test"]
+#[fork = "Frontier"]
+struct SignatureFrontier;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/TransactionTests/ttSignature"]
+#[test_with = "jsontests::util::run_transaction_test"]
+#[fork = "Homestead"]
+struct SignatureHomestead;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/TransactionTests/ttSignature"]
+#[test_with = "jsontests::util::run_transaction_test"]
+#[fork = "Byzantium"]
+struct SignatureByzantium;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/TransactionTests/ttEIP155"]
+#[test_with = "jsontests::util::run_transaction_test"]
+#[fork = "EIP158"]
+struct EIP155;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/TransactionTests/ttWrongRLP"]
+#[test_with = "jsontests::util::run_transaction_test"]
+#[fork = "Byzantium"]
+struct WrongRLP;
+
+#[derive(JsonTests)]
+#[directory = "jsontests/res/files/eth/TransactionTests/ttGasLimit"]
+#[test_with = "jsontests::util::run_transaction_test"]
+#[fork = "Homestead"]
+struct GasLimit;
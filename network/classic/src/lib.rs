@@ -16,6 +16,7 @@ impl AccountPatch for MainnetAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() }
     fn empty_considered_exists() -> bool { true }
+    fn chain_id() -> Option<u64> { Some(61) }
 }
 
 pub struct MordenAccountPatch;
@@ -23,6 +24,7 @@ impl AccountPatch for MordenAccountPatch {
     fn initial_nonce() -> U256 { U256::from(1048576) }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() }
     fn empty_considered_exists() -> bool { true }
+    fn chain_id() -> Option<u64> { Some(62) }
 }
 
 pub static ETC_PRECOMPILEDS: [(Address, Option<&'static [u8]>, &'static Precompiled); 4] = [
@@ -95,6 +97,9 @@ impl<A: AccountPatch> Patch for FrontierPatch<A> {
     fn err_on_call_with_more_gas() -> bool { true }
     fn call_create_l64_after_gas() -> bool { false }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { false }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &ETC_PRECOMPILEDS }
 }
@@ -127,6 +132,9 @@ impl<A: AccountPatch> Patch for HomesteadPatch<A> {
     fn err_on_call_with_more_gas() -> bool { true }
     fn call_create_l64_after_gas() -> bool { false }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &ETC_PRECOMPILEDS }
 }
@@ -159,6 +167,9 @@ impl<A: AccountPatch> Patch for EIP150Patch<A> {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &ETC_PRECOMPILEDS }
 }
@@ -191,6 +202,9 @@ impl<A: AccountPatch> Patch for EIP160Patch<A> {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &ETC_PRECOMPILEDS }
 }
@@ -223,6 +237,9 @@ impl<A: AccountPatch> Patch for ByzantiumPatch<A> {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &BYZANTIUM_PRECOMPILEDS }
 }
@@ -256,6 +273,31 @@ impl<A: AccountPatch> Patch for ConstantinoplePatch<A> {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &BYZANTIUM_PRECOMPILEDS }
 }
+
+/// A function generic over the patch, called by `with_patch` with
+/// the patch chosen at runtime.
+pub trait PatchFn {
+    /// Result of the function.
+    type Output;
+    /// Call the function with the patch.
+    fn call<P: Patch + 'static>(self) -> Self::Output;
+}
+
+/// Call the function with the mainnet patch of the name, one of
+/// `frontier`, `homestead`, `eip150` and `eip160`. Returns `None` if
+/// there is no such patch.
+pub fn with_patch<F: PatchFn>(name: &str, f: F) -> Option<F::Output> {
+    match name {
+        "frontier" => Some(f.call::<MainnetFrontierPatch>()),
+        "homestead" => Some(f.call::<MainnetHomesteadPatch>()),
+        "eip150" => Some(f.call::<MainnetEIP150Patch>()),
+        "eip160" => Some(f.call::<MainnetEIP160Patch>()),
+        _ => None,
+    }
+}
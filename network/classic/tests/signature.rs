@@ -0,0 +1,43 @@
+extern crate block;
+extern crate hexutil;
+extern crate rlp;
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+
+use block::Transaction;
+use hexutil::read_hex;
+use sputnikvm::{AccountPatch, Patch, UntrustedTransaction};
+use sputnikvm::errors::PreExecutionError;
+use sputnikvm_network_classic::{MainnetAccountPatch, MordenAccountPatch,
+                                MainnetEIP160Patch, MainnetByzantiumPatch, MordenByzantiumPatch};
+
+/// The example of EIP155, signed for chain id 1.
+fn eip155_mainnet_transaction() -> Transaction {
+    let raw = read_hex(concat!("0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0",
+                               "b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620",
+                               "aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")).unwrap();
+    rlp::decode(&raw)
+}
+
+#[test]
+fn chain_ids() {
+    assert_eq!(MainnetAccountPatch::chain_id(), Some(61));
+    assert_eq!(MordenAccountPatch::chain_id(), Some(62));
+}
+
+fn assert_rejected<P: Patch>(transaction: &Transaction) {
+    match UntrustedTransaction::caller_of::<P>(transaction) {
+        Err(PreExecutionError::InvalidCaller) => (),
+        other => panic!("signature for chain id 1 accepted: {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_other_chain_signature() {
+    let transaction = eip155_mainnet_transaction();
+    assert_eq!(transaction.signature.v, 37);
+
+    assert_rejected::<MainnetEIP160Patch>(&transaction);
+    assert_rejected::<MainnetByzantiumPatch>(&transaction);
+    assert_rejected::<MordenByzantiumPatch>(&transaction);
+}
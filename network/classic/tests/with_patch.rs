@@ -0,0 +1,22 @@
+extern crate bigint;
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+
+use bigint::Gas;
+use sputnikvm::Patch;
+use sputnikvm_network_classic::{PatchFn, with_patch};
+
+struct GasCall;
+impl PatchFn for GasCall {
+    type Output = Gas;
+    fn call<P: Patch + 'static>(self) -> Gas { P::gas_call() }
+}
+
+#[test]
+fn dispatches_by_name() {
+    assert_eq!(with_patch("frontier", GasCall), Some(Gas::from(40usize)));
+    assert_eq!(with_patch("homestead", GasCall), Some(Gas::from(40usize)));
+    assert_eq!(with_patch("eip150", GasCall), Some(Gas::from(700usize)));
+    assert_eq!(with_patch("eip160", GasCall), Some(Gas::from(700usize)));
+    assert_eq!(with_patch("byzantium", GasCall), None);
+}
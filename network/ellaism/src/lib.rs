@@ -27,6 +27,7 @@ impl AccountPatch for MainnetAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() }
     fn empty_considered_exists() -> bool { true }
+    fn chain_id() -> Option<u64> { Some(64) }
 }
 
 /// EIP160 patch.
@@ -56,6 +57,9 @@ impl<A: AccountPatch> Patch for EIP160Patch<A> {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { A::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &ELLA_PRECOMPILEDS }
 }
@@ -56,6 +56,7 @@ impl AccountPatch for FrontierAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() }
     fn empty_considered_exists() -> bool { true }
+    fn chain_id() -> Option<u64> { Some(2) }
 }
 
 pub struct StateClearingAccountPatch;
@@ -63,6 +64,7 @@ impl AccountPatch for StateClearingAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() + U256::from(1) }
     fn empty_considered_exists() -> bool { false }
+    fn chain_id() -> Option<u64> { Some(2) }
 }
 
 /// Frontier patch.
@@ -91,6 +93,9 @@ impl Patch for FrontierPatch {
     fn err_on_call_with_more_gas() -> bool { true }
     fn call_create_l64_after_gas() -> bool { false }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { false }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { FrontierAccountPatch::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &FRONTIER_PRECOMPILEDS }
 }
@@ -121,6 +126,9 @@ impl Patch for HomesteadPatch {
     fn err_on_call_with_more_gas() -> bool { true }
     fn call_create_l64_after_gas() -> bool { false }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { FrontierAccountPatch::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &FRONTIER_PRECOMPILEDS }
 }
@@ -151,6 +159,9 @@ impl Patch for SpuriousDragonPatch {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { StateClearingAccountPatch::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &FRONTIER_PRECOMPILEDS }
 }
@@ -181,6 +192,9 @@ impl Patch for ByzantiumPatch {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { StateClearingAccountPatch::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &BYZANTIUM_PRECOMPILEDS }
 }
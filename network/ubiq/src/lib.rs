@@ -25,6 +25,7 @@ impl AccountPatch for StateClearingAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() + U256::from(1) }
     fn empty_considered_exists() -> bool { false }
+    fn chain_id() -> Option<u64> { Some(8) }
 }
 
 /// Spurious Dragon patch.
@@ -53,6 +54,9 @@ impl Patch for SpuriousDragonPatch {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { StateClearingAccountPatch::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &FRONTIER_PRECOMPILEDS }
 }
@@ -2,7 +2,7 @@ extern crate bigint;
 extern crate bn;
 extern crate sputnikvm;
 
-use std::rc::Rc;
+use std::sync::Arc;
 use bigint::{Gas, U256};
 
 use sputnikvm::Precompiled;
@@ -12,7 +12,7 @@ pub static BN128_ADD_PRECOMPILED: Bn128AddPrecompiled = Bn128AddPrecompiled;
 
 pub struct Bn128AddPrecompiled;
 impl Precompiled for Bn128AddPrecompiled {
-    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Rc<Vec<u8>>), RuntimeError> {
+    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Arc<Vec<u8>>), RuntimeError> {
         use bn::{G1, AffineG1, Fq, Group};
 
         let gas = Gas::from(500usize);
@@ -52,7 +52,7 @@ impl Precompiled for Bn128AddPrecompiled {
             ret.y().to_big_endian(&mut output[32..64]).unwrap();
         }
 
-        Ok((gas, Rc::new(output)))
+        Ok((gas, Arc::new(output)))
     }
 }
 
@@ -60,7 +60,7 @@ pub static BN128_MUL_PRECOMPILED: Bn128MulPrecompiled = Bn128MulPrecompiled;
 
 pub struct Bn128MulPrecompiled;
 impl Precompiled for Bn128MulPrecompiled {
-    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Rc<Vec<u8>>), RuntimeError> {
+    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Arc<Vec<u8>>), RuntimeError> {
         use bn::{G1, AffineG1, Fq, Fr, Group};
 
         let gas = Gas::from(40000usize);
@@ -93,7 +93,7 @@ impl Precompiled for Bn128MulPrecompiled {
             ret.y().to_big_endian(&mut output[32..64]).unwrap();
         };
 
-        Ok((gas, Rc::new(output)))
+        Ok((gas, Arc::new(output)))
     }
 }
 
@@ -101,7 +101,7 @@ pub static BN128_PAIRING_PRECOMPILED: Bn128PairingPrecompiled = Bn128PairingPrec
 
 pub struct Bn128PairingPrecompiled;
 impl Precompiled for Bn128PairingPrecompiled {
-    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Rc<Vec<u8>>), RuntimeError> {
+    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Arc<Vec<u8>>), RuntimeError> {
         use bn::{G1, AffineG1, Fq, Group, pairing, Gt, G2, Fq2, AffineG2};
 
         fn read_one(s: &[u8]) -> Result<(G1, G2), RuntimeError> {
@@ -160,6 +160,6 @@ impl Precompiled for Bn128PairingPrecompiled {
         let mut output = vec![0u8; 32];
         result.to_big_endian(&mut output);
 
-        Ok((gas, Rc::new(output)))
+        Ok((gas, Arc::new(output)))
     }
 }
@@ -5,7 +5,7 @@ extern crate sputnikvm;
 #[cfg(test)]
 extern crate hexutil;
 
-use std::rc::Rc;
+use std::sync::Arc;
 use bigint::{Gas, U256};
 
 use sputnikvm::Precompiled;
@@ -15,7 +15,7 @@ pub static MODEXP_PRECOMPILED: ModexpPrecompiled = ModexpPrecompiled;
 
 pub struct ModexpPrecompiled;
 impl Precompiled for ModexpPrecompiled {
-    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Rc<Vec<u8>>), RuntimeError> {
+    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Arc<Vec<u8>>), RuntimeError> {
         use std::cmp;
         use num_bigint::BigUint;
 
@@ -37,7 +37,15 @@ impl Precompiled for ModexpPrecompiled {
             } else if exponent_length <= U256::from(32) {
                 U256::from(exp32.bits())
             } else {
-                U256::from(8) * (exponent_length - U256::from(32)) + U256::from(exp32.bits())
+                // Saturate on overflow, the gas cost is then out of
+                // reach anyway.
+                let (length, o1) = U256::from(8).overflowing_mul(exponent_length - U256::from(32));
+                let (length, o2) = length.overflowing_add(U256::from(exp32.bits()));
+                if o1 || o2 {
+                    U256::max_value()
+                } else {
+                    length
+                }
             }
         }
 
@@ -78,6 +86,12 @@ impl Precompiled for ModexpPrecompiled {
             return Err(RuntimeError::OnChain(OnChainError::EmptyGas));
         }
 
+        // The result is empty regardless of the other lengths, which
+        // may be too large to read.
+        if modulus_length == U256::zero() {
+            return Ok((gas, Arc::new(Vec::new())));
+        }
+
         if base_length > U256::from(usize::max_value()) ||
             exponent_length > U256::from(usize::max_value()) ||
             modulus_length > U256::from(usize::max_value())
@@ -125,7 +139,7 @@ impl Precompiled for ModexpPrecompiled {
             result.insert(0, 0u8);
         }
 
-        Ok((gas, Rc::new(result)))
+        Ok((gas, Arc::new(result)))
     }
 }
 
@@ -139,7 +153,7 @@ mod tests {
         let input = read_hex("00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000002003fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2efffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f").unwrap();
         let (_, output) = MODEXP_PRECOMPILED.gas_and_step(&input, Gas::from(10000000usize)).unwrap();
         let expected = read_hex("0000000000000000000000000000000000000000000000000000000000000001").unwrap();
-        assert_eq!(expected, Rc::try_unwrap(output).unwrap());
+        assert_eq!(expected, Arc::try_unwrap(output).unwrap());
     }
 
     #[test]
@@ -147,7 +161,7 @@ mod tests {
         let input = read_hex("000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000020fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2efffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f").unwrap();
         let (_, output) = MODEXP_PRECOMPILED.gas_and_step(&input, Gas::from(10000000usize)).unwrap();
         let expected = read_hex("0000000000000000000000000000000000000000000000000000000000000000").unwrap();
-        assert_eq!(expected, Rc::try_unwrap(output).unwrap());
+        assert_eq!(expected, Arc::try_unwrap(output).unwrap());
     }
 
     #[test]
@@ -164,7 +178,7 @@ mod tests {
         let input = read_hex("00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000002003ffff800000000000000000000000000000000000000000000000000000000000000007").unwrap();
         let (_, output) = MODEXP_PRECOMPILED.gas_and_step(&input, Gas::from(10000000usize)).unwrap();
         let expected = read_hex("3b01b01ac41f2d6e917c6d6a221ce793802469026d9ab7578fa2e79e4da6aaab").unwrap();
-        assert_eq!(expected, Rc::try_unwrap(output).unwrap());
+        assert_eq!(expected, Arc::try_unwrap(output).unwrap());
     }
 
     #[test]
@@ -172,6 +186,17 @@ mod tests {
         let input = read_hex("00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000002003ffff80").unwrap();
         let (_, output) = MODEXP_PRECOMPILED.gas_and_step(&input, Gas::from(10000000usize)).unwrap();
         let expected = read_hex("3b01b01ac41f2d6e917c6d6a221ce793802469026d9ab7578fa2e79e4da6aaab").unwrap();
-        assert_eq!(expected, Rc::try_unwrap(output).unwrap());
+        assert_eq!(expected, Arc::try_unwrap(output).unwrap());
+    }
+
+    #[test]
+    fn huge_exponent_length() {
+        let input = read_hex("0000000000000000000000000000000000000000000000000000000000000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+        let (gas, output) = MODEXP_PRECOMPILED.gas_and_step(&input, Gas::from(10000000usize)).unwrap();
+        assert_eq!(gas, Gas::zero());
+        assert!(output.is_empty());
+
+        let input = read_hex("0000000000000000000000000000000000000000000000000000000000000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0000000000000000000000000000000000000000000000000000000000000001").unwrap();
+        assert!(MODEXP_PRECOMPILED.gas_and_step(&input, Gas::from(10000000usize)).is_err());
     }
 }
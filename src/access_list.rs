@@ -0,0 +1,196 @@
+//! EIP-2930 access lists.
+
+#[cfg(not(feature = "std"))] use alloc::vec::Vec;
+#[cfg(feature = "std")] use std::marker::PhantomData;
+#[cfg(not(feature = "std"))] use core::marker::PhantomData;
+use bigint::{U256, H256, Gas, Address};
+
+use super::errors::{RequireError, CommitError, PreExecutionError};
+use super::{TransactionVM, ValidTransaction, HeaderParams, AccountState, BlockhashState,
+            AccountCommitment, Memory, Patch, VM};
+
+const G_ACCESSLISTADDRESS: usize = 2400;
+const G_ACCESSLISTSTORAGE: usize = 1900;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An address and the storage keys of it in an access list.
+pub struct AccessListItem {
+    /// Accessed address.
+    pub address: Address,
+    /// Accessed storage keys of the address.
+    pub storage_keys: Vec<H256>,
+}
+
+/// An EIP-2930 access list.
+pub type AccessList = Vec<AccessListItem>;
+
+/// Intrinsic gas charged for the access list.
+pub fn access_list_gas(access_list: &[AccessListItem]) -> Gas {
+    let mut gas = Gas::zero();
+    for item in access_list {
+        gas = gas + Gas::from(G_ACCESSLISTADDRESS) +
+            Gas::from(G_ACCESSLISTSTORAGE) * Gas::from(item.storage_keys.len());
+    }
+    gas
+}
+
+/// Generates the access list of a transaction, from the accounts and
+/// storage it requires during execution.
+///
+/// The sender, the recipient and precompiled contracts are left out.
+/// The block beneficiary is also left out, unless its storage is
+/// accessed, because it is required when paying the fee. The
+/// transaction is executed again with the access list applied until
+/// the list no longer changes. Accounts and block hashes committed to
+/// the generator are kept between executions.
+pub struct AccessListGenerator<M, P: Patch> {
+    transaction: ValidTransaction,
+    block: HeaderParams,
+    account_state: AccountState<P::Account>,
+    blockhash_state: BlockhashState,
+    access_list: AccessList,
+    _marker: PhantomData<M>,
+}
+
+impl<M: Memory + Default, P: Patch> AccessListGenerator<M, P> {
+    /// Create a new generator for the transaction.
+    pub fn new(transaction: ValidTransaction, block: HeaderParams) -> Self {
+        AccessListGenerator {
+            transaction,
+            block,
+            account_state: AccountState::default(),
+            blockhash_state: BlockhashState::default(),
+            access_list: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Commit an account information to the generator. This should be
+    /// used when receiving `RequireError`.
+    pub fn commit_account(&mut self, commitment: AccountCommitment) -> Result<(), CommitError> {
+        self.account_state.commit(commitment)
+    }
+
+    /// Commit a block hash to the generator. This should be used when
+    /// receiving `RequireError`.
+    pub fn commit_blockhash(&mut self, number: U256, hash: H256) -> Result<(), CommitError> {
+        self.blockhash_state.commit(number, hash)
+    }
+
+    fn is_excluded(&self, address: Address) -> bool {
+        Some(address) == self.transaction.caller ||
+            address == self.transaction.address() ||
+            P::precompileds().iter().any(|&(precompiled, _, _)| precompiled == address)
+    }
+
+    fn collect(&self) -> AccessList {
+        let storage = self.account_state.used_storage();
+
+        let mut access_list = Vec::new();
+        for address in self.account_state.used_addresses() {
+            if self.is_excluded(address) {
+                continue;
+            }
+
+            let mut storage_keys: Vec<U256> = match storage.get(&address) {
+                Some(indexes) => indexes.iter().cloned().collect(),
+                None => Vec::new(),
+            };
+            if address == self.block.beneficiary && storage_keys.is_empty() {
+                continue;
+            }
+            storage_keys.sort();
+
+            access_list.push(AccessListItem {
+                address,
+                storage_keys: storage_keys.into_iter().map(H256::from).collect(),
+            });
+        }
+        access_list.sort_by(|a, b| a.address.cmp(&b.address));
+        access_list
+    }
+
+    /// Generate the access list, and return it together with the gas
+    /// used when it is applied. Returns `RequireError` when an
+    /// account or block hash is needed. After committing it, call
+    /// this function again to continue.
+    pub fn generate(&mut self) -> Result<Result<(AccessList, Gas), PreExecutionError>, RequireError> {
+        loop {
+            let intrinsic_gas = self.transaction.intrinsic_gas::<P>() + access_list_gas(&self.access_list);
+            if self.transaction.gas_limit < intrinsic_gas {
+                return Ok(Err(PreExecutionError::InsufficientGasLimit));
+            }
+
+            let mut vm = TransactionVM::<M, P>::with_access_list(
+                self.transaction.clone(), self.block.clone(), &self.access_list,
+                self.account_state.clone(), self.blockhash_state.clone());
+            vm.fire()?;
+
+            let access_list = self.collect();
+            if access_list == self.access_list {
+                return Ok(Ok((access_list, vm.used_gas())));
+            }
+            self.access_list = access_list;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use bigint::*;
+    use test_util::{Account, address, account, contract, transaction, header, answer_all};
+
+    fn generate(accounts: &[Account], to: Address) -> (AccessList, Gas) {
+        let mut generator = SeqAccessListGenerator::<EmbeddedPatch>::new(
+            transaction(to, Gas::from(1000000u64)), header());
+        let (result, _) = answer_all(accounts, &mut generator, |generator| generator.generate(),
+                                     |generator, commitment| generator.commit_account(commitment));
+        result.unwrap()
+    }
+
+    #[test]
+    fn access_list_of_call() {
+        let other = address("0x3000000000000000000000000000000000000000");
+
+        // SLOAD(1), then BALANCE of the other account, of the
+        // identity precompiled contract, and of the caller.
+        let accounts = [
+            account("0x2000000000000000000000000000000000000000",
+                    concat!("0x600154",
+                            "733000000000000000000000000000000000000000", "31",
+                            "730000000000000000000000000000000000000004", "31",
+                            "731000000000000000000000000000000000000000", "3100"), 0),
+            account("0x3000000000000000000000000000000000000000", "0x600154", 0),
+        ];
+        let (access_list, gas) = generate(&accounts, contract());
+
+        assert_eq!(access_list, vec![AccessListItem {
+            address: other,
+            storage_keys: Vec::new(),
+        }]);
+        // Intrinsic gas, the access list, PUSH1 and SLOAD, and three
+        // times PUSH20 and BALANCE.
+        assert_eq!(gas, Gas::from(21000u64 + 2400 + 3 + 200 + 3 * (3 + 400)));
+    }
+
+    #[test]
+    fn access_list_of_storage() {
+        let other = address("0x3000000000000000000000000000000000000000");
+
+        // The contract calls the other account, which does SLOAD(2)
+        // and SLOAD(1).
+        let accounts = [
+            account("0x2000000000000000000000000000000000000000",
+                    concat!("0x6000600060006000600073",
+                            "3000000000000000000000000000000000000000", "5af100"), 0),
+            account("0x3000000000000000000000000000000000000000", "0x6002546001545000", 0),
+        ];
+        let (access_list, _) = generate(&accounts, contract());
+
+        assert_eq!(access_list, vec![AccessListItem {
+            address: other,
+            storage_keys: vec![H256::from(U256::from(1u64)), H256::from(U256::from(2u64))],
+        }]);
+    }
+}
@@ -9,17 +9,19 @@ use alloc::vec::Vec;
 #[cfg(not(feature = "std"))] use core::marker::PhantomData;
 #[cfg(feature = "std")] use std::cell::RefCell;
 #[cfg(not(feature = "std"))] use core::cell::RefCell;
+#[cfg(feature = "std")] use std::mem::replace;
+#[cfg(not(feature = "std"))] use core::mem::replace;
 use bigint::{M256, U256, Address};
 use patch::AccountPatch;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 use errors::{RequireError, CommitError};
 
 /// Internal representation of an account storage. It will return a
 /// `RequireError` if trying to access non-existing storage.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Storage {
     partial: bool,
     address: Address,
@@ -77,6 +79,11 @@ impl Storage {
         Ok(())
     }
 
+    /// Return all indexes of changed/full items in storage.
+    pub fn indexes(&self) -> map::Keys<U256, M256> {
+        self.storage.keys()
+    }
+
     /// Return the number of changed/full items in storage.
     #[inline]
     pub fn len(&self) -> usize {
@@ -102,7 +109,7 @@ pub enum AccountCommitment {
         /// Account balance.
         balance: U256,
         /// Code associated with this account.
-        code: Rc<Vec<u8>>,
+        code: Arc<Vec<u8>>,
     },
     /// Commit only code of the account. The client can keep changing
     /// it in other EVMs if the code remains unchanged.
@@ -110,7 +117,7 @@ pub enum AccountCommitment {
         /// Account address.
         address: Address,
         /// Code associated with this account.
-        code: Rc<Vec<u8>>,
+        code: Arc<Vec<u8>>,
     },
     /// Commit a storage. Must be used given a full account.
     Storage {
@@ -147,7 +154,7 @@ impl AccountCommitment {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Represents an account. This is usually returned by the EVM.
 pub enum AccountChange {
     /// A full account. The client is expected to replace its own account state with this.
@@ -161,7 +168,7 @@ pub enum AccountChange {
         /// Change storage with given indexes and values.
         changing_storage: Storage,
         /// Code associated with this account.
-        code: Rc<Vec<u8>>,
+        code: Arc<Vec<u8>>,
     },
     /// Only balance is changed, and it is increasing for this address.
     IncreaseBalance(Address, U256),
@@ -176,7 +183,7 @@ pub enum AccountChange {
         /// All storage values of this account, with given indexes and values.
         storage: Storage,
         /// Code associated with this account.
-        code: Rc<Vec<u8>>
+        code: Arc<Vec<u8>>
     },
     /// The account should remain nonexist, or should be deleted if
     /// exists.
@@ -201,12 +208,39 @@ impl AccountChange {
     }
 }
 
+#[derive(Debug, Clone)]
+/// A change to the account state that can be reverted.
+enum JournalEntry {
+    /// An account was replaced. Holds the account before, if it was
+    /// in the account state.
+    Account(Address, Option<AccountChange>),
+    /// Balance of a full or created account was changed. Holds the
+    /// balance before.
+    Balance(Address, U256),
+    /// Nonce of a full or created account was changed. Holds the
+    /// nonce before.
+    Nonce(Address, U256),
+    /// A storage value was written. Holds the value before, if it
+    /// was in the storage.
+    Storage(Address, U256, Option<M256>),
+    /// Code was deposited into a created account. Holds the code
+    /// before.
+    Code(Address, Arc<Vec<u8>>),
+}
+
 #[derive(Debug)]
 /// A struct that manages the current account state for one EVM.
+///
+/// All call frames share one account state. Changes are recorded in
+/// a journal, so that the changes made after a checkpoint can be
+/// reverted when a frame fails. Commitments are not changes, and are
+/// never reverted.
 pub struct AccountState<A: AccountPatch> {
     accounts: Map<Address, AccountChange>,
     orig_storage: RefCell<Map<Address, Storage>>,
-    codes: Map<Address, Rc<Vec<u8>>>,
+    codes: Map<Address, Arc<Vec<u8>>>,
+    journal: Vec<JournalEntry>,
+    checkpoints: Vec<usize>,
     _marker: PhantomData<A>,
 }
 
@@ -216,6 +250,8 @@ impl<A: AccountPatch> Default for AccountState<A> {
             accounts: Map::new(),
             codes: Map::new(),
             orig_storage: RefCell::new(Map::new()),
+            journal: Vec::new(),
+            checkpoints: Vec::new(),
             _marker: PhantomData,
         }
     }
@@ -227,6 +263,8 @@ impl<A: AccountPatch> Clone for AccountState<A> {
             accounts: self.accounts.clone(),
             codes: self.codes.clone(),
             orig_storage: self.orig_storage.clone(),
+            journal: self.journal.clone(),
+            checkpoints: self.checkpoints.clone(),
             _marker: PhantomData,
         }
     }
@@ -236,35 +274,69 @@ fn is_empty(nonce: U256, balance: U256, code: &[u8]) -> bool {
     nonce == U256::zero() && balance == U256::zero() && code.is_empty()
 }
 
+/// Account resulting from a full or nonexist commitment, given the
+/// account currently in the account state.
+fn committed_account<A: AccountPatch>(
+    account: Option<&AccountChange>, commitment: &AccountCommitment
+) -> Result<AccountChange, CommitError> {
+    match (commitment, account) {
+        (_, Some(&AccountChange::Full { .. })) |
+        (_, Some(&AccountChange::Create { .. })) |
+        (_, Some(&AccountChange::Nonexist(_))) => Err(CommitError::AlreadyCommitted),
+        (&AccountCommitment::Full { nonce, address, balance, ref code }, topup) => {
+            let topup = match topup {
+                Some(&AccountChange::IncreaseBalance(_, topup)) => topup,
+                _ => U256::zero(),
+            };
+            Ok(AccountChange::Full {
+                nonce,
+                address,
+                balance: balance + topup,
+                changing_storage: Storage::new(address, true),
+                code: code.clone(),
+            })
+        },
+        (&AccountCommitment::Nonexist(address), Some(&AccountChange::IncreaseBalance(_, topup))) => {
+            Ok(AccountChange::Create {
+                nonce: A::initial_nonce(),
+                address,
+                balance: topup,
+                storage: Storage::new(address, false),
+                code: Arc::new(Vec::new())
+            })
+        },
+        (&AccountCommitment::Nonexist(address), None) => Ok(AccountChange::Nonexist(address)),
+        _ => Err(CommitError::InvalidCommitment),
+    }
+}
+
 impl<A: AccountPatch> AccountState<A> {
-    fn insert_account(&mut self, account: AccountChange) {
+    fn normalize(account: AccountChange) -> AccountChange {
         match account {
             AccountChange::Full { nonce, address, balance, changing_storage, code } => {
                 if (!A::empty_considered_exists()) && is_empty(nonce, balance, &code)
                 {
-                    self.accounts.insert(address, AccountChange::Nonexist(address));
+                    AccountChange::Nonexist(address)
                 } else {
-                    self.accounts.insert(address, AccountChange::Full {
+                    AccountChange::Full {
                         nonce, address, balance, changing_storage, code
-                    });
+                    }
                 }
             },
             AccountChange::Create { nonce, address, balance, storage, code } => {
                 if (!A::empty_considered_exists()) && is_empty(nonce, balance, &code)
                 {
-                    self.accounts.insert(address, AccountChange::Nonexist(address));
+                    AccountChange::Nonexist(address)
                 } else {
-                    self.accounts.insert(address, AccountChange::Create {
+                    AccountChange::Create {
                         nonce, address, balance, storage, code
-                    });
+                    }
                 }
             },
-            AccountChange::Nonexist(address) => {
-                self.accounts.insert(address, AccountChange::Nonexist(address));
-            },
+            AccountChange::Nonexist(address) => AccountChange::Nonexist(address),
             AccountChange::IncreaseBalance(address, balance) => {
                 if A::allow_partial_change() {
-                    self.accounts.insert(address, AccountChange::IncreaseBalance(address, balance));
+                    AccountChange::IncreaseBalance(address, balance)
                 } else {
                     panic!()
                 }
@@ -272,6 +344,104 @@ impl<A: AccountPatch> AccountState<A> {
         }
     }
 
+    fn insert_account(&mut self, account: AccountChange) {
+        let account = Self::normalize(account);
+        self.accounts.insert(account.address(), account);
+    }
+
+    /// Record a change in the journal. Nothing is recorded if there
+    /// is no checkpoint to revert to.
+    fn record(&mut self, entry: JournalEntry) {
+        if !self.checkpoints.is_empty() {
+            self.journal.push(entry);
+        }
+    }
+
+    /// Replace an account, recording the account before.
+    fn replace_account(&mut self, address: Address, account: AccountChange) {
+        let previous = self.accounts.remove(&address);
+        self.record(JournalEntry::Account(address, previous));
+        self.insert_account(account);
+    }
+
+    /// Delete a full or created account that became empty, if empty
+    /// accounts are not considered existing.
+    fn touch(&mut self, address: Address) {
+        if A::empty_considered_exists() {
+            return;
+        }
+
+        let empty = match self.accounts.get(&address) {
+            Some(&AccountChange::Full { nonce, balance, ref code, .. }) |
+            Some(&AccountChange::Create { nonce, balance, ref code, .. }) =>
+                is_empty(nonce, balance, code),
+            _ => false,
+        };
+        if empty {
+            self.replace_account(address, AccountChange::Nonexist(address));
+        }
+    }
+
+    /// Start a new checkpoint. Changes made after it can either be
+    /// kept with `commit_checkpoint` or reverted with
+    /// `revert_checkpoint`.
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(self.journal.len());
+    }
+
+    /// Keep the changes made since the last checkpoint. They are
+    /// still reverted if an outer checkpoint is reverted.
+    ///
+    /// ### Panic
+    /// Requires a checkpoint to be started.
+    pub fn commit_checkpoint(&mut self) {
+        self.checkpoints.pop().unwrap();
+        if self.checkpoints.is_empty() {
+            self.journal.clear();
+        }
+    }
+
+    /// Revert the changes made since the last checkpoint. Accounts,
+    /// codes and storage committed in the meantime are kept.
+    ///
+    /// ### Panic
+    /// Requires a checkpoint to be started.
+    pub fn revert_checkpoint(&mut self) {
+        let len = self.checkpoints.pop().unwrap();
+        while self.journal.len() > len {
+            match self.journal.pop().unwrap() {
+                JournalEntry::Account(address, Some(account)) => {
+                    self.accounts.insert(address, account);
+                },
+                JournalEntry::Account(address, None) => {
+                    self.accounts.remove(&address);
+                },
+                JournalEntry::Balance(address, value) => match self.accounts.get_mut(&address) {
+                    Some(&mut AccountChange::Full { ref mut balance, .. }) |
+                    Some(&mut AccountChange::Create { ref mut balance, .. }) => *balance = value,
+                    _ => panic!(),
+                },
+                JournalEntry::Nonce(address, value) => match self.accounts.get_mut(&address) {
+                    Some(&mut AccountChange::Full { ref mut nonce, .. }) |
+                    Some(&mut AccountChange::Create { ref mut nonce, .. }) => *nonce = value,
+                    _ => panic!(),
+                },
+                JournalEntry::Storage(address, index, value) => match self.accounts.get_mut(&address) {
+                    Some(&mut AccountChange::Full { changing_storage: ref mut storage, .. }) |
+                    Some(&mut AccountChange::Create { ref mut storage, .. }) => match value {
+                        Some(value) => { storage.storage.insert(index, value); },
+                        None => { storage.storage.remove(&index); },
+                    },
+                    _ => panic!(),
+                },
+                JournalEntry::Code(address, value) => match self.accounts.get_mut(&address) {
+                    Some(&mut AccountChange::Create { ref mut code, .. }) => *code = value,
+                    _ => panic!(),
+                },
+            }
+        }
+    }
+
     /// Returns all fetched or modified addresses.
     pub fn used_addresses(&self) -> Set<Address> {
         let mut set = Set::new();
@@ -284,6 +454,22 @@ impl<A: AccountPatch> AccountState<A> {
         set
     }
 
+    /// Returns all fetched or modified storage indexes, by address.
+    pub fn used_storage(&self) -> Map<Address, Set<U256>> {
+        let mut map = Map::new();
+        for account in self.accounts() {
+            let storage = match *account {
+                AccountChange::Full { ref changing_storage, .. } => changing_storage,
+                AccountChange::Create { ref storage, .. } => storage,
+                _ => continue,
+            };
+            if !storage.is_empty() {
+                map.insert(account.address(), storage.indexes().cloned().collect());
+            }
+        }
+        map
+    }
+
     /// Returns all accounts right now in this account state.
     pub fn accounts(&self) -> map::Values<Address, AccountChange> {
         self.accounts.values()
@@ -325,39 +511,24 @@ impl<A: AccountPatch> AccountState<A> {
     /// Commit an account commitment into this account state.
     pub fn commit(&mut self, commitment: AccountCommitment) -> Result<(), CommitError> {
         match commitment {
-            AccountCommitment::Full {
-                nonce,
-                address,
-                balance,
-                code
-            } => {
-                let account = if self.accounts.contains_key(&address) {
-                    match self.accounts.remove(&address).unwrap() {
-                        | AccountChange::Full { .. }
-                        | AccountChange::Create { .. }
-                        | AccountChange::Nonexist(_) => return Err(CommitError::AlreadyCommitted),
-                        AccountChange::IncreaseBalance(address, topup) => {
-                            AccountChange::Full {
-                                nonce,
-                                address,
-                                balance: balance + topup,
-                                changing_storage: Storage::new(address, true),
-                                code,
-                            }
-                        },
-                    }
-                } else {
-                    AccountChange::Full {
-                        nonce,
-                        address,
-                        balance,
-                        changing_storage: Storage::new(address, true),
-                        code,
-                    }
-                };
-
+            AccountCommitment::Full { address, .. } |
+            AccountCommitment::Nonexist(address) => {
+                let account = committed_account::<A>(self.accounts.get(&address), &commitment)?;
                 self.insert_account(account);
                 self.codes.remove(&address);
+
+                // Accounts saved in the journal are also committed,
+                // so that they are kept when reverting.
+                for entry in &mut self.journal {
+                    if let JournalEntry::Account(entry_address, ref mut previous) = *entry {
+                        if entry_address != address {
+                            continue;
+                        }
+                        if let Ok(account) = committed_account::<A>(previous.as_ref(), &commitment) {
+                            *previous = Some(Self::normalize(account));
+                        }
+                    }
+                }
             },
             AccountCommitment::Code {
                 address,
@@ -385,35 +556,21 @@ impl<A: AccountPatch> AccountState<A> {
                         return Err(CommitError::InvalidCommitment);
                     },
                 }
+                for entry in &mut self.journal {
+                    if let JournalEntry::Account(entry_address, Some(AccountChange::Full {
+                        ref mut changing_storage, ..
+                    })) = *entry {
+                        if entry_address == address {
+                            let _ = changing_storage.commit(index, value);
+                        }
+                    }
+                }
                 self.orig_storage
                     .borrow_mut()
                     .entry(address)
                     .or_insert(Storage::new(address, true))
                     .commit(index, value)?
             },
-            AccountCommitment::Nonexist(address) => {
-                let account = if self.accounts.contains_key(&address) {
-                    match self.accounts.remove(&address).unwrap() {
-                        | AccountChange::Full { .. }
-                        | AccountChange::Create { .. }
-                        | AccountChange::Nonexist(_) => return Err(CommitError::AlreadyCommitted),
-                        AccountChange::IncreaseBalance(address, topup) => {
-                            AccountChange::Create {
-                                nonce: A::initial_nonce(),
-                                address,
-                                balance: topup,
-                                storage: Storage::new(address, false),
-                                code: Rc::new(Vec::new())
-                            }
-                        },
-                    }
-                } else {
-                    AccountChange::Nonexist(address)
-                };
-
-                self.insert_account(account);
-                self.codes.remove(&address);
-            }
         }
         Ok(())
     }
@@ -447,14 +604,14 @@ impl<A: AccountPatch> AccountState<A> {
 
     /// Find code by its address in this account state. If the search
     /// failed, returns a `RequireError`.
-    pub fn code(&self, address: Address) -> Result<Rc<Vec<u8>>, RequireError> {
+    pub fn code(&self, address: Address) -> Result<Arc<Vec<u8>>, RequireError> {
         self.code_opt_nonexist(address)
-            .map(|opt_code| opt_code.unwrap_or_else(|| Rc::new(Vec::new())))
+            .map(|opt_code| opt_code.unwrap_or_else(|| Arc::new(Vec::new())))
     }
 
     /// Find code of account that may not exist. If search
     /// failed, returns a `RequireError`
-    pub fn code_opt_nonexist(&self, address: Address) -> Result<Option<Rc<Vec<u8>>>, RequireError> {
+    pub fn code_opt_nonexist(&self, address: Address) -> Result<Option<Arc<Vec<u8>>>, RequireError> {
         if self.accounts.contains_key(&address) {
             match self.accounts[&address] {
                 | AccountChange::Full { ref code, .. }
@@ -550,237 +707,243 @@ impl<A: AccountPatch> AccountState<A> {
     /// Write a value from an account storage. The account will be
     /// created if it is nonexist.
     pub fn storage_write(&mut self, address: Address, index: U256, value: M256) -> Result<(), RequireError> {
-        if self.accounts.contains_key(&address) {
-            match self.accounts.get_mut(&address).unwrap() {
-                AccountChange::Full {
-                    ref mut changing_storage,
-                    ..
-                } => return changing_storage.write(index, value),
-                AccountChange::Create {
-                    ref mut storage,
-                    ..
-                } => return storage.write(index, value),
-                val => {
-                    let is_nonexist;
-                    match val {
-                        AccountChange::Nonexist(_) => { is_nonexist = true; }
-                        _ => { is_nonexist = false; }
-                    }
-                    if is_nonexist {
-                        let mut storage = Storage::new(address, false);
-                        let ret = storage.write(index, value);
-                        *val = AccountChange::Create {
-                            nonce: A::initial_nonce(),
-                            address,
-                            balance: U256::zero(),
-                            storage,
-                            code: Rc::new(Vec::new())
-                        };
-                        return ret;
-                    }
-                }
-            }
-        }
-
-        Err(RequireError::Account(address))
+        let entry = match self.accounts.get_mut(&address) {
+            Some(&mut AccountChange::Full {
+                changing_storage: ref mut storage,
+                ..
+            }) |
+            Some(&mut AccountChange::Create {
+                ref mut storage,
+                ..
+            }) => {
+                let previous = storage.storage.get(&index).cloned();
+                storage.write(index, value)?;
+                JournalEntry::Storage(address, index, previous)
+            },
+            Some(val @ &mut AccountChange::Nonexist(_)) => {
+                let mut storage = Storage::new(address, false);
+                storage.write(index, value)?;
+                let previous = replace(val, AccountChange::Create {
+                    nonce: A::initial_nonce(),
+                    address,
+                    balance: U256::zero(),
+                    storage,
+                    code: Arc::new(Vec::new())
+                });
+                JournalEntry::Account(address, Some(previous))
+            },
+            _ => return Err(RequireError::Account(address)),
+        };
+        self.record(entry);
+        Ok(())
     }
 
     /// Create a new account (that should not yet have existed
     /// before).
     pub fn create(&mut self, address: Address, topup: U256) -> Result<(), RequireError> {
-        let account = if self.accounts.contains_key(&address) {
-            match self.accounts.remove(&address).unwrap() {
-                AccountChange::Full { balance, .. } => {
-                    AccountChange::Create {
-                        address, code: Rc::new(Vec::new()), nonce: A::initial_create_nonce(),
-                        balance: balance + topup, storage: Storage::new(address, false),
-                    }
-                },
-                AccountChange::Create { balance, .. } => {
-                    AccountChange::Create {
-                        address, code: Rc::new(Vec::new()), nonce: A::initial_create_nonce(),
-                        balance: balance + topup, storage: Storage::new(address, false),
-                    }
-                },
-                AccountChange::Nonexist(_) => {
-                    AccountChange::Create {
-                        address, code: Rc::new(Vec::new()), nonce: A::initial_create_nonce(),
-                        balance: topup, storage: Storage::new(address, false),
-                    }
-                },
-                _ => {
-                    // Although creation will clean up storage and
-                    // code, the balance will be added if it was
-                    // existing in prior. So if it is IncreaseBalance
-                    // or DecreaseBalance, we need to ask for the
-                    // account first.
-                    return Err(RequireError::Account(address));
-                },
-            }
-        } else {
-            return Err(RequireError::Account(address));
+        let balance = match self.accounts.get(&address) {
+            Some(&AccountChange::Full { balance, .. }) |
+            Some(&AccountChange::Create { balance, .. }) => balance + topup,
+            Some(&AccountChange::Nonexist(_)) => topup,
+            _ => {
+                // Although creation will clean up storage and
+                // code, the balance will be added if it was
+                // existing in prior. So if it is IncreaseBalance
+                // or DecreaseBalance, we need to ask for the
+                // account first.
+                return Err(RequireError::Account(address));
+            },
         };
 
-        self.codes.remove(&address);
-        self.insert_account(account);
+        self.replace_account(address, AccountChange::Create {
+            address, code: Arc::new(Vec::new()), nonce: A::initial_create_nonce(),
+            balance, storage: Storage::new(address, false),
+        });
 
         Ok(())
     }
 
     /// Deposit code in to a created account. Only usable in a newly
     /// created account.
-    pub fn code_deposit(&mut self, address: Address, new_code: Rc<Vec<u8>>) {
-        match self.accounts.get_mut(&address).unwrap() {
+    pub fn code_deposit(&mut self, address: Address, new_code: Arc<Vec<u8>>) {
+        let previous = match self.accounts.get_mut(&address).unwrap() {
             AccountChange::Create { ref mut code, .. } => {
-                *code = new_code;
+                replace(code, new_code)
             },
             _ => panic!(),
-        }
+        };
+        self.record(JournalEntry::Code(address, previous));
     }
 
     /// Increase the balance of an account. The account will be
     /// created if it is nonexist in the beginning.
     pub fn increase_balance(&mut self, address: Address, topup: U256) {
-        let account = match self.accounts.remove(&address) {
-            Some(AccountChange::Full {
-                address,
-                balance,
-                changing_storage,
-                code,
-                nonce,
-            }) => {
-                AccountChange::Full {
-                    address,
-                    balance: balance + topup,
-                    changing_storage,
-                    code,
-                    nonce,
-                }
-            },
-            Some(AccountChange::IncreaseBalance(address, balance)) => {
-                AccountChange::IncreaseBalance(address, balance + topup)
-            },
-            Some(AccountChange::Create {
-                address,
-                balance,
-                storage,
-                code,
-                nonce,
-            }) => {
-                AccountChange::Create {
-                    address,
-                    balance: balance + topup,
-                    storage,
-                    code,
-                    nonce,
-                }
+        let entry = match self.accounts.get_mut(&address) {
+            Some(&mut AccountChange::Full { ref mut balance, .. }) |
+            Some(&mut AccountChange::Create { ref mut balance, .. }) => {
+                let previous = *balance;
+                *balance = previous + topup;
+                Some(JournalEntry::Balance(address, previous))
             },
-            Some(AccountChange::Nonexist(address)) => {
-                AccountChange::Create {
-                    nonce: A::initial_nonce(),
-                    address,
-                    balance: topup,
-                    storage: Storage::new(address, false),
-                    code: Rc::new(Vec::new())
-                }
+            _ => None,
+        };
+
+        match entry {
+            Some(entry) => {
+                self.record(entry);
+                self.touch(address);
             },
             None => {
-                AccountChange::IncreaseBalance(address, topup)
+                let account = match self.accounts.get(&address) {
+                    Some(&AccountChange::IncreaseBalance(address, balance)) => {
+                        AccountChange::IncreaseBalance(address, balance + topup)
+                    },
+                    Some(&AccountChange::Nonexist(address)) => {
+                        AccountChange::Create {
+                            nonce: A::initial_nonce(),
+                            address,
+                            balance: topup,
+                            storage: Storage::new(address, false),
+                            code: Arc::new(Vec::new())
+                        }
+                    },
+                    _ => {
+                        AccountChange::IncreaseBalance(address, topup)
+                    },
+                };
+                self.replace_account(address, account);
             },
-        };
-        self.insert_account(account);
+        }
     }
 
     /// Decrease the balance of an account. The account will be
     /// created if it is nonexist in the beginning.
     pub fn decrease_balance(&mut self, address: Address, withdraw: U256) {
-        let account = match self.accounts.remove(&address) {
-            Some(AccountChange::Full {
-                address,
-                balance,
-                changing_storage,
-                code,
-                nonce,
-            }) => {
-                AccountChange::Full {
-                    address,
-                    balance: balance - withdraw,
-                    changing_storage,
-                    code,
-                    nonce,
-                }
+        let entry = match self.accounts.get_mut(&address) {
+            Some(&mut AccountChange::Full { ref mut balance, .. }) |
+            Some(&mut AccountChange::Create { ref mut balance, .. }) => {
+                let previous = *balance;
+                *balance = previous - withdraw;
+                JournalEntry::Balance(address, previous)
             },
-            Some(AccountChange::Create {
-                address,
-                balance,
-                storage,
-                code,
-                nonce,
-            }) => {
-                AccountChange::Create {
-                    address,
-                    balance: balance - withdraw,
-                    storage,
-                    code,
-                    nonce,
-                }
-            },
-            Some(AccountChange::IncreaseBalance(_, _)) => panic!(),
-            Some(AccountChange::Nonexist(_)) => panic!(),
+            Some(&mut AccountChange::IncreaseBalance(_, _)) => panic!(),
+            Some(&mut AccountChange::Nonexist(_)) => panic!(),
             None => panic!(),
         };
-        self.insert_account(account);
+        self.record(entry);
+        self.touch(address);
     }
 
     /// Set nonce of an account. If the account is not already
     /// commited, returns a `RequireError`. The account will be
     /// created if it is nonexist in the beginning.
     pub fn set_nonce(&mut self, address: Address, new_nonce: U256) -> Result<(), RequireError> {
-        match self.accounts.get_mut(&address) {
+        let entry = match self.accounts.get_mut(&address) {
             Some(&mut AccountChange::Full {
                 ref mut nonce,
                 ..
-            }) => {
-                *nonce = new_nonce;
-                Ok(())
-            },
+            }) |
             Some(&mut AccountChange::Create {
                 ref mut nonce,
                 ..
             }) => {
-                *nonce = new_nonce;
-                Ok(())
+                JournalEntry::Nonce(address, replace(nonce, new_nonce))
             },
-            Some(val) => {
-                let is_nonexist;
-                match val {
-                    AccountChange::Nonexist(_) => { is_nonexist = true; }
-                    _ => { is_nonexist = false; }
-                }
-                if is_nonexist {
-                    *val = AccountChange::Create {
-                        nonce: new_nonce,
-                        address,
-                        balance: U256::zero(),
-                        storage: Storage::new(address, false),
-                        code: Rc::new(Vec::new())
-                    };
-                    Ok(())
-                } else {
-                    Err(RequireError::Account(address))
-                }
-            }
-            None => {
-                Err(RequireError::Account(address))
+            Some(val @ &mut AccountChange::Nonexist(_)) => {
+                let previous = replace(val, AccountChange::Create {
+                    nonce: new_nonce,
+                    address,
+                    balance: U256::zero(),
+                    storage: Storage::new(address, false),
+                    code: Arc::new(Vec::new())
+                });
+                JournalEntry::Account(address, Some(previous))
             },
-        }
+            _ => {
+                return Err(RequireError::Account(address));
+            },
+        };
+        self.record(entry);
+        Ok(())
     }
 
     /// Delete an account from this account state. The account is set
     /// to null.
     pub fn remove(&mut self, address: Address) -> Result<(), RequireError> {
-        self.codes.remove(&address);
-        self.insert_account(AccountChange::Nonexist(address));
+        self.replace_account(address, AccountChange::Nonexist(address));
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patch::EmbeddedAccountPatch;
+    use std::str::FromStr;
+
+    fn address(value: &str) -> Address {
+        Address::from_str(value).unwrap()
+    }
+
+    fn full(address: Address, balance: u64) -> AccountCommitment {
+        AccountCommitment::Full {
+            nonce: U256::zero(),
+            address,
+            balance: U256::from(balance),
+            code: Arc::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn revert_checkpoint_restores_changes() {
+        let a = address("0x1000000000000000000000000000000000000000");
+        let b = address("0x2000000000000000000000000000000000000000");
+        let mut state = AccountState::<EmbeddedAccountPatch>::default();
+        state.commit(full(a, 100)).unwrap();
+        state.commit(AccountCommitment::Storage { address: a, index: U256::one(), value: M256::from(5u64) }).unwrap();
+        state.commit(AccountCommitment::Nonexist(b)).unwrap();
+
+        state.checkpoint();
+        state.increase_balance(a, U256::from(10u64));
+        state.storage_write(a, U256::one(), M256::from(7u64)).unwrap();
+        state.set_nonce(a, U256::one()).unwrap();
+
+        state.checkpoint();
+        state.create(b, U256::from(3u64)).unwrap();
+        state.storage_write(b, U256::from(2u64), M256::from(9u64)).unwrap();
+        state.code_deposit(b, Arc::new(vec![0x00]));
+        state.commit_checkpoint();
+        assert_eq!(state.balance(b).unwrap(), U256::from(3u64));
+
+        state.revert_checkpoint();
+        assert_eq!(state.balance(a).unwrap(), U256::from(100u64));
+        assert_eq!(state.nonce(a).unwrap(), U256::zero());
+        assert_eq!(state.storage_read(a, U256::one()).unwrap(), M256::from(5u64));
+        assert_eq!(state.exists(b).unwrap(), false);
+
+        state.checkpoint();
+        state.decrease_balance(a, U256::from(40u64));
+        state.commit_checkpoint();
+        assert_eq!(state.balance(a).unwrap(), U256::from(60u64));
+    }
+
+    #[test]
+    fn revert_checkpoint_keeps_commitments() {
+        let a = address("0x1000000000000000000000000000000000000000");
+        let b = address("0x2000000000000000000000000000000000000000");
+        let mut state = AccountState::<EmbeddedAccountPatch>::default();
+        state.commit(full(b, 0)).unwrap();
+
+        state.checkpoint();
+        state.increase_balance(a, U256::from(10u64));
+        state.increase_balance(b, U256::from(10u64));
+        state.commit(full(a, 50)).unwrap();
+        state.commit(AccountCommitment::Storage { address: b, index: U256::one(), value: M256::from(4u64) }).unwrap();
+        assert_eq!(state.balance(a).unwrap(), U256::from(60u64));
+
+        state.revert_checkpoint();
+        assert_eq!(state.balance(a).unwrap(), U256::from(50u64));
+        assert_eq!(state.balance(b).unwrap(), U256::zero());
+        assert_eq!(state.storage_read(b, U256::one()).unwrap(), M256::from(4u64));
+    }
+}
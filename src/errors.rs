@@ -1,5 +1,8 @@
 //! VM errors
 
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
 use bigint::{Address, U256};
 
 #[derive(Debug, Clone)]
@@ -15,6 +18,20 @@ pub enum PreExecutionError {
     InsufficientGasLimit,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// Errors when estimating the gas limit of a transaction.
+pub enum EstimateError {
+    /// The highest gas limit to try is smaller than the intrinsic gas
+    /// required.
+    InsufficientGasLimit,
+    /// The transaction fails even with the highest gas limit. Holds
+    /// the error and the returned data, which is the revert reason
+    /// when the error is `Revert`.
+    Failed(OnChainError, Vec<u8>),
+    /// The VM does not support an operation of the transaction.
+    NotSupported(NotSupportedError),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 /// Errors that can be written on chain.
 pub enum OnChainError {
@@ -0,0 +1,210 @@
+//! Gas estimation of transactions.
+
+#[cfg(feature = "std")] use std::marker::PhantomData;
+#[cfg(not(feature = "std"))] use core::marker::PhantomData;
+use bigint::{U256, H256, Gas};
+
+use super::errors::{RequireError, CommitError, EstimateError};
+use super::{TransactionVM, ValidTransaction, HeaderParams, AccountState, BlockhashState,
+            AccountCommitment, Memory, Patch, VM, VMStatus};
+
+/// Finds the smallest gas limit at which a transaction succeeds, by
+/// binary search over the gas limit of the transaction.
+///
+/// The gas used by an execution is a lower bound only. Refunds are
+/// deducted after the execution, and with the 63/64 rule, a call or
+/// create needs more gas available than it finally uses. Every
+/// attempt executes the transaction on a fresh `TransactionVM`, but
+/// accounts and block hashes committed to the estimator are kept
+/// between attempts.
+pub struct GasEstimator<M, P: Patch> {
+    transaction: ValidTransaction,
+    block: HeaderParams,
+    account_state: AccountState<P::Account>,
+    blockhash_state: BlockhashState,
+    /// Highest gas limit known to fail.
+    lower: Gas,
+    /// Lowest gas limit known to succeed, once `succeeded` is set.
+    upper: Gas,
+    succeeded: bool,
+    probe: Option<Gas>,
+    _marker: PhantomData<M>,
+}
+
+impl<M: Memory + Default, P: Patch> GasEstimator<M, P> {
+    /// Create a new estimator for the transaction. Its gas limit is
+    /// the highest gas limit tried, usually the block gas limit. The
+    /// caller must be able to pay for the transaction at that limit.
+    pub fn new(transaction: ValidTransaction, block: HeaderParams) -> Self {
+        let lower = transaction.intrinsic_gas::<P>() - Gas::from(1u64);
+        let upper = transaction.gas_limit;
+
+        GasEstimator {
+            transaction,
+            block,
+            account_state: AccountState::default(),
+            blockhash_state: BlockhashState::default(),
+            lower,
+            upper,
+            succeeded: false,
+            probe: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Commit an account information to the estimator. This should
+    /// be used when receiving `RequireError`.
+    pub fn commit_account(&mut self, commitment: AccountCommitment) -> Result<(), CommitError> {
+        self.account_state.commit(commitment)
+    }
+
+    /// Commit a block hash to the estimator. This should be used when
+    /// receiving `RequireError`.
+    pub fn commit_blockhash(&mut self, number: U256, hash: H256) -> Result<(), CommitError> {
+        self.blockhash_state.commit(number, hash)
+    }
+
+    fn attempt(&self, gas_limit: Gas) -> Result<TransactionVM<M, P>, RequireError> {
+        let mut transaction = self.transaction.clone();
+        transaction.gas_limit = gas_limit;
+
+        let mut vm = TransactionVM::with_states(
+            transaction, self.block.clone(),
+            self.account_state.clone(), self.blockhash_state.clone());
+        vm.fire()?;
+        Ok(vm)
+    }
+
+    /// Estimate the gas limit. Returns `RequireError` when an account
+    /// or block hash is needed. After committing it, call this
+    /// function again and the search continues where it stopped.
+    ///
+    /// If the transaction fails at the highest gas limit, the error
+    /// and the returned data, which holds the revert reason, are
+    /// reported.
+    pub fn estimate(&mut self) -> Result<Result<Gas, EstimateError>, RequireError> {
+        if !self.succeeded {
+            if self.upper <= self.lower {
+                return Ok(Err(EstimateError::InsufficientGasLimit));
+            }
+
+            let vm = self.attempt(self.upper)?;
+            match vm.status() {
+                VMStatus::ExitedOk => (),
+                VMStatus::ExitedErr(err) =>
+                    return Ok(Err(EstimateError::Failed(err, vm.out().to_vec()))),
+                VMStatus::ExitedNotSupported(err) =>
+                    return Ok(Err(EstimateError::NotSupported(err))),
+                VMStatus::Running => panic!(),
+            }
+
+            // Used gas already has refunds deducted, and the execution
+            // can not use more gas than its limit, so anything below
+            // it fails.
+            let used_gas = vm.used_gas();
+            if used_gas - Gas::from(1u64) > self.lower {
+                self.lower = used_gas - Gas::from(1u64);
+            }
+
+            // Try first the gas before refunds, plus what the 63/64
+            // rule keeps back in the caller.
+            let mut probe = used_gas + vm.refunded_gas();
+            if P::call_create_l64_after_gas() {
+                probe = probe + probe / Gas::from(63u64);
+            }
+            self.probe = Some(probe);
+            self.succeeded = true;
+        }
+
+        while self.lower + Gas::from(1u64) < self.upper {
+            let gas_limit = match self.probe {
+                Some(probe) if probe > self.lower && probe < self.upper => probe,
+                _ => (self.lower + self.upper) / Gas::from(2u64),
+            };
+
+            let vm = self.attempt(gas_limit)?;
+            self.probe = None;
+            if vm.status() == VMStatus::ExitedOk {
+                self.upper = gas_limit;
+            } else {
+                self.lower = gas_limit;
+            }
+        }
+
+        Ok(Ok(self.upper))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use bigint::*;
+    use test_util::{Account, account, contract, transaction, header, answer_all};
+
+    fn execute<P: Patch>(accounts: &[Account], gas_limit: Gas) -> (SeqTransactionVM<P>, usize) {
+        let mut vm = SeqTransactionVM::<P>::new(transaction(contract(), gas_limit), header());
+        let ((), queries) = answer_all(accounts, &mut vm, |vm| vm.fire(), |vm, commitment| vm.commit_account(commitment));
+        (vm, queries)
+    }
+
+    fn estimate<P: Patch>(accounts: &[Account]) -> (Result<Gas, EstimateError>, usize) {
+        let mut estimator = SeqGasEstimator::<P>::new(transaction(contract(), Gas::from(1000000u64)), header());
+        answer_all(accounts, &mut estimator, |estimator| estimator.estimate(),
+                   |estimator, commitment| estimator.commit_account(commitment))
+    }
+
+    #[test]
+    fn estimate_sstore() {
+        let accounts = [account("0x2000000000000000000000000000000000000000", "0x600160005500", 0)];
+        let (result, queries) = estimate::<EmbeddedPatch>(&accounts);
+        assert_eq!(result.unwrap(), Gas::from(41006u64));
+
+        let (vm, expected_queries) = execute::<EmbeddedPatch>(&accounts, Gas::from(41006u64));
+        assert_eq!(vm.status(), VMStatus::ExitedOk);
+        assert_eq!(queries, expected_queries);
+    }
+
+    #[test]
+    fn estimate_with_refund() {
+        let accounts = [account("0x2000000000000000000000000000000000000000", "0x600060005500", 1)];
+        let (result, _) = estimate::<EmbeddedPatch>(&accounts);
+        assert_eq!(result.unwrap(), Gas::from(26006u64));
+
+        let (vm, _) = execute::<EmbeddedPatch>(&accounts, Gas::from(26006u64));
+        assert_eq!(vm.status(), VMStatus::ExitedOk);
+        assert_eq!(vm.used_gas(), Gas::from(13003u64));
+    }
+
+    #[test]
+    fn estimate_with_l64_call() {
+        let accounts = [
+            account("0x2000000000000000000000000000000000000000",
+                    concat!("0x6000600060006000600073",
+                            "3000000000000000000000000000000000000000",
+                            "5af1602557fe5b00"), 0),
+            account("0x3000000000000000000000000000000000000000", "0x600160005500", 0),
+        ];
+        let (result, _) = estimate::<EmbeddedPatch>(&accounts);
+        let gas = result.unwrap();
+
+        let (vm, _) = execute::<EmbeddedPatch>(&accounts, gas);
+        assert_eq!(vm.status(), VMStatus::ExitedOk);
+        assert!(vm.used_gas() < gas);
+
+        let (vm, _) = execute::<EmbeddedPatch>(&accounts, gas - Gas::from(1u64));
+        assert!(vm.status() != VMStatus::ExitedOk);
+    }
+
+    #[test]
+    fn estimate_reverted() {
+        let accounts = [account("0x2000000000000000000000000000000000000000", "0x602a60005260206000fd", 0)];
+        let (result, _) = estimate::<VMTestPatch>(&accounts);
+        match result {
+            Err(EstimateError::Failed(OnChainError::Revert, out)) => {
+                assert_eq!(out.len(), 32);
+                assert_eq!(out[31], 0x2a);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Code analysis and the analyzed code cache.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
+#[cfg(feature = "std")] use std::collections::HashMap as Map;
+#[cfg(not(feature = "std"))] use alloc::collections::BTreeMap as Map;
+#[cfg(feature = "std")] use std::sync::{Mutex, MutexGuard};
+#[cfg(not(feature = "std"))] use core::cell::{RefCell, RefMut};
+#[cfg(feature = "std")] use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(feature = "std"))] use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")] use std::marker::PhantomData;
+#[cfg(not(feature = "std"))] use core::marker::PhantomData;
+#[cfg(feature = "std")] use std::cmp::min;
+#[cfg(not(feature = "std"))] use core::cmp::min;
+
+use bigint::{H256, Gas};
+use sha3::{Digest, Keccak256};
+
+use errors::OnChainError;
+use util::opcode::Opcode;
+use ::{Instruction, Patch, PC, Valids};
+use super::cost::static_gas_cost;
+use super::check::block_pop_push;
+
+const NOT_AN_INSTRUCTION: usize = usize::max_value();
+
+/// Code position of the instruction after the one at `position`.
+fn following_position(code: &[u8], position: usize) -> usize {
+    let opcode: Opcode = code[position].into();
+    match opcode {
+        Opcode::PUSH(v) => min(position + v + 1, code.len()),
+        _ => position + 1,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Straight-line instructions, starting at the beginning of the code
+/// or at a `JUMPDEST`, and ending before the next `JUMPDEST` or after
+/// the first instruction that cannot be checked ahead of time (a
+/// jump, a halting or invalid instruction, or one with a dynamic gas
+/// cost or touching memory).
+pub struct BasicBlock {
+    /// Code position of the first instruction.
+    pub start: usize,
+    /// Code position after the last instruction.
+    pub end: usize,
+    /// Sum of the gas cost of the instructions checked ahead of time,
+    /// which are all of them except an ending one that cannot be.
+    pub static_gas: Gas,
+    /// Stack items needed on entry by the instructions checked ahead
+    /// of time.
+    pub stack_required: usize,
+    /// Maximum stack growth from the entry over the instructions
+    /// checked ahead of time.
+    pub stack_growth: usize,
+}
+
+struct BlockBuilder {
+    start: usize,
+    static_gas: Gas,
+    height: isize,
+    stack_required: isize,
+    stack_growth: isize,
+}
+
+impl BlockBuilder {
+    fn new(start: usize) -> Self {
+        BlockBuilder {
+            start,
+            static_gas: Gas::zero(),
+            height: 0,
+            stack_required: 0,
+            stack_growth: 0,
+        }
+    }
+
+    fn add(&mut self, gas: Gas, pop: usize, push: usize) {
+        let (pop, push) = (pop as isize, push as isize);
+        self.static_gas = self.static_gas + gas;
+        if pop - self.height > self.stack_required {
+            self.stack_required = pop - self.height;
+        }
+        self.height = self.height - pop + push;
+        if self.height > self.stack_growth {
+            self.stack_growth = self.height;
+        }
+    }
+
+    fn finish(self, end: usize) -> BasicBlock {
+        BasicBlock {
+            start: self.start,
+            end,
+            static_gas: self.static_gas,
+            stack_required: self.stack_required as usize,
+            stack_growth: self.stack_growth as usize,
+        }
+    }
+}
+
+/// Code decoded ahead of execution.
+pub struct AnalyzedCode {
+    valids: Valids,
+    instructions: Vec<Result<Instruction, OnChainError>>,
+    positions: Vec<usize>,
+    indexes: Vec<usize>,
+    in_block: Vec<bool>,
+    blocks: Vec<BasicBlock>,
+}
+
+impl AnalyzedCode {
+    /// Decode the code with the given patch.
+    pub fn new<P: Patch>(code: &[u8]) -> Self {
+        let valids = Valids::new(code);
+        let mut instructions = Vec::new();
+        let mut positions = Vec::new();
+        let mut indexes = Vec::with_capacity(code.len());
+        indexes.resize(code.len(), NOT_AN_INSTRUCTION);
+
+        let mut position = 0;
+        while position < code.len() {
+            indexes[position] = instructions.len();
+            positions.push(position);
+            instructions.push(PC::<P>::new(code, &valids, &position).peek());
+            position = following_position(code, position);
+        }
+
+        let mut blocks = Vec::new();
+        let mut in_block = Vec::with_capacity(instructions.len());
+        let mut block: Option<BlockBuilder> = None;
+        for (i, instruction) in instructions.iter().enumerate() {
+            if let Ok(Instruction::JUMPDEST) = *instruction {
+                if let Some(block) = block.take() {
+                    blocks.push(block.finish(positions[i]));
+                }
+            }
+            let mut current = block.take().unwrap_or_else(|| BlockBuilder::new(positions[i]));
+
+            let checked = match *instruction {
+                Ok(instruction) => match (block_pop_push(instruction), static_gas_cost::<P>(instruction)) {
+                    (Some((pop, push)), Some(gas)) => {
+                        current.add(gas, pop, push);
+                        true
+                    },
+                    _ => false,
+                },
+                Err(_) => false,
+            };
+            in_block.push(checked);
+
+            if checked {
+                block = Some(current);
+            } else {
+                blocks.push(current.finish(positions.get(i + 1).cloned().unwrap_or(code.len())));
+            }
+        }
+        if let Some(block) = block.take() {
+            blocks.push(block.finish(code.len()));
+        }
+
+        AnalyzedCode { valids, instructions, positions, indexes, in_block, blocks }
+    }
+
+    /// Only find the valid jump destinations of the code. Nothing is
+    /// decoded ahead of execution and the code has no basic blocks,
+    /// so this is the cheaper analysis when the code is not cached.
+    pub fn valids_only(code: &[u8]) -> Self {
+        AnalyzedCode {
+            valids: Valids::new(code),
+            instructions: Vec::new(),
+            positions: Vec::new(),
+            indexes: Vec::new(),
+            in_block: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Valid jump destinations of the code.
+    pub fn valids(&self) -> &Valids {
+        &self.valids
+    }
+
+    /// Number of decoded instructions.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Returns true if no instruction was decoded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The decoded instruction at the given code position. Returns
+    /// `None` if no instruction starts at the position.
+    pub fn instruction(&self, position: usize) -> Option<Result<Instruction, OnChainError>> {
+        match self.indexes.get(position) {
+            Some(&index) if index != NOT_AN_INSTRUCTION => Some(self.instructions[index].clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the instruction at the given code position is
+    /// checked ahead of time with its basic block.
+    pub fn is_in_block(&self, position: usize) -> bool {
+        match self.indexes.get(position) {
+            Some(&index) if index != NOT_AN_INSTRUCTION => self.in_block[index],
+            _ => false,
+        }
+    }
+
+    /// Code position of the instruction after the one at the given
+    /// position of `code`, which must be the analyzed code.
+    pub fn next_position(&self, code: &[u8], position: usize) -> usize {
+        match self.indexes.get(position) {
+            Some(&index) if index != NOT_AN_INSTRUCTION => match self.positions.get(index + 1) {
+                Some(&next) => next,
+                None => code.len(),
+            },
+            _ => following_position(code, position),
+        }
+    }
+
+    /// The basic block starting at the given code position, if any.
+    pub fn block(&self, position: usize) -> Option<&BasicBlock> {
+        match self.blocks.binary_search_by_key(&position, |block| block.start) {
+            Ok(index) => Some(&self.blocks[index]),
+            Err(_) => None,
+        }
+    }
+
+    /// All basic blocks of the code, in order.
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+}
+
+type CodeEntries = Map<H256, (Arc<AnalyzedCode>, usize)>;
+
+/// A cache of analyzed code keyed by code hash, to be shared by VMs
+/// running with the same patch. When full, the least recently used
+/// code is evicted. With the `std` feature, the cache can be shared
+/// across threads.
+pub struct CodeCache<P: Patch> {
+    capacity: usize,
+    #[cfg(feature = "std")]
+    entries: Mutex<CodeEntries>,
+    #[cfg(not(feature = "std"))]
+    entries: RefCell<CodeEntries>,
+    clock: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    // Patches are only used as types, so the cache can be shared
+    // between threads whatever the patch is.
+    _marker: PhantomData<fn() -> P>,
+}
+
+impl<P: Patch> CodeCache<P> {
+    /// Create a new cache holding at most `capacity` codes.
+    pub fn new(capacity: usize) -> Self {
+        CodeCache {
+            capacity,
+            #[cfg(feature = "std")]
+            entries: Mutex::new(Map::new()),
+            #[cfg(not(feature = "std"))]
+            entries: RefCell::new(Map::new()),
+            clock: AtomicUsize::new(0),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn entries(&self) -> MutexGuard<CodeEntries> {
+        self.entries.lock().unwrap()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn entries(&self) -> RefMut<CodeEntries> {
+        self.entries.borrow_mut()
+    }
+
+    /// Get the analyzed code, analyzing it if it is not cached. The
+    /// code is hashed first, unless the cache has no capacity.
+    pub fn get(&self, code: &[u8]) -> Arc<AnalyzedCode> {
+        if self.capacity == 0 {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return Arc::new(AnalyzedCode::new::<P>(code));
+        }
+        self.get_with_hash(H256::from(Keccak256::digest(code).as_slice()), code)
+    }
+
+    /// Get the analyzed code whose Keccak hash is already known,
+    /// analyzing it if it is not cached.
+    pub fn get_with_hash(&self, hash: H256, code: &[u8]) -> Arc<AnalyzedCode> {
+        let clock = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let mut entries = self.entries();
+        if let Some(entry) = entries.get_mut(&hash) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            entry.1 = clock;
+            return entry.0.clone();
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let analyzed = Arc::new(AnalyzedCode::new::<P>(code));
+        if self.capacity == 0 {
+            return analyzed;
+        }
+        if entries.len() >= self.capacity {
+            let oldest = entries.iter()
+                .min_by_key(|&(_, &(_, used))| used)
+                .map(|(hash, _)| *hash);
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(hash, (analyzed.clone(), clock));
+        analyzed
+    }
+
+    /// Maximum number of cached codes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of cached codes.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Returns true if no code is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of lookups that found the code in the cache.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups that had to analyze the code.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigint::{U256, M256, Address};
+    use hexutil::read_hex;
+    use ::{EmbeddedPatch, VMTestPatch, Context, HeaderParams, SeqContextVM, VM, VMStatus, ExecutionMode};
+
+    #[test]
+    fn analyze_blocks() {
+        // PUSH1 4, JUMP, INVALID, JUMPDEST, PUSH2 0x0102, POP, STOP
+        let code = [0x60, 0x04, 0x56, 0xfe, 0x5b, 0x61, 0x01, 0x02, 0x50, 0x00];
+        let analyzed = AnalyzedCode::new::<EmbeddedPatch>(&code);
+
+        assert_eq!(analyzed.len(), 7);
+        assert_eq!(analyzed.instruction(0), Some(Ok(Instruction::PUSH(M256::from(4u64)))));
+        assert_eq!(analyzed.instruction(1), None);
+        assert_eq!(analyzed.instruction(3), Some(Err(OnChainError::InvalidOpcode)));
+        assert_eq!(analyzed.instruction(5), Some(Ok(Instruction::PUSH(M256::from(0x0102u64)))));
+        assert_eq!(analyzed.next_position(&code, 5), 8);
+        assert_eq!(analyzed.next_position(&code, 9), 10);
+        assert!(analyzed.valids().is_valid(4));
+
+        assert_eq!(analyzed.blocks(), &[
+            BasicBlock { start: 0, end: 3, static_gas: Gas::from(3u64), stack_required: 0, stack_growth: 1 },
+            BasicBlock { start: 3, end: 4, static_gas: Gas::zero(), stack_required: 0, stack_growth: 0 },
+            BasicBlock { start: 4, end: 10, static_gas: Gas::from(1u64 + 3 + 2), stack_required: 0, stack_growth: 1 },
+        ]);
+        assert_eq!(analyzed.block(4).unwrap().end, 10);
+        assert_eq!(analyzed.block(5), None);
+        assert!(analyzed.is_in_block(0));
+        assert!(!analyzed.is_in_block(2));
+        assert!(!analyzed.is_in_block(9));
+    }
+
+    #[test]
+    fn block_stack_bounds() {
+        // ADD, DUP2, CALLER, SWAP1, MSTORE, POP
+        let code = [0x01, 0x81, 0x33, 0x90, 0x52, 0x50];
+        let analyzed = AnalyzedCode::new::<EmbeddedPatch>(&code);
+
+        assert_eq!(analyzed.blocks(), &[
+            BasicBlock { start: 0, end: 5, static_gas: Gas::from(3u64 * 3 + 2), stack_required: 3, stack_growth: 1 },
+            BasicBlock { start: 5, end: 6, static_gas: Gas::from(2u64), stack_required: 1, stack_growth: 0 },
+        ]);
+    }
+
+    #[test]
+    fn cache_hits_and_evicts() {
+        let cache = CodeCache::<EmbeddedPatch>::new(2);
+        cache.get(&[0x00]);
+        cache.get(&[0x01]);
+        cache.get(&[0x00]);
+        cache.get(&[0x02]);
+        assert_eq!(cache.len(), 2);
+
+        cache.get(&[0x00]);
+        cache.get(&[0x01]);
+        assert_eq!(cache.hits(), 2);
+        assert_eq!(cache.misses(), 4);
+    }
+
+    #[test]
+    fn valids_only_decodes_on_the_fly() {
+        let code = [0x60, 0x04, 0x56, 0xfe, 0x5b, 0x61, 0x01, 0x02, 0x50, 0x00];
+        let analyzed = AnalyzedCode::valids_only(&code);
+
+        assert!(analyzed.is_empty());
+        assert!(analyzed.valids().is_valid(4));
+        assert_eq!(analyzed.instruction(0), None);
+        assert!(!analyzed.is_in_block(0));
+        assert_eq!(analyzed.next_position(&code, 0), 2);
+        assert_eq!(analyzed.next_position(&code, 5), 8);
+        assert_eq!(analyzed.next_position(&code, 9), 10);
+        assert!(analyzed.blocks().is_empty());
+    }
+
+    #[test]
+    fn cache_with_known_hash() {
+        let code = [0x60, 0x01, 0x00];
+        let hash = H256::from(Keccak256::digest(&code).as_slice());
+        let cache = CodeCache::<EmbeddedPatch>::new(1);
+        let analyzed = cache.get_with_hash(hash, &code);
+        assert!(Arc::ptr_eq(&analyzed, &cache.get(&code)));
+        assert_eq!(cache.hits(), 1);
+
+        let uncached = CodeCache::<EmbeddedPatch>::new(0);
+        assert_eq!(uncached.get(&code).len(), 2);
+        assert!(uncached.is_empty());
+        assert_eq!(uncached.misses(), 1);
+    }
+
+    fn run(code: &str, gas_limit: u64, mode: ExecutionMode) -> (VMStatus, Gas, Vec<u8>) {
+        let context = Context {
+            address: Address::default(),
+            caller: Address::default(),
+            code: Arc::new(read_hex(code).unwrap()),
+            data: Arc::new(Vec::new()),
+            gas_limit: Gas::from(gas_limit),
+            gas_price: Gas::zero(),
+            origin: Address::default(),
+            value: U256::zero(),
+            apprent_value: U256::zero(),
+            is_system: false,
+            is_static: false,
+        };
+        let mut vm = SeqContextVM::<VMTestPatch>::new(context, HeaderParams {
+            beneficiary: Address::default(),
+            timestamp: 0,
+            number: U256::zero(),
+            difficulty: U256::zero(),
+            gas_limit: Gas::zero(),
+        });
+        vm.set_execution_mode(mode);
+        vm.fire().unwrap();
+        (vm.status(), vm.used_gas(), vm.out().to_vec())
+    }
+
+    #[test]
+    fn basic_block_mode_matches() {
+        let cases = [
+            // Returns `1 + 2 + GAS`.
+            ("0x60016002015a0160005260206000f3", 100000),
+            // Loops until it runs out of gas.
+            ("0x5b600150600056", 100),
+            // Stack underflow in the middle of a block.
+            ("0x6001600101016000", 100000),
+            // Runs out of gas in the middle of a block.
+            ("0x60016001600100", 7),
+        ];
+
+        for &(code, gas_limit) in cases.iter() {
+            assert_eq!(run(code, gas_limit, ExecutionMode::BasicBlock),
+                       run(code, gas_limit, ExecutionMode::PerInstruction));
+        }
+    }
+}
@@ -3,7 +3,7 @@
 use bigint::{U256, M256, Gas};
 
 use ::{Memory, Instruction, Patch};
-use errors::{OnChainError, NotSupportedError, EvalOnChainError};
+use errors::{OnChainError, NotSupportedError, EvalOnChainError, RequireError};
 use eval::{State, Runtime, ControlCheck};
 
 use super::util::check_range;
@@ -57,7 +57,7 @@ pub fn check_support<M: Memory + Default, P: Patch>(instruction: Instruction, st
                 state.stack.peek(5).unwrap().into(), state.stack.peek(6).unwrap().into())?;
             Ok(())
         },
-        Instruction::DELEGATECALL => {
+        Instruction::DELEGATECALL | Instruction::STATICCALL => {
             state.memory.check_write_range(
                 state.stack.peek(4).unwrap().into(), state.stack.peek(5).unwrap().into())?;
             Ok(())
@@ -376,3 +376,65 @@ pub fn check_opcode<M: Memory + Default, P: Patch>(instruction: Instruction, sta
         },
     }
 }
+
+/// Stack items popped and pushed by an instruction that can be checked
+/// once for a whole basic block: its gas cost is static, it does not
+/// touch memory, and it does not change the control flow. Returns
+/// `None` for other instructions.
+pub fn block_pop_push(instruction: Instruction) -> Option<(usize, usize)> {
+    Some(match instruction {
+        Instruction::ADD | Instruction::MUL | Instruction::SUB | Instruction::DIV |
+        Instruction::SDIV | Instruction::MOD | Instruction::SMOD |
+        Instruction::SIGNEXTEND => (2, 1),
+        Instruction::ADDMOD | Instruction::MULMOD => (3, 1),
+
+        Instruction::LT | Instruction::GT | Instruction::SLT | Instruction::SGT |
+        Instruction::EQ | Instruction::AND | Instruction::OR | Instruction::XOR |
+        Instruction::BYTE | Instruction::SHL | Instruction::SHR |
+        Instruction::SAR => (2, 1),
+        Instruction::ISZERO | Instruction::NOT => (1, 1),
+
+        Instruction::BALANCE | Instruction::CALLDATALOAD | Instruction::EXTCODESIZE |
+        Instruction::EXTCODEHASH | Instruction::BLOCKHASH | Instruction::SLOAD => (1, 1),
+        Instruction::ADDRESS | Instruction::ORIGIN | Instruction::CALLER |
+        Instruction::CALLVALUE | Instruction::CALLDATASIZE | Instruction::CODESIZE |
+        Instruction::GASPRICE | Instruction::RETURNDATASIZE | Instruction::COINBASE |
+        Instruction::TIMESTAMP | Instruction::NUMBER | Instruction::DIFFICULTY |
+        Instruction::GASLIMIT | Instruction::PC | Instruction::MSIZE => (0, 1),
+
+        Instruction::POP => (1, 0),
+        Instruction::JUMPDEST => (0, 0),
+        Instruction::PUSH(_) => (0, 1),
+        Instruction::DUP(v) => (v, v+1),
+        Instruction::SWAP(v) => (v+1, v+1),
+
+        _ => return None,
+    })
+}
+
+/// Check an instruction of a basic block whose stack bounds were
+/// already checked. Only the accounts, storage and blockhashes it
+/// requires are left to check.
+pub fn check_block_opcode<M: Memory + Default, P: Patch>(instruction: Instruction, state: &State<M, P>, runtime: &Runtime) -> Result<(), RequireError> {
+    match instruction {
+        Instruction::BALANCE => {
+            state.account_state.require(state.stack.peek(0).unwrap().into())?;
+        },
+        Instruction::EXTCODESIZE | Instruction::EXTCODEHASH => {
+            state.account_state.require_code(state.stack.peek(0).unwrap().into())?;
+        },
+        Instruction::BLOCKHASH => {
+            let current_number = runtime.block.number;
+            let number: U256 = state.stack.peek(0).unwrap().into();
+            if !(number >= current_number || current_number - number > U256::from(256u64)) {
+                runtime.blockhash_state.get(number)?;
+            }
+        },
+        Instruction::SLOAD => {
+            state.account_state.require(state.context.address)?;
+            state.account_state.require_storage(state.context.address, state.stack.peek(0).unwrap().into())?;
+        },
+        _ => (),
+    }
+    Ok(())
+}
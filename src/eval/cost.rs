@@ -171,6 +171,14 @@ pub fn memory_cost<M: Memory + Default, P: Patch>(instruction: Instruction, stat
             memory_expand(memory_expand(current, Gas::from(in_from), Gas::from(in_len)),
                           Gas::from(out_from), Gas::from(out_len))
         },
+        Instruction::DELEGATECALL | Instruction::STATICCALL => {
+            let in_from: U256 = stack.peek(2).unwrap().into();
+            let in_len: U256 = stack.peek(3).unwrap().into();
+            let out_from: U256 = stack.peek(4).unwrap().into();
+            let out_len: U256 = stack.peek(5).unwrap().into();
+            memory_expand(memory_expand(current, Gas::from(in_from), Gas::from(in_len)),
+                          Gas::from(out_from), Gas::from(out_len))
+        },
         _ => {
             current
         }
@@ -221,13 +229,22 @@ pub fn gas_cost<M: Memory + Default, P: Patch>(instruction: Instruction, state:
             }
         }
 
-        Instruction::CREATE => G_CREATE.into(),
         Instruction::CREATE2 => {
             let base = G_CREATE;
             let init_code_len = state.stack.peek(2).unwrap().as_u64();
             let sha_addup = G_SHA3WORD * (init_code_len as f32 / 32.0).ceil() as usize;
             (base + sha_addup).into()
         },
+
+        _ => static_gas_cost::<P>(instruction).unwrap(),
+    }
+}
+
+/// Calculate the gas cost of instructions whose cost does not depend
+/// on the state. Returns `None` for other instructions.
+pub fn static_gas_cost<P: Patch>(instruction: Instruction) -> Option<Gas> {
+    Some(match instruction {
+        Instruction::CREATE => G_CREATE.into(),
         Instruction::JUMPDEST => G_JUMPDEST.into(),
         Instruction::SLOAD => P::gas_sload(),
 
@@ -271,7 +288,9 @@ pub fn gas_cost<M: Memory + Default, P: Patch>(instruction: Instruction, state:
         Instruction::BALANCE => P::gas_balance(),
         Instruction::BLOCKHASH => G_BLOCKHASH.into(),
         Instruction::EXTCODEHASH => G_EXTCODEHASH.into(),
-    }
+
+        _ => return None,
+    })
 }
 
 /// Raise gas stipend for CALL and CALLCODE instruction.
@@ -3,8 +3,8 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 use bigint::{U256, M256, Gas, Address};
 use errors::{RequireError, OnChainError};
@@ -21,7 +21,8 @@ use super::cost::code_deposit_gas;
 /// can be stepped as normal. When the machine meets a CALL/CALLCODE
 /// or CREATE instruction, a sub-machine will be created. This
 /// submachine should first call `invoke_call` or
-/// `invoke_create`. After the submachine is finished, it should call
+/// `invoke_create`. If that fails, it should be given back with
+/// `discard_sub`. After the submachine is finished, it should call
 /// `apply_sub`. When the non-invoked transaction is finished, it
 /// should first call `code_deposit` if it is a contract creation
 /// transaction. After that, it should call `finalize`.
@@ -109,7 +110,7 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
             if !P::force_code_deposit() {
                 reset_error_hard!(self, OnChainError::EmptyGas);
             } else {
-                self.state.account_state.code_deposit(self.state.context.address, Rc::new(Vec::new()));
+                self.state.account_state.code_deposit(self.state.context.address, Arc::new(Vec::new()));
             }
         } else {
             self.state.used_gas += deposit_cost;
@@ -208,6 +209,14 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         }
     }
 
+    /// Give a sub runtime created by the current runtime's `derive`
+    /// function back without applying it, because it failed to be
+    /// invoked. Changes made by the sub runtime are reverted.
+    pub fn discard_sub(&mut self, mut sub: Machine<M, P>) {
+        sub.state.account_state.revert_checkpoint();
+        self.state.account_state = sub.state.account_state;
+    }
+
     fn apply_create(&mut self, mut sub: Machine<M, P>) {
         sub.code_deposit();
 
@@ -220,15 +229,17 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
 
         match sub.status() {
             MachineStatus::ExitedOk => {
-                self.state.account_state = sub.state.account_state;
+                sub.state.account_state.commit_checkpoint();
                 self.state.removed = sub.state.removed;
             },
             MachineStatus::ExitedErr(_) => {
+                sub.state.account_state.revert_checkpoint();
                 self.state.stack.pop().unwrap();
                 self.state.stack.push(M256::zero()).unwrap();
             },
             _ => panic!(),
         }
+        self.state.account_state = sub.state.account_state;
     }
 
     fn apply_call(&mut self, mut sub: Machine<M, P>, out_start: U256, out_len: U256) {
@@ -243,16 +254,18 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
 
         match sub.status() {
             MachineStatus::ExitedOk => {
-                self.state.account_state = sub.state.account_state;
+                sub.state.account_state.commit_checkpoint();
                 self.state.removed = sub.state.removed;
-                self.state.ret = Rc::new(Vec::new());
+                self.state.ret = Arc::new(Vec::new());
             },
             MachineStatus::ExitedErr(_) => {
+                sub.state.account_state.revert_checkpoint();
                 self.state.stack.pop().unwrap();
                 self.state.stack.push(M256::zero()).unwrap();
                 self.state.ret = sub.state.out.clone();
             },
             _ => panic!(),
         }
+        self.state.account_state = sub.state.account_state;
     }
 }
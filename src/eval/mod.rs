@@ -3,22 +3,23 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(not(feature = "std"))] use alloc::boxed::Box;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 #[cfg(not(feature = "std"))] use core::ops::AddAssign;
 #[cfg(feature = "std")] use std::ops::AddAssign;
+#[cfg(not(feature = "std"))] use core::mem::replace;
+#[cfg(feature = "std")] use std::mem::replace;
 
 use bigint::{M256, U256, Gas, Address};
 use super::pc::Instruction;
 use super::commit::{AccountState, BlockhashState};
 use super::errors::{RequireError, RuntimeError, CommitError, EvalOnChainError,
                     OnChainError, NotSupportedError};
-use super::{Stack, Context, HeaderParams, Patch, PC, PCMut, Valids, Memory,
+use super::{Stack, STACK_LIMIT, Context, HeaderParams, Patch, PC, PCMut, Memory,
             AccountCommitment, Log, Opcode};
 
-use self::check::{check_opcode, check_static, check_support, extra_check_opcode};
+use self::check::{check_opcode, check_static, check_support, extra_check_opcode, check_block_opcode};
 use self::run::run_opcode;
 use self::cost::{gas_refund, AddRefund, gas_stipend, gas_cost, memory_cost, memory_gas};
 
@@ -28,7 +29,7 @@ macro_rules! reset_error_hard {
         $self.state.used_gas = GasUsage::All;
         $self.state.refunded_gas = Gas::zero();
         $self.state.logs = Vec::new();
-        $self.state.out = Rc::new(Vec::new());
+        $self.state.out = Arc::new(Vec::new());
     }
 }
 
@@ -44,7 +45,7 @@ macro_rules! reset_error_not_supported {
         $self.state.used_gas = GasUsage::Some(Gas::zero());
         $self.state.refunded_gas = Gas::zero();
         $self.state.logs = Vec::new();
-        $self.state.out = Rc::new(Vec::new());
+        $self.state.out = Arc::new(Vec::new());
     }
 }
 
@@ -53,6 +54,9 @@ mod run;
 mod check;
 mod util;
 mod lifecycle;
+mod analysis;
+
+pub use self::analysis::{AnalyzedCode, BasicBlock, CodeCache};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GasUsage {
@@ -82,9 +86,9 @@ pub struct State<M, P: Patch> {
     pub context: Context,
 
     /// The current out value.
-    pub out: Rc<Vec<u8>>,
+    pub out: Arc<Vec<u8>>,
     /// Return data buffer.
-    pub ret: Rc<Vec<u8>>,
+    pub ret: Arc<Vec<u8>>,
 
     /// The current memory cost. Note that this is different from
     /// memory gas.
@@ -104,12 +108,34 @@ pub struct State<M, P: Patch> {
     /// Depth of this runtime.
     pub depth: usize,
 
-    /// Code valid maps.
-    pub valids: Valids,
+    /// Analyzed code, holding the valid jump destinations, and the
+    /// decoded instructions if the code came from a `CodeCache`.
+    pub analysis: Arc<AnalyzedCode>,
     /// PC position.
     pub position: usize,
 }
 
+impl<M: Clone, P: Patch> Clone for State<M, P> {
+    fn clone(&self) -> Self {
+        State {
+            memory: self.memory.clone(),
+            stack: self.stack.clone(),
+            context: self.context.clone(),
+            out: self.out.clone(),
+            ret: self.ret.clone(),
+            memory_cost: self.memory_cost,
+            used_gas: self.used_gas,
+            refunded_gas: self.refunded_gas,
+            account_state: self.account_state.clone(),
+            logs: self.logs.clone(),
+            removed: self.removed.clone(),
+            depth: self.depth,
+            analysis: self.analysis.clone(),
+            position: self.position,
+        }
+    }
+}
+
 impl<M, P: Patch> State<M, P> {
     /// Memory gas, part of total used gas.
     pub fn memory_gas(&self) -> Gas {
@@ -130,15 +156,32 @@ impl<M, P: Patch> State<M, P> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How gas and stack bounds are checked.
+pub enum ExecutionMode {
+    /// Check every instruction on its own.
+    PerInstruction,
+    /// Charge the static gas and check the stack bounds once when
+    /// entering a basic block, and check the rest per instruction.
+    /// Falls back to per-instruction checks for a block that would
+    /// run out of gas or stack, so that the results are the same.
+    /// Basic blocks are only found by a `CodeCache`, so the VMs use
+    /// one with no capacity if none is set.
+    BasicBlock,
+}
+
+#[derive(Clone)]
 /// A VM runtime. Only available in eval.
 pub struct Runtime {
     /// The current blockhash commitment states.
     pub blockhash_state: BlockhashState,
     /// Block header.
     pub block: HeaderParams,
+    /// How gas and stack bounds are checked.
+    pub execution_mode: ExecutionMode,
 
     /// Hooks for context history.
-    pub context_history_hooks: Vec<Box<Fn(&Context)>>,
+    pub context_history_hooks: Vec<Arc<Fn(&Context) + Send + Sync>>,
 }
 
 impl Runtime {
@@ -151,6 +194,7 @@ impl Runtime {
     pub fn with_states(block: HeaderParams, blockhash_state: BlockhashState) -> Self {
         Runtime {
             block, blockhash_state,
+            execution_mode: ExecutionMode::PerInstruction,
 
             context_history_hooks: Vec::new(),
         }
@@ -161,6 +205,19 @@ impl Runtime {
 pub struct Machine<M, P: Patch> {
     state: State<M, P>,
     status: MachineStatus,
+    code_cache: Option<Arc<CodeCache<P>>>,
+    block_charged: bool,
+}
+
+impl<M: Clone, P: Patch> Clone for Machine<M, P> {
+    fn clone(&self) -> Self {
+        Machine {
+            state: self.state.clone(),
+            status: self.status.clone(),
+            code_cache: self.code_cache.clone(),
+            block_charged: self.block_charged,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -221,8 +278,8 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
                 memory: M::default(),
                 stack: Stack::default(),
 
-                out: Rc::new(Vec::new()),
-                ret: Rc::new(Vec::new()),
+                out: Arc::new(Vec::new()),
+                ret: Arc::new(Vec::new()),
 
                 memory_cost: Gas::zero(),
                 used_gas: GasUsage::Some(Gas::zero()),
@@ -234,45 +291,69 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
 
                 depth,
                 position: 0,
-                valids: Valids::new(context.code.as_slice()),
+                analysis: Arc::new(AnalyzedCode::valids_only(context.code.as_slice())),
 
                 context,
             },
+            code_cache: None,
+            block_charged: false,
         }
     }
 
-    /// Derive this runtime to create a sub runtime. This will not
-    /// modify the current runtime, and it will have a chance to
-    /// review whether it wants to accept the result of this sub
-    /// runtime afterwards.
-    pub fn derive(&self, context: Context) -> Self {
+    /// Derive this runtime to create a sub runtime. The account state
+    /// is moved into the sub runtime after starting a checkpoint, and
+    /// is given back by `apply_sub` or `discard_sub`, where the
+    /// current runtime will have a chance to review whether it wants
+    /// to accept the result of this sub runtime.
+    pub fn derive(&mut self, context: Context) -> Self {
+        let mut account_state = replace(&mut self.state.account_state, AccountState::default());
+        account_state.checkpoint();
+        let analysis = match self.code_cache {
+            Some(ref cache) => cache.get(context.code.as_slice()),
+            None => Arc::new(AnalyzedCode::valids_only(context.code.as_slice())),
+        };
+
         Machine {
             status: MachineStatus::Running,
             state: State {
                 memory: M::default(),
                 stack: Stack::default(),
 
-                out: Rc::new(Vec::new()),
-                ret: Rc::new(Vec::new()),
+                out: Arc::new(Vec::new()),
+                ret: Arc::new(Vec::new()),
 
                 memory_cost: Gas::zero(),
                 used_gas: GasUsage::Some(Gas::zero()),
                 refunded_gas: Gas::zero(),
 
-                account_state: self.state.account_state.clone(),
+                account_state,
                 logs: Vec::new(),
                 removed: self.state.removed.clone(),
 
                 depth: self.state.depth + 1,
 
                 position: 0,
-                valids: Valids::new(context.code.as_slice()),
+                analysis,
 
                 context,
             },
+            code_cache: self.code_cache.clone(),
+            block_charged: false,
         }
     }
 
+    /// Use the cache for the code of this runtime and of the sub
+    /// runtimes derived from it.
+    pub fn set_code_cache(&mut self, cache: Arc<CodeCache<P>>) {
+        self.state.analysis = cache.get(self.state.context.code.as_slice());
+        self.code_cache = Some(cache);
+    }
+
+    /// The cache used for the code of this runtime, if any.
+    pub fn code_cache(&self) -> Option<&Arc<CodeCache<P>>> {
+        self.code_cache.as_ref()
+    }
+
     /// Commit a new account into this runtime.
     pub fn commit_account(&mut self, commitment: AccountCommitment) -> Result<(), CommitError> {
         self.state.account_state.commit(commitment)
@@ -307,10 +388,29 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         false
     }
 
+    /// Static gas of the basic block starting at the current position,
+    /// if its gas and stack bounds can be checked on entry.
+    fn block_gas(&self) -> Option<Gas> {
+        let block = self.state.analysis.block(self.state.position)?;
+        let used_gas = match self.state.used_gas {
+            GasUsage::Some(gas) => gas,
+            GasUsage::All => return None,
+        };
+
+        let len = self.state.stack.len();
+        if len < block.stack_required || len + block.stack_growth > STACK_LIMIT {
+            return None;
+        }
+        if self.state.context.gas_limit < self.state.memory_gas() + used_gas + block.static_gas {
+            return None;
+        }
+        Some(block.static_gas)
+    }
+
     /// Peek the next instruction.
     pub fn peek(&self) -> Option<Instruction> {
         let pc = PC::<P>::new(&self.state.context.code,
-                              &self.state.valids, &self.state.position);
+                              self.state.analysis.valids(), &self.state.position);
         match pc.peek() {
             Ok(val) => Some(val),
             Err(_) => None,
@@ -320,7 +420,7 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
     /// Peek the next opcode.
     pub fn peek_opcode(&self) -> Option<Opcode> {
         let pc = PC::<P>::new(&self.state.context.code,
-                              &self.state.valids, &self.state.position);
+                              self.state.analysis.valids(), &self.state.position);
         match pc.peek_opcode() {
             Ok(val) => Some(val),
             Err(_) => None,
@@ -338,8 +438,10 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         debug!("Stack: {:#x?}", self.state.stack);
 
         struct Precheck {
+            instruction: Instruction,
             position: usize,
             memory_cost: Gas,
+            block_gas: Option<Gas>,
             gas_cost: Gas,
             gas_stipend: Gas,
             gas_refund: isize,
@@ -357,11 +459,11 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         }
 
         let Precheck {
-            position, memory_cost,
+            instruction, position, memory_cost, block_gas,
             gas_cost, gas_stipend, gas_refund, after_gas
         } = {
             let pc = PC::<P>::new(&self.state.context.code,
-                                  &self.state.valids, &self.state.position);
+                                  self.state.analysis.valids(), &self.state.position);
 
             if pc.is_end() {
                 debug!("reached code EOF");
@@ -369,7 +471,9 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
                 return Ok(());
             }
 
-            let instruction = match pc.peek() {
+            let decoded = self.state.analysis.instruction(pc.position())
+                .unwrap_or_else(|| pc.peek());
+            let instruction = match decoded {
                 Ok(val) => val,
                 Err(err) => {
                     reset_error_hard!(self, err);
@@ -377,30 +481,39 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
                 },
             };
 
-            match check_opcode(instruction, &self.state, runtime).and_then(|v| {
-                match v {
-                    None => Ok(()),
-                    Some(ControlCheck::Jump(dest)) => {
-                        if dest <= M256::from(usize::max_value()) && pc.is_valid(dest.as_usize()) {
-                            Ok(())
-                        } else {
-                            Err(OnChainError::BadJumpDest.into())
+            let position = pc.position();
+            let in_block = runtime.execution_mode == ExecutionMode::BasicBlock &&
+                self.state.analysis.is_in_block(position);
+            let block_gas = if in_block && !self.block_charged {
+                self.block_gas()
+            } else {
+                None
+            };
+
+            if in_block && (self.block_charged || block_gas.is_some()) {
+                check_block_opcode(instruction, &self.state, runtime)?;
+
+                Precheck {
+                    instruction, position, block_gas,
+                    memory_cost: self.state.memory_cost,
+                    gas_cost: Gas::zero(),
+                    gas_stipend: Gas::zero(),
+                    gas_refund: 0,
+                    after_gas: self.state.available_gas() - block_gas.unwrap_or(Gas::zero()),
+                }
+            } else {
+                match check_opcode(instruction, &self.state, runtime).and_then(|v| {
+                    match v {
+                        None => Ok(()),
+                        Some(ControlCheck::Jump(dest)) => {
+                            if dest <= M256::from(usize::max_value()) && pc.is_valid(dest.as_usize()) {
+                                Ok(())
+                            } else {
+                                Err(OnChainError::BadJumpDest.into())
+                            }
                         }
                     }
-                }
-            }) {
-                Ok(()) => (),
-                Err(EvalOnChainError::OnChain(error)) => {
-                    reset_error_hard!(self, error);
-                    return Ok(());
-                },
-                Err(EvalOnChainError::Require(error)) => {
-                    return Err(error);
-                },
-            }
-
-            if self.state.context.is_static {
-                match check_static(instruction, &self.state, runtime) {
+                }) {
                     Ok(()) => (),
                     Err(EvalOnChainError::OnChain(error)) => {
                         reset_error_hard!(self, error);
@@ -410,50 +523,62 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
                         return Err(error);
                     },
                 }
-            }
 
-            let used_gas = match self.state.used_gas {
-                GasUsage::Some(gas) => gas,
-                GasUsage::All => {
-                    reset_error_hard!(self, OnChainError::EmptyGas);
-                    return Ok(());
-                },
-            };
+                if self.state.context.is_static {
+                    match check_static(instruction, &self.state, runtime) {
+                        Ok(()) => (),
+                        Err(EvalOnChainError::OnChain(error)) => {
+                            reset_error_hard!(self, error);
+                            return Ok(());
+                        },
+                        Err(EvalOnChainError::Require(error)) => {
+                            return Err(error);
+                        },
+                    }
+                }
 
-            let position = pc.position();
-            let memory_cost = memory_cost(instruction, &self.state);
-            let memory_gas = memory_gas(memory_cost);
-            let gas_cost = gas_cost::<M, P>(instruction, &self.state);
-            let gas_stipend = gas_stipend(instruction, &self.state);
-            let gas_refund = gas_refund(instruction, &self.state);
-
-            let all_gas_cost = memory_gas + used_gas + gas_cost;
-            if self.state.context.gas_limit < all_gas_cost {
-                reset_error_hard!(self, OnChainError::EmptyGas);
-                return Ok(());
-            }
+                let used_gas = match self.state.used_gas {
+                    GasUsage::Some(gas) => gas,
+                    GasUsage::All => {
+                        reset_error_hard!(self, OnChainError::EmptyGas);
+                        return Ok(());
+                    },
+                };
 
-            match check_support(instruction, &self.state) {
-                Ok(()) => (),
-                Err(err) => {
-                    reset_error_not_supported!(self, err);
+                let memory_cost = memory_cost(instruction, &self.state);
+                let memory_gas = memory_gas(memory_cost);
+                let gas_cost = gas_cost::<M, P>(instruction, &self.state);
+                let gas_stipend = gas_stipend(instruction, &self.state);
+                let gas_refund = gas_refund(instruction, &self.state);
+
+                let all_gas_cost = memory_gas + used_gas + gas_cost;
+                if self.state.context.gas_limit < all_gas_cost {
+                    reset_error_hard!(self, OnChainError::EmptyGas);
                     return Ok(());
-                },
-            };
+                }
+
+                match check_support(instruction, &self.state) {
+                    Ok(()) => (),
+                    Err(err) => {
+                        reset_error_not_supported!(self, err);
+                        return Ok(());
+                    },
+                };
 
-            let after_gas = self.state.context.gas_limit - all_gas_cost;
+                let after_gas = self.state.context.gas_limit - all_gas_cost;
 
-            match extra_check_opcode::<M, P>(instruction, &self.state, gas_stipend, after_gas) {
-                Ok(()) => (),
-                Err(err) => {
-                    reset_error_hard!(self, err);
-                    return Ok(());
-                },
-            }
+                match extra_check_opcode::<M, P>(instruction, &self.state, gas_stipend, after_gas) {
+                    Ok(()) => (),
+                    Err(err) => {
+                        reset_error_hard!(self, err);
+                        return Ok(());
+                    },
+                }
 
-            Precheck {
-                position, memory_cost,
-                gas_cost, gas_stipend, gas_refund, after_gas
+                Precheck {
+                    instruction, position, memory_cost, block_gas: None,
+                    gas_cost, gas_stipend, gas_refund, after_gas
+                }
             }
         };
 
@@ -464,9 +589,22 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         trace!("gas_refund:  {:x}", gas_refund);
         trace!("after_gas:   {:x?}", after_gas);
 
-        let instruction = PCMut::<P>::new(&self.state.context.code,
-                                          &self.state.valids, &mut self.state.position)
-            .read().unwrap();
+        // The instruction only writes within the memory it was charged
+        // for.
+        match self.state.memory.expand((memory_cost * Gas::from(32u64)).into()) {
+            Ok(()) => (),
+            Err(err) => {
+                reset_error_not_supported!(self, err);
+                return Ok(());
+            },
+        }
+
+        self.state.position = self.state.analysis.next_position(&self.state.context.code, position);
+        if let Some(block_gas) = block_gas {
+            trace!("block_gas:   {:x?}", block_gas);
+            self.state.used_gas += block_gas;
+            self.block_charged = true;
+        }
 
         let result = run_opcode::<M, P>((instruction, position),
                                         &mut self.state, runtime, gas_stipend, after_gas);
@@ -475,6 +613,11 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         self.state.memory_cost = memory_cost;
         self.state.refunded_gas = self.state.refunded_gas.add_refund(gas_refund);;
 
+        if self.block_charged && (!self.state.analysis.is_in_block(position) ||
+                                  self.state.analysis.block(self.state.position).is_some()) {
+            self.block_charged = false;
+        }
+
         debug!("{:?} => {:?}", instruction, result);
         debug!("gas used: {:x?}", self.state.total_used_gas());
         debug!("gas left: {:x?}", self.state.available_gas());
@@ -483,7 +626,7 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
             None => Ok(()),
             Some(Control::Jump(dest)) => {
                 PCMut::<P>::new(&self.state.context.code,
-                                &self.state.valids, &mut self.state.position)
+                                self.state.analysis.valids(), &mut self.state.position)
                     .jump(dest.as_usize()).unwrap();
                 Ok(())
             },
@@ -506,14 +649,16 @@ impl<M: Memory + Default, P: Patch> Machine<M, P> {
         }
     }
 
-    /// Get the runtime state.
+    /// Get the runtime state. While a sub runtime derived from this
+    /// one runs, the sub runtime holds the account state, and the
+    /// account state here is empty.
     pub fn state(&self) -> &State<M, P> {
         &self.state
     }
 
     /// Get the runtime PC.
     pub fn pc(&self) -> PC<P> {
-        PC::new(&self.state.context.code, &self.state.valids, &self.state.position)
+        PC::new(&self.state.context.code, self.state.analysis.valids(), &self.state.position)
     }
 
     /// Get the current runtime status.
@@ -1,14 +1,18 @@
 //! Instruction running logic
+//!
+//! The stack bounds of an instruction are checked by `check_opcode`,
+//! or with its basic block, before it is run, so the stack is
+//! accessed without checks here.
 
 macro_rules! pop {
     ( $machine:expr, $( $x:ident ),* ) => (
         $(
-            let $x = $machine.stack.pop().unwrap();
+            let $x = unsafe { $machine.stack.pop_unchecked() };
         )*
     );
     ( $machine:expr, $( $x:ident : $t: ty ),* ) => (
         $(
-            let $x: $t = $machine.stack.pop().unwrap().into();
+            let $x: $t = unsafe { $machine.stack.pop_unchecked() }.into();
         )*
     );
 }
@@ -16,7 +20,7 @@ macro_rules! pop {
 macro_rules! push {
     ( $machine:expr, $( $x:expr ),* ) => (
         $(
-            $machine.stack.push($x).unwrap();
+            unsafe { $machine.stack.push_unchecked($x) };
         )*
     )
 }
@@ -49,8 +53,8 @@ mod flow;
 mod environment;
 mod system;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 use bigint::{M256, MI256, U256, Address, Gas};
 #[cfg(feature = "std")] use std::ops::{Add, Sub, Mul, Div, Rem, BitAnd, BitOr, BitXor};
@@ -147,7 +151,7 @@ pub fn run_opcode<M: Memory + Default, P: Patch>(pc: (Instruction, usize), state
         Instruction::DIFFICULTY => { push!(state, M256::from(runtime.block.difficulty)); None },
         Instruction::GASLIMIT => { push!(state, runtime.block.gas_limit.into()); None },
 
-        Instruction::POP => { state.stack.pop().unwrap(); None },
+        Instruction::POP => { unsafe { state.stack.pop_unchecked() }; None },
         Instruction::MLOAD => { flow::mload(state); None },
         Instruction::MSTORE => { flow::mstore(state); None },
         Instruction::MSTORE8 => { flow::mstore8(state); None },
@@ -167,14 +171,8 @@ pub fn run_opcode<M: Memory + Default, P: Patch>(pc: (Instruction, usize), state
 
         Instruction::PUSH(v) => { push!(state, v); None }
 
-        Instruction::DUP(v) => { let val = state.stack.peek(v-1).unwrap();
-                                 push!(state, val);
-                                 None },
-        Instruction::SWAP(v) => { let val1 = state.stack.peek(0).unwrap();
-                                  let val2 = state.stack.peek(v).unwrap();
-                                  state.stack.set(0, val2).unwrap();
-                                  state.stack.set(v, val1).unwrap();
-                                  None },
+        Instruction::DUP(v) => { unsafe { state.stack.dup_unchecked(v) }; None },
+        Instruction::SWAP(v) => { unsafe { state.stack.swap_unchecked(v) }; None },
         Instruction::LOG(v) => { system::log(state, v); None },
 
         Instruction::CREATE => { system::create::<M, P>(state, after_gas, false) },
@@ -184,10 +182,10 @@ pub fn run_opcode<M: Memory + Default, P: Patch>(pc: (Instruction, usize), state
         Instruction::DELEGATECALL => { system::delegate_call::<M, P>(state, after_gas) },
         Instruction::STATICCALL => { system::static_call::<M, P>(state, stipend_gas, after_gas) },
         Instruction::RETURN => { pop!(state, start: U256, len: U256);
-                                 state.out = Rc::new(copy_from_memory(&state.memory, start, len));
+                                 state.out = Arc::new(copy_from_memory(&state.memory, start, len));
                                  Some(Control::Stop) },
         Instruction::REVERT => { pop!(state, start: U256, len: U256);
-                                 state.out = Rc::new(copy_from_memory(&state.memory, start, len));
+                                 state.out = Arc::new(copy_from_memory(&state.memory, start, len));
                                  Some(Control::Revert) },
         Instruction::SUICIDE => { system::suicide(state); Some(Control::Stop) },
     }
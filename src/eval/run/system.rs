@@ -3,8 +3,8 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 use bigint::{U256, M256, H256, Address, Gas};
 use ::{Memory, Log, ValidTransaction, Patch};
@@ -34,7 +34,7 @@ pub fn log<M: Memory + Default, P: Patch>(state: &mut State<M, P>, topic_len: us
     let data = copy_from_memory(&state.memory, index, len);
     let mut topics = Vec::new();
     for _ in 0..topic_len {
-        topics.push(H256::from(state.stack.pop().unwrap()));
+        topics.push(H256::from(unsafe { state.stack.pop_unchecked() }));
     }
 
     state.logs.push(Log {
@@ -78,7 +78,7 @@ pub fn create<M: Memory + Default, P: Patch>(state: &mut State<M, P>, after_gas:
     try_callstack_limit!(state, P);
     try_balance!(state, value, Gas::zero());
 
-    let init = Rc::new(copy_from_memory(&state.memory, init_start, init_len));
+    let init = Arc::new(copy_from_memory(&state.memory, init_start, init_len));
     let transaction = if !is_create2 {
         ValidTransaction {
             caller: Some(state.context.address),
@@ -123,7 +123,7 @@ pub fn call<M: Memory + Default, P: Patch>(state: &mut State<M, P>, stipend_gas:
     try_callstack_limit!(state, P);
     try_balance!(state, value, gas_limit);
 
-    let input = Rc::new(copy_from_memory(&state.memory, in_start, in_len));
+    let input = Arc::new(copy_from_memory(&state.memory, in_start, in_len));
     let transaction = ValidTransaction {
         caller: Some(state.context.address),
         gas_price: state.context.gas_price,
@@ -155,7 +155,7 @@ pub fn static_call<M: Memory + Default, P: Patch>(state: &mut State<M, P>, stipe
 
     try_callstack_limit!(state, P);
 
-    let input = Rc::new(copy_from_memory(&state.memory, in_start, in_len));
+    let input = Arc::new(copy_from_memory(&state.memory, in_start, in_len));
     let transaction = ValidTransaction {
         caller: Some(state.context.address),
         gas_price: state.context.gas_price,
@@ -184,7 +184,7 @@ pub fn delegate_call<M: Memory + Default, P: Patch>(state: &mut State<M, P>, aft
 
     try_callstack_limit!(state, P);
 
-    let input = Rc::new(copy_from_memory(&state.memory, in_start, in_len));
+    let input = Arc::new(copy_from_memory(&state.memory, in_start, in_len));
     let transaction = ValidTransaction {
         caller: Some(state.context.caller),
         gas_price: state.context.gas_price,
@@ -50,7 +50,7 @@
 //!                 HeaderParams, ValidTransaction, TransactionAction,
 //!                 VM, SeqTransactionVM};
 //! use bigint::{Gas, U256, Address};
-//! use std::rc::Rc;
+//! use std::sync::Arc;
 //!
 //! fn main() {
 //!   let block_number = 1000;
@@ -60,7 +60,7 @@
 //!     gas_limit: Gas::max_value(),
 //!     action: TransactionAction::Create,
 //!     value: U256::zero(),
-//!     input: Rc::new(Vec::new()),
+//!     input: Arc::new(Vec::new()),
 //!     nonce: U256::zero()
 //!   };
 //!   let header = HeaderParams {
@@ -145,28 +145,42 @@ mod eval;
 mod commit;
 mod patch;
 mod transaction;
+mod estimate;
+mod access_list;
+mod revert;
+mod trace;
+mod profile;
+#[cfg(test)]
+mod test_util;
 pub mod errors;
 
 pub use self::memory::{Memory, SeqMemory};
-pub use self::stack::Stack;
+pub use self::stack::{Stack, STACK_LIMIT};
 pub use self::pc::{PC, PCMut, Instruction, Valids};
 pub use self::params::*;
 pub use self::patch::*;
-pub use self::eval::{State, Machine, Runtime, MachineStatus};
+pub use self::eval::{State, Machine, Runtime, MachineStatus, ExecutionMode, AnalyzedCode, BasicBlock, CodeCache};
 pub use self::commit::{AccountCommitment, AccountChange, AccountState, BlockhashState, Storage};
 pub use self::transaction::{ValidTransaction, TransactionVM, UntrustedTransaction};
-pub use self::errors::{OnChainError, NotSupportedError, RequireError, CommitError, PreExecutionError};
+pub use self::estimate::GasEstimator;
+pub use self::access_list::{AccessList, AccessListItem, AccessListGenerator, access_list_gas};
+pub use self::revert::{RevertReason, panic_description};
+pub use self::trace::{CallTracer, CallFrame};
+pub use self::profile::{GasProfiler, GasProfile, ContractProfile, InstructionProfile};
+pub use self::errors::{OnChainError, NotSupportedError, RequireError, CommitError, PreExecutionError,
+                       EstimateError};
 pub use self::util::opcode::Opcode;
 pub use block_core::TransactionAction;
 
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(feature = "std")] use std::collections::{HashSet as Set, hash_map as map};
-#[cfg(not(feature = "std"))] use alloc::{collections::BTreeSet as Set, collections::btree_map as map};
-#[cfg(not(feature = "std"))] use alloc::boxed::Box;
+#[cfg(feature = "std")] use std::collections::{HashSet as Set, HashMap as Map, hash_map as map};
+#[cfg(not(feature = "std"))] use alloc::{collections::BTreeSet as Set, collections::BTreeMap as Map, collections::btree_map as map};
 #[cfg(feature = "std")] use std::cmp::min;
 #[cfg(not(feature = "std"))] use core::cmp::min;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 use bigint::{U256, H256, Gas, Address};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -224,8 +238,18 @@ pub trait VM {
     fn accounts(&self) -> map::Values<Address, AccountChange>;
     /// Returns all fetched or modified addresses.
     fn used_addresses(&self) -> Set<Address>;
+    /// Returns all fetched or modified storage indexes, by address.
+    fn used_storage(&self) -> Map<Address, Set<U256>>;
     /// Returns the out value, if any.
     fn out(&self) -> &[u8];
+    /// Returns the decoded revert reason, if the VM exited with
+    /// `Revert`.
+    fn revert_reason(&self) -> Option<RevertReason> {
+        match self.status() {
+            VMStatus::ExitedErr(OnChainError::Revert) => Some(RevertReason::decode(self.out())),
+            _ => None,
+        }
+    }
     /// Returns the available gas of this VM.
     fn available_gas(&self) -> Gas;
     /// Returns the refunded gas of this VM.
@@ -247,14 +271,32 @@ pub type SeqContextVM<P> = ContextVM<SeqMemory<P>, P>;
 /// A sequential transaction VM. This is same as `SeqContextVM` except
 /// it runs at transaction level.
 pub type SeqTransactionVM<P> = TransactionVM<SeqMemory<P>, P>;
+/// A sequential gas estimator, running the transaction with
+/// `SeqTransactionVM`.
+pub type SeqGasEstimator<P> = GasEstimator<SeqMemory<P>, P>;
+/// A sequential access list generator, running the transaction with
+/// `SeqTransactionVM`.
+pub type SeqAccessListGenerator<P> = AccessListGenerator<SeqMemory<P>, P>;
 
 /// A VM that executes using a context and block information.
 pub struct ContextVM<M, P: Patch> {
     runtime: Runtime,
+    /// The call stack. Only the last machine holds the account state;
+    /// the ones below it get it back when their sub machine exits.
     machines: Vec<Machine<M, P>>,
     fresh_account_state: AccountState<P::Account>,
 }
 
+impl<M: Clone, P: Patch> Clone for ContextVM<M, P> {
+    fn clone(&self) -> Self {
+        ContextVM {
+            runtime: self.runtime.clone(),
+            machines: self.machines.clone(),
+            fresh_account_state: self.fresh_account_state.clone(),
+        }
+    }
+}
+
 impl<M: Memory + Default, P: Patch> ContextVM<M, P> {
     /// Create a new VM using the given context, block header and patch.
     pub fn new(context: Context, block: HeaderParams) -> Self {
@@ -291,11 +333,19 @@ impl<M: Memory + Default, P: Patch> ContextVM<M, P> {
     }
 
     /// Create a new VM with the result of the previous VM. This is
-    /// usually used by transaction for chainning them.
+    /// usually used by transaction for chainning them. If the
+    /// previous VM is still running, the new VM starts from the
+    /// account state of its current call. The code cache and
+    /// execution mode of the previous VM are kept.
     pub fn with_previous(context: Context, block: HeaderParams, vm: &ContextVM<M, P>) -> Self {
-        Self::with_states(context, block,
-                          vm.machines[0].state().account_state.clone(),
-                          vm.runtime.blockhash_state.clone())
+        let mut next = Self::with_states(context, block,
+                                         vm.current_state().account_state.clone(),
+                                         vm.runtime.blockhash_state.clone());
+        if let Some(cache) = vm.code_cache() {
+            next.set_code_cache(cache.clone());
+        }
+        next.set_execution_mode(vm.execution_mode());
+        next
     }
 
     /// Returns the current state of the VM.
@@ -309,17 +359,45 @@ impl<M: Memory + Default, P: Patch> ContextVM<M, P> {
     }
 
     /// Add a new context history hook.
-    pub fn add_context_history_hook<F: 'static + Fn(&Context)>(&mut self, f: F) {
-        self.runtime.context_history_hooks.push(Box::new(f));
+    pub fn add_context_history_hook<F: 'static + Fn(&Context) + Send + Sync>(&mut self, f: F) {
+        self.runtime.context_history_hooks.push(Arc::new(f));
         debug!("registered a new history hook");
     }
+
+    /// Use the cache for the code run by this VM. The cache can be
+    /// shared with other VMs running with the same patch.
+    pub fn set_code_cache(&mut self, cache: Arc<CodeCache<P>>) {
+        for machine in &mut self.machines {
+            machine.set_code_cache(cache.clone());
+        }
+    }
+
+    /// Returns the code cache used by this VM, if any.
+    pub fn code_cache(&self) -> Option<&Arc<CodeCache<P>>> {
+        self.current_machine().code_cache()
+    }
+
+    /// Returns how gas and stack bounds are checked.
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.runtime.execution_mode
+    }
+
+    /// Set how gas and stack bounds are checked. Code run without a
+    /// cache is only fully analyzed when using basic blocks, so a
+    /// cache with no capacity is set for them if there is none.
+    pub fn set_execution_mode(&mut self, mode: ExecutionMode) {
+        self.runtime.execution_mode = mode;
+        if mode == ExecutionMode::BasicBlock && self.current_machine().code_cache().is_none() {
+            self.set_code_cache(Arc::new(CodeCache::new(0)));
+        }
+    }
 }
 
 impl<M: Memory + Default, P: Patch> VM for ContextVM<M, P> {
     fn commit_account(&mut self, commitment: AccountCommitment) -> Result<(), CommitError> {
-        for machine in &mut self.machines {
-            machine.commit_account(commitment.clone())?;
-        }
+        // Only the current machine holds the account state. The ones
+        // below it get it back when the sub machines are applied.
+        self.machines.last_mut().unwrap().commit_account(commitment.clone())?;
         debug!("committed account info: {:?}", commitment);
         Ok(())
     }
@@ -395,20 +473,34 @@ impl<M: Memory + Default, P: Patch> VM for ContextVM<M, P> {
                     hook(&context)
                 }
 
-                let mut sub = self.machines.last().unwrap().derive(context);
-                sub.invoke_call()?;
-                self.machines.push(sub);
-                Ok(())
+                let mut sub = self.machines.last_mut().unwrap().derive(context);
+                match sub.invoke_call() {
+                    Ok(()) => {
+                        self.machines.push(sub);
+                        Ok(())
+                    },
+                    Err(err) => {
+                        self.machines.last_mut().unwrap().discard_sub(sub);
+                        Err(err)
+                    },
+                }
             },
             MachineStatus::InvokeCreate(context) => {
                for hook in &self.runtime.context_history_hooks {
                     hook(&context)
                 }
 
-                let mut sub = self.machines.last().unwrap().derive(context);
-                sub.invoke_create()?;
-                self.machines.push(sub);
-                Ok(())
+                let mut sub = self.machines.last_mut().unwrap().derive(context);
+                match sub.invoke_create() {
+                    Ok(()) => {
+                        self.machines.push(sub);
+                        Ok(())
+                    },
+                    Err(err) => {
+                        self.machines.last_mut().unwrap().discard_sub(sub);
+                        Err(err)
+                    },
+                }
             },
         }
     }
@@ -428,11 +520,15 @@ impl<M: Memory + Default, P: Patch> VM for ContextVM<M, P> {
     }
 
     fn accounts(&self) -> map::Values<Address, AccountChange> {
-        self.machines[0].state().account_state.accounts()
+        self.current_machine().state().account_state.accounts()
     }
 
     fn used_addresses(&self) -> Set<Address> {
-        self.machines[0].state().account_state.used_addresses()
+        self.current_machine().state().account_state.used_addresses()
+    }
+
+    fn used_storage(&self) -> Map<Address, Set<U256>> {
+        self.current_machine().state().account_state.used_storage()
     }
 
     fn out(&self) -> &[u8] {
@@ -10,17 +10,22 @@ use super::Patch;
 
 #[cfg(feature = "std")] use std::marker::PhantomData;
 #[cfg(not(feature = "std"))] use core::marker::PhantomData;
+#[cfg(feature = "std")] use std::cmp::min;
+#[cfg(not(feature = "std"))] use core::cmp::min;
 
 /// Represent a memory in EVM. Read should always succeed. Write can
-/// fall.
+/// fall. The interpreter expands the memory to the charged memory
+/// cost before each instruction, so writes only happen within the
+/// expanded range.
 pub trait Memory {
     /// Check whether write on this index would result in an error. If
     /// this function returns Ok, then both `write` and `write_raw` on
-    /// this index should succeed.
+    /// this index should succeed once the memory is expanded over it.
     fn check_write(&self, index: U256) -> Result<(), NotSupportedError>;
     /// Check whether write on the given index range would result in
     /// an error. If this function returns Ok, then both `write` and
-    /// `write_raw` on the given index range should succeed.
+    /// `write_raw` on the given index range should succeed once the
+    /// memory is expanded over it.
     fn check_write_range(&self, start: U256, len: U256) -> Result<(), NotSupportedError>;
 
     /// Write value into the index.
@@ -31,6 +36,48 @@ pub trait Memory {
     fn read(&self, index: U256) -> M256;
     /// Read only one byte value from the index.
     fn read_raw(&self, index: U256) -> u8;
+
+    /// Expand the memory so that it holds at least `len` bytes. New
+    /// bytes are zero. Memories that allocate on write only need to
+    /// check the length.
+    fn expand(&mut self, len: U256) -> Result<(), NotSupportedError> {
+        self.check_write_range(U256::zero(), len)
+    }
+
+    /// Read `len` bytes starting from the index. Bytes that were never
+    /// written are zero.
+    fn read_slice(&self, index: U256, len: U256) -> Vec<u8> {
+        let mut result: Vec<u8> = Vec::new();
+        let mut i = U256::zero();
+        while i < len {
+            result.push(self.read_raw(index + i));
+            i = i + U256::from(1u64);
+        }
+        result
+    }
+
+    /// Write the values into memory starting from the index.
+    fn write_slice(&mut self, index: U256, values: &[u8]) -> Result<(), NotSupportedError> {
+        for (i, value) in values.iter().enumerate() {
+            self.write_raw(index + U256::from(i), *value)?;
+        }
+        Ok(())
+    }
+
+    /// Copy `len` bytes of the values starting from `value_index` into
+    /// memory starting from the index. Bytes past the end of the
+    /// values are written as zero.
+    fn copy_slice(&mut self, index: U256, values: &[u8], value_index: U256, len: U256) -> Result<(), NotSupportedError> {
+        let value_len = U256::from(values.len());
+        let mut i = U256::zero();
+        while i < len {
+            let j = value_index.saturating_add(i);
+            let value = if j < value_len { values[j.as_usize()] } else { 0u8 };
+            self.write_raw(index + i, value)?;
+            i = i + U256::from(1u64);
+        }
+        Ok(())
+    }
 }
 
 /// A sequencial memory. It uses Rust's `Vec` for internal
@@ -40,6 +87,15 @@ pub struct SeqMemory<P: Patch> {
     _marker: PhantomData<P>,
 }
 
+impl<P: Patch> Clone for SeqMemory<P> {
+    fn clone(&self) -> SeqMemory<P> {
+        SeqMemory {
+            memory: self.memory.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
 impl<P: Patch> Default for SeqMemory<P> {
     fn default() -> SeqMemory<P> {
         SeqMemory {
@@ -59,6 +115,31 @@ impl<P: Patch> SeqMemory<P> {
     /// Return true if current effective memory range is zero
     #[inline]
     pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Check that the memory was expanded over the given range.
+    fn check_expanded(&self, start: U256, len: U256) -> Result<(), NotSupportedError> {
+        if start.saturating_add(len) > U256::from(self.memory.len()) {
+            Err(NotSupportedError::MemoryIndexNotSupported)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fill the buffer with memory starting from the index.
+    fn read_into(&self, index: U256, buffer: &mut [u8]) {
+        let copied = if index < U256::from(self.memory.len()) {
+            let start = index.as_usize();
+            let end = min(self.memory.len(), start.saturating_add(buffer.len()));
+            buffer[..(end - start)].copy_from_slice(&self.memory[start..end]);
+            end - start
+        } else {
+            0
+        };
+
+        for item in &mut buffer[copied..] {
+            *item = 0u8;
+        }
+    }
 }
 
 impl<P: Patch> Memory for SeqMemory<P> {
@@ -84,53 +165,138 @@ impl<P: Patch> Memory for SeqMemory<P> {
     }
 
     fn write(&mut self, index: U256, value: M256) -> Result<(), NotSupportedError> {
-        let end = M256::from(index) + 32.into();
-        if end > M256::from(P::memory_limit()) {
+        let mut a: [u8; 32] = [0u8; 32];
+
+        for (i, item) in a[0..32].iter_mut().enumerate() {
+            *item = value.index(i);
+        }
+
+        self.write_slice(index, &a)
+    }
+
+    fn write_raw(&mut self, index: U256, value: u8) -> Result<(), NotSupportedError> {
+        self.write_slice(index, &[value])
+    }
+
+    fn read(&self, index: U256) -> M256 {
+        let mut a: [u8; 32] = [0u8; 32];
+        self.read_into(index, &mut a);
+        a.as_ref().into()
+    }
+
+    fn read_raw(&self, index: U256) -> u8 {
+        let mut a: [u8; 1] = [0u8];
+        self.read_into(index, &mut a);
+        a[0]
+    }
+
+    fn expand(&mut self, len: U256) -> Result<(), NotSupportedError> {
+        if len > U256::from(P::memory_limit()) {
             return Err(NotSupportedError::MemoryIndexNotSupported);
         }
 
-        for i in 0..32 {
-            self.write_raw(index + i.into(), value.index(i)).unwrap();
+        let len: usize = len.as_usize();
+        if self.memory.len() < len {
+            self.memory.resize(len, 0u8);
         }
         Ok(())
     }
 
-    fn write_raw(&mut self, index: U256, value: u8) -> Result<(), NotSupportedError> {
-        if index > U256::from(P::memory_limit()) {
-            return Err(NotSupportedError::MemoryIndexNotSupported);
+    fn read_slice(&self, index: U256, len: U256) -> Vec<u8> {
+        if len == U256::zero() {
+            return Vec::new();
         }
 
-        let index: usize = index.as_usize();
+        let mut result: Vec<u8> = Vec::new();
+        result.resize(len.as_usize(), 0u8);
+        self.read_into(index, &mut result);
+        result
+    }
 
-        if self.memory.len() <= index {
-            self.memory.resize(index + 1, 0u8);
+    fn write_slice(&mut self, index: U256, values: &[u8]) -> Result<(), NotSupportedError> {
+        if values.is_empty() {
+            return Ok(());
         }
 
-        self.memory[index] = value;
+        self.check_expanded(index, U256::from(values.len()))?;
+        let start: usize = index.as_usize();
+        self.memory[start..(start + values.len())].copy_from_slice(values);
         Ok(())
     }
 
-    fn read(&self, index: U256) -> M256 {
-        let mut a: [u8; 32] = [0u8; 32];
+    fn copy_slice(&mut self, index: U256, values: &[u8], value_index: U256, len: U256) -> Result<(), NotSupportedError> {
+        if len == U256::zero() {
+            return Ok(());
+        }
 
-        for (i, item) in a[0..32].iter_mut().enumerate() {
-            *item = self.read_raw(index + i.into());
+        self.check_expanded(index, len)?;
+        let start: usize = index.as_usize();
+        let target = &mut self.memory[start..(start + len.as_usize())];
+
+        let copied = if value_index < U256::from(values.len()) {
+            let value_start = value_index.as_usize();
+            let copied = min(target.len(), values.len() - value_start);
+            target[..copied].copy_from_slice(&values[value_start..(value_start + copied)]);
+            copied
+        } else {
+            0
+        };
+
+        for item in &mut target[copied..] {
+            *item = 0u8;
         }
+        Ok(())
+    }
+}
 
-        a.as_ref().into()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patch::EmbeddedPatch;
+
+    #[test]
+    fn read_and_write_slices() {
+        let mut memory = SeqMemory::<EmbeddedPatch>::default();
+        memory.expand(U256::from(34u64)).unwrap();
+        memory.write_slice(U256::from(30u64), &[1, 2, 3, 4]).unwrap();
+        assert_eq!(memory.len(), 34);
+        assert_eq!(memory.read_slice(U256::from(31u64), U256::from(5u64)), vec![2, 3, 4, 0, 0]);
+        assert_eq!(memory.read_raw(U256::from(33u64)), 4);
+        assert_eq!(memory.read_raw(U256::from(u64::max_value())), 0);
+        assert_eq!(memory.read(U256::zero()), M256::from(0x0102u64));
+
+        memory.write(U256::from(2u64), M256::from(0xffu64)).unwrap();
+        assert_eq!(memory.read_slice(U256::from(32u64), U256::from(3u64)), vec![0, 0xff, 0]);
+        assert_eq!(memory.read_slice(U256::from(u64::max_value()), U256::zero()), Vec::<u8>::new());
     }
 
-    fn read_raw(&self, index: U256) -> u8 {
-        if index > U256::from(usize::max_value()) {
-            return 0u8;
-        }
+    #[test]
+    fn write_past_expanded() {
+        let mut memory = SeqMemory::<EmbeddedPatch>::default();
+        memory.expand(U256::from(32u64)).unwrap();
+        assert!(memory.write(U256::from(1u64), M256::zero()).is_err());
+        assert!(memory.write_raw(U256::from(32u64), 1).is_err());
+        assert_eq!(memory.len(), 32);
+
+        memory.write_slice(U256::from(31u64), &[]).unwrap();
+        memory.write_raw(U256::from(31u64), 1).unwrap();
+        assert_eq!(memory.read_raw(U256::from(31u64)), 1);
+        assert!(memory.expand(U256::from(usize::max_value()) + U256::one()).is_err());
+    }
 
-        let index: usize = index.as_usize();
+    #[test]
+    fn copy_slice_pads_with_zero() {
+        let mut memory = SeqMemory::<EmbeddedPatch>::default();
+        memory.expand(U256::from(10u64)).unwrap();
+        memory.write_slice(U256::zero(), &[0xff; 8]).unwrap();
+        memory.copy_slice(U256::from(1u64), &[1, 2, 3], U256::from(1u64), U256::from(4u64)).unwrap();
+        assert_eq!(memory.read_slice(U256::zero(), U256::from(8u64)), vec![0xff, 2, 3, 0, 0, 0xff, 0xff, 0xff]);
 
-        if self.memory.len() <= index {
-            return 0u8;
-        }
+        memory.copy_slice(U256::from(6u64), &[1, 2, 3], U256::from(u64::max_value()), U256::from(4u64)).unwrap();
+        assert_eq!(memory.len(), 10);
+        assert_eq!(memory.read_slice(U256::from(4u64), U256::from(6u64)), vec![0, 0xff, 0, 0, 0, 0]);
 
-        self.memory[index]
+        memory.expand(U256::from(64u64)).unwrap();
+        assert_eq!(memory.len(), 64);
     }
 }
@@ -3,8 +3,8 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 use bigint::{U256, Address, Gas};
 #[cfg(feature = "std")]
@@ -46,9 +46,9 @@ pub struct Context {
     /// Caller of the runtime.
     pub caller: Address,
     /// Code to be executed.
-    pub code: Rc<Vec<u8>>,
+    pub code: Arc<Vec<u8>>,
     /// Data associated with this execution.
-    pub data: Rc<Vec<u8>>,
+    pub data: Arc<Vec<u8>>,
     /// Gas limit.
     pub gas_limit: Gas,
     /// Gas price.
@@ -15,6 +15,9 @@ pub trait AccountPatch {
     fn initial_create_nonce() -> U256;
     /// Whether empty accounts are considered to be existing. (EIP161.b/EIP161.c/EIP161.d)
     fn empty_considered_exists() -> bool;
+    /// Chain id of EIP155 signatures, or `None` if the chain has
+    /// none.
+    fn chain_id() -> Option<u64>;
     /// Whether to allow partial change IncreaseBalance.
     fn allow_partial_change() -> bool {
         Self::empty_considered_exists()
@@ -27,6 +30,7 @@ impl AccountPatch for EmbeddedAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() }
     fn empty_considered_exists() -> bool { true }
+    fn chain_id() -> Option<u64> { Some(1) }
 }
 
 /// Mainnet account patch
@@ -35,6 +39,7 @@ impl AccountPatch for EmbeddedByzantiumAccountPatch {
     fn initial_nonce() -> U256 { U256::zero() }
     fn initial_create_nonce() -> U256 { Self::initial_nonce() + U256::one() }
     fn empty_considered_exists() -> bool { false }
+    fn chain_id() -> Option<u64> { Some(1) }
 }
 
 /// Represents different block range context.
@@ -88,6 +93,15 @@ pub trait Patch {
     fn call_create_l64_after_gas() -> bool;
     /// Maximum size of the memory, in bytes.
     fn memory_limit() -> usize;
+    /// Whether transaction signatures must have an `s` value of at
+    /// most half the curve order. (EIP2)
+    fn has_low_s_signature() -> bool;
+    /// Whether transaction signatures may have a `v` value of
+    /// `chain_id * 2 + 35` or `chain_id * 2 + 36`. (EIP155)
+    fn has_eip155_signature() -> bool;
+    /// Chain id of EIP155 signatures, or `None` to accept any chain
+    /// id.
+    fn chain_id() -> Option<u64>;
     /// Precompiled contracts at given address, with required code,
     /// and its definition.
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)];
@@ -135,6 +149,9 @@ impl Patch for VMTestPatch {
     fn err_on_call_with_more_gas() -> bool { true }
     fn call_create_l64_after_gas() -> bool { false }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { false }
+    fn has_eip155_signature() -> bool { false }
+    fn chain_id() -> Option<u64> { None }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &EMBEDDED_PRECOMPILEDS }
 }
@@ -165,6 +182,9 @@ impl Patch for EmbeddedPatch {
     fn err_on_call_with_more_gas() -> bool { false }
     fn call_create_l64_after_gas() -> bool { true }
     fn memory_limit() -> usize { usize::max_value() }
+    fn has_low_s_signature() -> bool { true }
+    fn has_eip155_signature() -> bool { true }
+    fn chain_id() -> Option<u64> { EmbeddedAccountPatch::chain_id() }
     fn precompileds() -> &'static [(Address, Option<&'static [u8]>, &'static Precompiled)] {
         &EMBEDDED_PRECOMPILEDS
     }
@@ -1,8 +1,8 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
 use bigint::Gas;
 #[cfg(all(feature = "std", any(feature = "rust-secp256k1", feature = "c-secp256k1")))] use std::cmp::min;
@@ -23,7 +23,7 @@ use secp256k1::{recover, Message, RecoveryId, Signature, Error};
 /// Represent a precompiled contract.
 pub trait Precompiled: Sync {
     /// Step a precompiled contract based on the gas required.
-    fn step(&self, _: &[u8]) -> Rc<Vec<u8>> {
+    fn step(&self, _: &[u8]) -> Arc<Vec<u8>> {
         unimplemented!()
     }
     /// Gas needed for a given computation.
@@ -31,7 +31,7 @@ pub trait Precompiled: Sync {
         unimplemented!()
     }
     /// Combine step and gas together, given the gas limit.
-    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Rc<Vec<u8>>), RuntimeError> {
+    fn gas_and_step(&self, data: &[u8], gas_limit: Gas) -> Result<(Gas, Arc<Vec<u8>>), RuntimeError> {
         let gas = self.gas(data);
         if gas > gas_limit {
             Err(RuntimeError::OnChain(OnChainError::EmptyGas))
@@ -49,8 +49,8 @@ impl Precompiled for IDPrecompiled {
             Gas::from(3u64) * gas_div_ceil(Gas::from(data.len()), Gas::from(32u64))
     }
 
-    fn step(&self, data: &[u8]) -> Rc<Vec<u8>> {
-        Rc::new(data.into())
+    fn step(&self, data: &[u8]) -> Arc<Vec<u8>> {
+        Arc::new(data.into())
     }
 }
 /// Static value of ID precompiled contract.
@@ -64,7 +64,7 @@ impl Precompiled for RIP160Precompiled {
             Gas::from(120u64) * gas_div_ceil(Gas::from(data.len()), Gas::from(32u64))
     }
 
-    fn step(&self, data: &[u8]) -> Rc<Vec<u8>> {
+    fn step(&self, data: &[u8]) -> Arc<Vec<u8>> {
         let mut ripemd = Ripemd160::default();
         ripemd.input(data);
         let fixed = ripemd.fixed_result();
@@ -72,7 +72,7 @@ impl Precompiled for RIP160Precompiled {
         for i in 0..20 {
             result[i + 12] = fixed[i];
         }
-        Rc::new(result.as_ref().into())
+        Arc::new(result.as_ref().into())
     }
 }
 /// Static value of RIP160 precompiled contract.
@@ -87,7 +87,7 @@ impl Precompiled for SHA256Precompiled {
                                             Gas::from(32u64))
     }
 
-    fn step(&self, data: &[u8]) -> Rc<Vec<u8>> {
+    fn step(&self, data: &[u8]) -> Arc<Vec<u8>> {
         let mut sha2 = Sha256::default();
         sha2.input(data);
         let fixed = sha2.fixed_result();
@@ -95,7 +95,7 @@ impl Precompiled for SHA256Precompiled {
         for i in 0..32 {
             result[i] = fixed[i];
         }
-        Rc::new(result.as_ref().into())
+        Arc::new(result.as_ref().into())
     }
 }
 /// Static value of SHA256 precompiled contract.
@@ -109,7 +109,7 @@ impl Precompiled for ECRECPrecompiled {
         Gas::from(3000u64)
     }
 
-    fn step(&self, datao: &[u8]) -> Rc<Vec<u8>> {
+    fn step(&self, datao: &[u8]) -> Arc<Vec<u8>> {
         let mut data = [0u8; 128];
         let copy_bytes = min(datao.len(), 128);
         data[..copy_bytes].clone_from_slice(&datao[..copy_bytes]);
@@ -118,15 +118,15 @@ impl Precompiled for ECRECPrecompiled {
                 for i in &mut ret[..12] {
                     *i = 0
                 }
-                Rc::new(ret.as_ref().into())
+                Arc::new(ret.as_ref().into())
             },
-            Err(_) => Rc::new(Vec::new()),
+            Err(_) => Arc::new(Vec::new()),
         }
     }
 }
 #[cfg(all(not(feature = "c-secp256k1"), not(feature = "rust-secp256k1")))]
 impl Precompiled for ECRECPrecompiled {
-    fn gas_and_step(&self, _: &[u8], _: Gas) -> Result<(Gas, Rc<Vec<u8>>), RuntimeError> {
+    fn gas_and_step(&self, _: &[u8], _: Gas) -> Result<(Gas, Arc<Vec<u8>>), RuntimeError> {
         use errors::NotSupportedError;
 
         Err(RuntimeError::NotSupported(NotSupportedError::PrecompiledNotSupported))
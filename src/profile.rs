@@ -0,0 +1,295 @@
+//! Gas profiling of bytecode execution.
+
+#[cfg(not(feature = "std"))] use alloc::vec::Vec;
+#[cfg(not(feature = "std"))] use alloc::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "std")] use std::collections::{BTreeMap, BTreeSet};
+use bigint::{Gas, Address, H256};
+use sha3::{Digest, Keccak256};
+
+use super::{Machine, MachineStatus, Memory, Patch, Opcode};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Statistics of the instruction at a position of a contract.
+pub struct InstructionProfile {
+    /// Opcode at this position.
+    pub opcode: Opcode,
+    /// Number of times the instruction was executed.
+    pub count: usize,
+    /// Gas used by the instruction, excluding gas used inside the sub
+    /// calls it invoked.
+    pub gas: Gas,
+    /// Part of `gas` used for memory expansion.
+    pub memory_gas: Gas,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Statistics of a contract code. The code of a frame is the code it
+/// runs, which for `CALLCODE` and `DELEGATECALL` is the code of the
+/// callee, run at the address of the caller.
+pub struct ContractProfile {
+    /// Addresses of the frames that ran the code.
+    pub addresses: BTreeSet<Address>,
+    /// Number of instructions executed.
+    pub count: usize,
+    /// Gas used by the instructions of the contract.
+    pub gas: Gas,
+    /// Part of `gas` used for memory expansion.
+    pub memory_gas: Gas,
+    /// Number of `SLOAD` executed.
+    pub sloads: usize,
+    /// Number of `SSTORE` executed.
+    pub sstores: usize,
+    /// Statistics per position.
+    pub instructions: BTreeMap<usize, InstructionProfile>,
+}
+
+impl Default for ContractProfile {
+    fn default() -> Self {
+        ContractProfile {
+            addresses: BTreeSet::new(),
+            count: 0,
+            gas: Gas::zero(),
+            memory_gas: Gas::zero(),
+            sloads: 0,
+            sstores: 0,
+            instructions: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Result of a gas profiler.
+pub struct GasProfile {
+    /// Statistics per contract, keyed by the Keccak hash of its
+    /// code.
+    pub contracts: BTreeMap<H256, ContractProfile>,
+    /// Gas per call path and opcode, where the call path is the
+    /// address of the outermost frame first. Suitable as folded
+    /// stacks for flame graphs.
+    pub stacks: BTreeMap<(Vec<Address>, Opcode), Gas>,
+}
+
+impl GasProfile {
+    /// Total gas used by all instructions. Refunds are not included.
+    pub fn total_gas(&self) -> Gas {
+        self.contracts.values().fold(Gas::zero(), |gas, contract| gas + contract.gas)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingInstruction {
+    position: usize,
+    opcode: Opcode,
+    used_gas: Gas,
+    memory_gas: Gas,
+}
+
+#[derive(Debug, Clone)]
+struct ProfileFrame {
+    address: Address,
+    code_hash: H256,
+    pending: Option<PendingInstruction>,
+    /// Gas attributed to this frame and its sub frames.
+    attributed: Gas,
+    /// Gas attributed to sub frames invoked by the pending
+    /// instruction.
+    sub_attributed: Gas,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Collects gas statistics of an execution. Call `record` with the
+/// current machine before every step of the VM, and once more after
+/// the VM exited. Steps that return a `RequireError` and are retried
+/// are only counted once. Gas is only attributed per instruction in
+/// `ExecutionMode::PerInstruction`.
+pub struct GasProfiler {
+    frames: Vec<ProfileFrame>,
+    profile: GasProfile,
+}
+
+impl GasProfiler {
+    /// Create a new profiler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the current machine of the VM.
+    pub fn record<M: Memory + Default, P: Patch>(&mut self, machine: &Machine<M, P>) {
+        let state = machine.state();
+        let status = machine.status();
+
+        while self.frames.len() > state.depth {
+            let frame = self.frames.pop().unwrap();
+            if let Some(parent) = self.frames.last_mut() {
+                parent.sub_attributed = parent.sub_attributed + frame.attributed;
+            }
+        }
+        if self.frames.len() < state.depth {
+            self.frames.push(ProfileFrame {
+                address: state.context.address,
+                code_hash: H256::from(Keccak256::digest(&state.context.code).as_slice()),
+                pending: None,
+                attributed: Gas::zero(),
+                sub_attributed: Gas::zero(),
+            });
+        }
+
+        let used_gas = state.total_used_gas();
+        let memory_gas = state.memory_gas();
+
+        match status {
+            MachineStatus::InvokeCall(_, _) | MachineStatus::InvokeCreate(_) => return,
+            MachineStatus::Running => {
+                let retried = match self.frames.last().unwrap().pending {
+                    Some(ref pending) =>
+                        pending.position == state.position && pending.used_gas == used_gas,
+                    None => false,
+                };
+                if retried {
+                    return;
+                }
+            },
+            _ => (),
+        }
+
+        let path: Vec<Address> = self.frames.iter().map(|frame| frame.address).collect();
+        let frame = self.frames.last_mut().unwrap();
+        if let Some(pending) = frame.pending.take() {
+            let used = if used_gas > pending.used_gas { used_gas - pending.used_gas } else { Gas::zero() };
+            let gas = if used > frame.sub_attributed { used - frame.sub_attributed } else { Gas::zero() };
+            let memory = if memory_gas > pending.memory_gas { memory_gas - pending.memory_gas } else { Gas::zero() };
+            frame.attributed = frame.attributed + frame.sub_attributed + gas;
+            frame.sub_attributed = Gas::zero();
+
+            let contract = self.profile.contracts.entry(frame.code_hash).or_insert_with(ContractProfile::default);
+            contract.addresses.insert(frame.address);
+            contract.count += 1;
+            contract.gas = contract.gas + gas;
+            contract.memory_gas = contract.memory_gas + memory;
+            match pending.opcode {
+                Opcode::SLOAD => contract.sloads += 1,
+                Opcode::SSTORE => contract.sstores += 1,
+                _ => (),
+            }
+
+            let instruction = contract.instructions.entry(pending.position).or_insert(InstructionProfile {
+                opcode: pending.opcode,
+                count: 0,
+                gas: Gas::zero(),
+                memory_gas: Gas::zero(),
+            });
+            instruction.count += 1;
+            instruction.gas = instruction.gas + gas;
+            instruction.memory_gas = instruction.memory_gas + memory;
+
+            let stack = self.profile.stacks.entry((path, pending.opcode)).or_insert_with(Gas::zero);
+            *stack = *stack + gas;
+        }
+
+        if let MachineStatus::Running = status {
+            if let Some(opcode) = machine.peek_opcode() {
+                frame.pending = Some(PendingInstruction {
+                    position: state.position,
+                    opcode,
+                    used_gas,
+                    memory_gas,
+                });
+            }
+        }
+    }
+
+    /// The collected statistics.
+    pub fn finish(self) -> GasProfile {
+        self.profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use bigint::*;
+    use hexutil::read_hex;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use sha3::{Digest, Keccak256};
+
+    fn code_hash(code: &[u8]) -> H256 {
+        H256::from(Keccak256::digest(code).as_slice())
+    }
+
+    #[test]
+    fn profile_with_sub_call() {
+        let callee = Address::from_str("0x3000000000000000000000000000000000000000").unwrap();
+        // Stores 1 at 0, loads it back, then calls the callee.
+        let code = read_hex(concat!("0x600160005560005450",
+                                    "6000600060006000600073",
+                                    "3000000000000000000000000000000000000000", "5af100")).unwrap();
+        // MSTORE(0, 1) and STOP.
+        let callee_code = read_hex("0x600160005200").unwrap();
+
+        let context = Context {
+            address: Address::from_str("0x2000000000000000000000000000000000000000").unwrap(),
+            caller: Address::default(),
+            code: Arc::new(code.clone()),
+            data: Arc::new(Vec::new()),
+            gas_limit: Gas::from(100000u64),
+            gas_price: Gas::zero(),
+            origin: Address::default(),
+            value: U256::zero(),
+            apprent_value: U256::zero(),
+            is_system: false,
+            is_static: false,
+        };
+        let address = context.address;
+        let mut vm = SeqContextVM::<VMTestPatch>::new(context, HeaderParams {
+            beneficiary: Address::default(),
+            timestamp: 0,
+            number: U256::zero(),
+            difficulty: U256::zero(),
+            gas_limit: Gas::zero(),
+        });
+
+        let mut profiler = GasProfiler::new();
+        loop {
+            profiler.record(vm.current_machine());
+            if vm.status() != VMStatus::Running {
+                break;
+            }
+            match vm.step() {
+                Ok(()) => (),
+                Err(RequireError::Account(address)) | Err(RequireError::AccountCode(address)) => {
+                    vm.commit_account(AccountCommitment::Full {
+                        nonce: U256::zero(),
+                        address,
+                        balance: U256::zero(),
+                        code: Arc::new(if address == callee { callee_code.clone() } else { Vec::new() }),
+                    }).unwrap();
+                },
+                Err(RequireError::AccountStorage(address, index)) => {
+                    vm.commit_account(AccountCommitment::Storage {
+                        address, index,
+                        value: M256::zero(),
+                    }).unwrap();
+                },
+                Err(require) => panic!("unexpected require {:?}", require),
+            }
+        }
+        assert_eq!(vm.status(), VMStatus::ExitedOk);
+
+        let profile = profiler.finish();
+        assert_eq!(profile.total_gas(), vm.used_gas());
+
+        let caller = &profile.contracts[&code_hash(&code)];
+        assert!(caller.addresses.contains(&address));
+        assert_eq!(caller.sloads, 1);
+        assert_eq!(caller.sstores, 1);
+        assert_eq!(caller.instructions[&4].opcode, Opcode::SSTORE);
+        assert_eq!(caller.instructions[&4].count, 1);
+
+        let callee_profile = &profile.contracts[&code_hash(&callee_code)];
+        assert!(callee_profile.addresses.contains(&callee));
+        assert_eq!(callee_profile.count, 4);
+        assert_eq!(callee_profile.memory_gas, Gas::from(3u64));
+        assert_eq!(profile.stacks[&(vec![address, callee], Opcode::MSTORE)], Gas::from(6u64));
+    }
+}
@@ -0,0 +1,179 @@
+//! Decoding of the data returned by `REVERT`.
+
+#[cfg(not(feature = "std"))] use alloc::vec::Vec;
+#[cfg(not(feature = "std"))] use alloc::string::String;
+#[cfg(feature = "std")] use std::fmt;
+#[cfg(not(feature = "std"))] use core::fmt;
+use bigint::U256;
+
+/// Selector of `Error(string)`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+#[derive(Debug, Clone, PartialEq)]
+/// Reason of a revert, decoded from the returned data.
+pub enum RevertReason {
+    /// Reverted without data.
+    Empty,
+    /// `Error(string)`, raised by `require` and `revert` with a
+    /// message.
+    Error(String),
+    /// `Panic(uint256)`, raised by failed assertions and checks
+    /// inserted by the Solidity compiler.
+    Panic(U256),
+    /// A custom error. Holds the selector and the encoded arguments.
+    Custom([u8; 4], Vec<u8>),
+    /// Data that is too short to hold a selector, or a malformed
+    /// `Error(string)` or `Panic(uint256)`.
+    Raw(Vec<u8>),
+}
+
+fn word(data: &[u8], offset: usize) -> Option<U256> {
+    if offset.checked_add(32).map(|end| end <= data.len()) == Some(true) {
+        Some(U256::from(&data[offset..(offset + 32)]))
+    } else {
+        None
+    }
+}
+
+fn to_usize(value: U256) -> Option<usize> {
+    if value > U256::from(usize::max_value() as u64) {
+        None
+    } else {
+        Some(value.as_usize())
+    }
+}
+
+fn decode_string(data: &[u8]) -> Option<String> {
+    let offset = to_usize(word(data, 0)?)?;
+    let len = to_usize(word(data, offset)?)?;
+    let start = offset + 32;
+    let end = start.checked_add(len)?;
+    if end > data.len() {
+        return None;
+    }
+    String::from_utf8(data[start..end].to_vec()).ok()
+}
+
+impl RevertReason {
+    /// Decode the data returned by `REVERT`.
+    pub fn decode(out: &[u8]) -> RevertReason {
+        if out.is_empty() {
+            return RevertReason::Empty;
+        }
+        if out.len() < 4 {
+            return RevertReason::Raw(out.to_vec());
+        }
+
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&out[0..4]);
+        let data = &out[4..];
+
+        if selector == ERROR_SELECTOR {
+            match decode_string(data) {
+                Some(message) => RevertReason::Error(message),
+                None => RevertReason::Raw(out.to_vec()),
+            }
+        } else if selector == PANIC_SELECTOR {
+            match word(data, 0) {
+                Some(code) if data.len() == 32 => RevertReason::Panic(code),
+                _ => RevertReason::Raw(out.to_vec()),
+            }
+        } else {
+            RevertReason::Custom(selector, data.to_vec())
+        }
+    }
+}
+
+/// Description of a Solidity panic code.
+pub fn panic_description(code: U256) -> Option<&'static str> {
+    if code > U256::from(0xffu64) {
+        return None;
+    }
+
+    match code.as_u32() {
+        0x00 => Some("generic compiler panic"),
+        0x01 => Some("assertion failed"),
+        0x11 => Some("arithmetic overflow or underflow"),
+        0x12 => Some("division or modulo by zero"),
+        0x21 => Some("invalid enum value"),
+        0x22 => Some("invalid storage byte array encoding"),
+        0x31 => Some("pop on empty array"),
+        0x32 => Some("array index out of bounds"),
+        0x41 => Some("too much memory allocated"),
+        0x51 => Some("call to zero-initialized function"),
+        _ => None,
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RevertReason::Empty => write!(f, "reverted"),
+            RevertReason::Error(ref message) => write!(f, "reverted: {}", message),
+            RevertReason::Panic(code) => match panic_description(code) {
+                Some(description) => write!(f, "panic 0x{:02x}: {}", code.as_u32(), description),
+                None => write!(f, "panic 0x{:x}", code),
+            },
+            RevertReason::Custom(ref selector, _) => {
+                write!(f, "custom error ")?;
+                write_hex(f, selector)
+            },
+            RevertReason::Raw(ref data) => {
+                write!(f, "reverted with ")?;
+                write_hex(f, data)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigint::U256;
+    use hexutil::read_hex;
+
+    #[test]
+    fn decode_error_string() {
+        let out = read_hex(concat!(
+            "0x08c379a0",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "000000000000000000000000000000000000000000000000000000000000000e",
+            "6e6f7420656e6f75676820455448000000000000000000000000000000000000")).unwrap();
+        let reason = RevertReason::decode(&out);
+        assert_eq!(reason, RevertReason::Error("not enough ETH".to_string()));
+        assert_eq!(format!("{}", reason), "reverted: not enough ETH");
+    }
+
+    #[test]
+    fn decode_panic() {
+        let out = read_hex(concat!(
+            "0x4e487b71",
+            "0000000000000000000000000000000000000000000000000000000000000011")).unwrap();
+        let reason = RevertReason::decode(&out);
+        assert_eq!(reason, RevertReason::Panic(U256::from(0x11u64)));
+        assert_eq!(format!("{}", reason), "panic 0x11: arithmetic overflow or underflow");
+    }
+
+    #[test]
+    fn decode_custom_and_malformed() {
+        let out = read_hex("0xdeadbeef0102").unwrap();
+        assert_eq!(RevertReason::decode(&out), RevertReason::Custom([0xde, 0xad, 0xbe, 0xef], vec![0x01, 0x02]));
+        assert_eq!(format!("{}", RevertReason::decode(&out)), "custom error 0xdeadbeef");
+
+        let out = read_hex(concat!(
+            "0x08c379a0",
+            "00000000000000000000000000000000000000000000000000000000000000ff")).unwrap();
+        assert_eq!(RevertReason::decode(&out), RevertReason::Raw(out.clone()));
+        assert_eq!(RevertReason::decode(&[]), RevertReason::Empty);
+    }
+}
@@ -2,32 +2,54 @@
 
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
+#[cfg(feature = "std")] use std::{fmt, ptr};
+#[cfg(not(feature = "std"))] use core::{fmt, ptr};
 
 use bigint::M256;
 use super::errors::OnChainError;
 
-/// Represents an EVM stack.
-#[derive(Debug)]
+/// Maximum number of items on the stack.
+pub const STACK_LIMIT: usize = 1024;
+
+/// Represents an EVM stack. Room for all `STACK_LIMIT` slots is
+/// reserved upfront, so that pushing never reallocates, but a slot is
+/// only written when a value is pushed to it.
 pub struct Stack {
-    stack: Vec<M256>,
+    data: Vec<M256>,
 }
 
 impl Default for Stack {
     fn default() -> Stack {
         Stack {
-            stack: Vec::new(),
+            data: Vec::with_capacity(STACK_LIMIT),
         }
     }
 }
 
+impl Clone for Stack {
+    fn clone(&self) -> Stack {
+        // A cloned `Vec` only has room for its values.
+        let mut data = Vec::with_capacity(STACK_LIMIT);
+        data.extend_from_slice(&self.data);
+        Stack { data }
+    }
+}
+
+impl fmt::Debug for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.data.iter()).finish()
+    }
+}
+
 impl Stack {
     /// Check a pop-push cycle. If the check succeeded, `push`, `pop`,
-    /// `set`, `peek` within the limit should not fail.
+    /// `set`, `peek` within the limit should not fail, and the
+    /// unchecked variants can be used instead.
     pub fn check_pop_push(&self, pop: usize, push: usize) -> Result<(), OnChainError> {
-        if self.len() < pop {
+        if self.data.len() < pop {
             return Err(OnChainError::StackUnderflow);
         }
-        if self.len() - pop + push > 1024 {
+        if self.data.len() - pop + push > STACK_LIMIT {
             return Err(OnChainError::StackOverflow);
         }
         Ok(())
@@ -35,30 +57,27 @@ impl Stack {
 
     /// Push a new value to the stack.
     pub fn push(&mut self, elem: M256) -> Result<(), OnChainError> {
-        self.stack.push(elem);
-        if self.len() > 1024 {
-            self.stack.pop();
-            Err(OnChainError::StackOverflow)
-        } else {
-            Ok(())
+        if self.data.len() == STACK_LIMIT {
+            return Err(OnChainError::StackOverflow);
         }
+        unsafe { self.push_unchecked(elem) };
+        Ok(())
     }
 
     /// Pop a value from the stack.
     pub fn pop(&mut self) -> Result<M256, OnChainError> {
-        match self.stack.pop() {
-            Some(x) => Ok(x),
-            None => Err(OnChainError::StackUnderflow),
+        if self.data.is_empty() {
+            return Err(OnChainError::StackUnderflow);
         }
+        Ok(unsafe { self.pop_unchecked() })
     }
 
     /// Set a value at given index for the stack, where the top of the
     /// stack is at index `0`. If the index is too large,
     /// `StackError::Underflow` is returned.
     pub fn set(&mut self, no_from_top: usize, val: M256) -> Result<(), OnChainError> {
-        if self.stack.len() > no_from_top {
-            let len = self.stack.len();
-            self.stack[len - no_from_top - 1] = val;
+        if self.data.len() > no_from_top {
+            unsafe { self.set_unchecked(no_from_top, val) };
             Ok(())
         } else {
             Err(OnChainError::StackUnderflow)
@@ -69,20 +88,150 @@ impl Stack {
     /// the stack is at index `0`. If the index is too large,
     /// `StackError::Underflow` is returned.
     pub fn peek(&self, no_from_top: usize) -> Result<M256, OnChainError> {
-        if self.stack.len() > no_from_top {
-            Ok(self.stack[self.stack.len() - no_from_top - 1])
+        if self.data.len() > no_from_top {
+            Ok(unsafe { self.peek_unchecked(no_from_top) })
         } else {
             Err(OnChainError::StackUnderflow)
         }
     }
 
+    /// Duplicate the `n`-th value from the top, counting from `1`, as
+    /// `DUPn` does.
+    pub fn dup(&mut self, n: usize) -> Result<(), OnChainError> {
+        self.check_pop_push(n, n + 1)?;
+        unsafe { self.dup_unchecked(n) };
+        Ok(())
+    }
+
+    /// Swap the top value with the `n + 1`-th one, as `SWAPn` does.
+    pub fn swap(&mut self, n: usize) -> Result<(), OnChainError> {
+        self.check_pop_push(n + 1, n + 1)?;
+        unsafe { self.swap_unchecked(n) };
+        Ok(())
+    }
+
+    /// Push a new value, without checking for overflow.
+    ///
+    /// # Safety
+    ///
+    /// The stack must not be full, as checked by `check_pop_push`.
+    #[inline]
+    pub unsafe fn push_unchecked(&mut self, elem: M256) {
+        debug_assert!(self.data.len() < STACK_LIMIT);
+        let len = self.data.len();
+        ptr::write(self.data.as_mut_ptr().add(len), elem);
+        self.data.set_len(len + 1);
+    }
+
+    /// Pop a value, without checking for underflow.
+    ///
+    /// # Safety
+    ///
+    /// The stack must not be empty, as checked by `check_pop_push`.
+    #[inline]
+    pub unsafe fn pop_unchecked(&mut self) -> M256 {
+        debug_assert!(!self.data.is_empty());
+        let len = self.data.len() - 1;
+        self.data.set_len(len);
+        ptr::read(self.data.as_ptr().add(len))
+    }
+
+    /// Peek a value at given index from the top, without checking the
+    /// stack height.
+    ///
+    /// # Safety
+    ///
+    /// The stack must hold more than `no_from_top` values.
+    #[inline]
+    pub unsafe fn peek_unchecked(&self, no_from_top: usize) -> M256 {
+        debug_assert!(self.data.len() > no_from_top);
+        *self.data.get_unchecked(self.data.len() - no_from_top - 1)
+    }
+
+    /// Set a value at given index from the top, without checking the
+    /// stack height.
+    ///
+    /// # Safety
+    ///
+    /// The stack must hold more than `no_from_top` values.
+    #[inline]
+    pub unsafe fn set_unchecked(&mut self, no_from_top: usize, val: M256) {
+        debug_assert!(self.data.len() > no_from_top);
+        let index = self.data.len() - no_from_top - 1;
+        *self.data.get_unchecked_mut(index) = val;
+    }
+
+    /// `DUPn` without checking the stack height.
+    ///
+    /// # Safety
+    ///
+    /// The check of `dup` must succeed.
+    #[inline]
+    pub unsafe fn dup_unchecked(&mut self, n: usize) {
+        let val = self.peek_unchecked(n - 1);
+        self.push_unchecked(val);
+    }
+
+    /// `SWAPn` without checking the stack height.
+    ///
+    /// # Safety
+    ///
+    /// The check of `swap` must succeed.
+    #[inline]
+    pub unsafe fn swap_unchecked(&mut self, n: usize) {
+        debug_assert!(self.data.len() > n);
+        let top = self.data.as_mut_ptr().add(self.data.len() - 1);
+        ptr::swap(top, top.sub(n));
+    }
+
     /// Get the current stack length.
     #[inline]
     pub fn len(&self) -> usize {
-        self.stack.len()
+        self.data.len()
     }
 
     /// Returns true if stack is empty
     #[inline]
     pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Values on the stack, from the bottom to the top.
+    pub fn to_vec(&self) -> Vec<M256> {
+        self.data.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_pop_limits() {
+        let mut stack = Stack::default();
+        assert_eq!(stack.pop(), Err(OnChainError::StackUnderflow));
+
+        for i in 0..STACK_LIMIT {
+            stack.push(M256::from(i as u64)).unwrap();
+        }
+        assert_eq!(stack.push(M256::zero()), Err(OnChainError::StackOverflow));
+        assert_eq!(stack.len(), STACK_LIMIT);
+        assert_eq!(stack.peek(0), Ok(M256::from((STACK_LIMIT - 1) as u64)));
+        assert_eq!(stack.pop(), Ok(M256::from((STACK_LIMIT - 1) as u64)));
+        assert_eq!(stack.len(), STACK_LIMIT - 1);
+    }
+
+    #[test]
+    fn dup_and_swap() {
+        let mut stack = Stack::default();
+        for i in 1..4u64 {
+            stack.push(M256::from(i)).unwrap();
+        }
+
+        stack.dup(3).unwrap();
+        assert_eq!(stack.to_vec(), vec![M256::from(1u64), M256::from(2u64), M256::from(3u64), M256::from(1u64)]);
+        stack.swap(2).unwrap();
+        assert_eq!(stack.to_vec(), vec![M256::from(1u64), M256::from(1u64), M256::from(3u64), M256::from(2u64)]);
+
+        assert_eq!(stack.dup(5), Err(OnChainError::StackUnderflow));
+        assert_eq!(stack.swap(4), Err(OnChainError::StackUnderflow));
+    }
 }
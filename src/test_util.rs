@@ -0,0 +1,118 @@
+//! Fixtures shared by the unit tests: a caller calling a contract,
+//! a block header, and answers to the requirements of a VM from a
+//! list of accounts.
+
+use bigint::{Address, Gas, U256, M256};
+use block_core::TransactionAction;
+use hexutil::read_hex;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use errors::{RequireError, CommitError};
+use {ValidTransaction, HeaderParams, AccountCommitment};
+
+pub fn address(value: &str) -> Address {
+    Address::from_str(value).unwrap()
+}
+
+/// Caller of the test transactions, an account without code.
+pub fn caller() -> Address {
+    address("0x1000000000000000000000000000000000000000")
+}
+
+/// Contract called by the test transactions.
+pub fn contract() -> Address {
+    address("0x2000000000000000000000000000000000000000")
+}
+
+/// An account with code, holding `storage` at index zero.
+pub struct Account {
+    pub address: Address,
+    pub code: Vec<u8>,
+    pub storage: M256,
+}
+
+pub fn account(address: &str, code: &str, storage: u64) -> Account {
+    Account {
+        address: self::address(address),
+        code: read_hex(code).unwrap(),
+        storage: M256::from(storage),
+    }
+}
+
+/// A call from the caller to `to` without value and gas price.
+pub fn transaction(to: Address, gas_limit: Gas) -> ValidTransaction {
+    ValidTransaction {
+        caller: Some(caller()),
+        gas_price: Gas::zero(),
+        gas_limit,
+        action: TransactionAction::Call(to),
+        value: U256::zero(),
+        input: Arc::new(Vec::new()),
+        nonce: U256::zero(),
+    }
+}
+
+pub fn header() -> HeaderParams {
+    HeaderParams {
+        beneficiary: address("0x4000000000000000000000000000000000000000"),
+        timestamp: 0,
+        number: U256::zero(),
+        difficulty: U256::zero(),
+        gas_limit: Gas::from(1000000u64),
+    }
+}
+
+/// Answer an account requirement from the accounts. The caller is
+/// empty and other addresses do not exist.
+pub fn answer(accounts: &[Account], require: RequireError) -> AccountCommitment {
+    let (address, index) = match require {
+        RequireError::Account(address) | RequireError::AccountCode(address) => (address, None),
+        RequireError::AccountStorage(address, index) => (address, Some(index)),
+        RequireError::Blockhash(number) => panic!("unexpected blockhash requirement {}", number),
+    };
+
+    if address == caller() && index.is_none() {
+        return AccountCommitment::Full {
+            nonce: U256::zero(),
+            address,
+            balance: U256::zero(),
+            code: Arc::new(Vec::new()),
+        };
+    }
+
+    match (accounts.iter().find(|account| account.address == address), index) {
+        (Some(account), None) => AccountCommitment::Full {
+            nonce: U256::zero(),
+            address,
+            balance: U256::zero(),
+            code: Arc::new(account.code.clone()),
+        },
+        (Some(account), Some(index)) => AccountCommitment::Storage {
+            address,
+            index,
+            value: if index == U256::zero() { account.storage } else { M256::zero() },
+        },
+        (None, Some(index)) => AccountCommitment::Storage { address, index, value: M256::zero() },
+        (None, None) => AccountCommitment::Nonexist(address),
+    }
+}
+
+/// Call `run` on the target until it no longer requires anything,
+/// committing the answers from the accounts. Returns its result and
+/// the number of requirements answered.
+pub fn answer_all<V, T, R, C>(accounts: &[Account], target: &mut V, mut run: R, mut commit: C) -> (T, usize) where
+    R: FnMut(&mut V) -> Result<T, RequireError>,
+    C: FnMut(&mut V, AccountCommitment) -> Result<(), CommitError>,
+{
+    let mut queries = 0;
+    loop {
+        match run(target) {
+            Ok(result) => return (result, queries),
+            Err(require) => {
+                queries += 1;
+                commit(target, answer(accounts, require)).unwrap();
+            },
+        }
+    }
+}
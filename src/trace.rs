@@ -0,0 +1,173 @@
+//! Call tree tracing.
+
+#[cfg(not(feature = "std"))] use alloc::vec::Vec;
+use bigint::{U256, Gas, Address};
+
+use super::errors::OnChainError;
+use super::revert::RevertReason;
+use super::{Machine, MachineStatus, Memory, Patch};
+
+#[derive(Debug, Clone, PartialEq)]
+/// A call or create in the call tree.
+pub struct CallFrame {
+    /// Whether this frame is a contract creation.
+    pub create: bool,
+    /// Caller of the frame.
+    pub caller: Address,
+    /// Address of the frame.
+    pub address: Address,
+    /// Value transferred.
+    pub value: U256,
+    /// Gas limit of the frame.
+    pub gas_limit: Gas,
+    /// Gas used by the frame, including its sub frames.
+    pub gas_used: Gas,
+    /// Input data, or init code for contract creation.
+    pub input: Vec<u8>,
+    /// Returned data.
+    pub output: Vec<u8>,
+    /// Error the frame exited with, if any.
+    pub error: Option<OnChainError>,
+    /// Decoded revert reason, if the frame reverted.
+    pub revert_reason: Option<RevertReason>,
+    /// Sub frames, in the order they were invoked.
+    pub calls: Vec<CallFrame>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Builds the call tree of an execution. Call `record` with the
+/// current machine before every step of the VM, and once more after
+/// the VM exited.
+pub struct CallTracer {
+    frames: Vec<CallFrame>,
+    invoked_create: bool,
+    root: Option<CallFrame>,
+}
+
+impl CallTracer {
+    /// Create a new tracer. `create` tells whether the execution
+    /// starts with a contract creation.
+    pub fn new(create: bool) -> Self {
+        CallTracer {
+            frames: Vec::new(),
+            invoked_create: create,
+            root: None,
+        }
+    }
+
+    /// Record the current machine of the VM.
+    pub fn record<M: Memory + Default, P: Patch>(&mut self, machine: &Machine<M, P>) {
+        let state = machine.state();
+        let status = machine.status();
+
+        if self.root.is_some() {
+            return;
+        }
+
+        if self.frames.len() < state.depth {
+            self.frames.push(CallFrame {
+                create: self.invoked_create,
+                caller: state.context.caller,
+                address: state.context.address,
+                value: state.context.value,
+                gas_limit: state.context.gas_limit,
+                gas_used: Gas::zero(),
+                input: if self.invoked_create {
+                    state.context.code.to_vec()
+                } else {
+                    state.context.data.to_vec()
+                },
+                output: Vec::new(),
+                error: None,
+                revert_reason: None,
+                calls: Vec::new(),
+            });
+        }
+
+        match status {
+            MachineStatus::InvokeCall(_, _) => self.invoked_create = false,
+            MachineStatus::InvokeCreate(_) => self.invoked_create = true,
+            MachineStatus::ExitedOk | MachineStatus::ExitedErr(_) |
+            MachineStatus::ExitedNotSupported(_) if self.frames.len() == state.depth => {
+                let mut frame = self.frames.pop().unwrap();
+                frame.gas_used = state.total_used_gas();
+                frame.output = state.out.to_vec();
+                if let MachineStatus::ExitedErr(ref err) = status {
+                    if *err == OnChainError::Revert {
+                        frame.revert_reason = Some(RevertReason::decode(&frame.output));
+                    }
+                    frame.error = Some(err.clone());
+                }
+
+                match self.frames.last_mut() {
+                    Some(parent) => parent.calls.push(frame),
+                    None => self.root = Some(frame),
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// The call tree, once the outermost frame exited.
+    pub fn finish(self) -> Option<CallFrame> {
+        self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use bigint::*;
+    use test_util::{address, account, contract, header, answer_all};
+    use std::sync::Arc;
+
+    #[test]
+    fn call_tree_with_revert() {
+        let callee = address("0x3000000000000000000000000000000000000000");
+        let accounts = [
+            // Calls the callee, which reverts with `Error("no")`.
+            account("0x2000000000000000000000000000000000000000",
+                    concat!("0x6000600060006000600073",
+                            "3000000000000000000000000000000000000000", "5af100"), 0),
+            account("0x3000000000000000000000000000000000000000", concat!(
+                "0x7f08c379a000000000000000000000000000000000000000000000000000000000600052",
+                "7f0000000000000000000000000000000000000000000000000000000000000020600452",
+                "7f0000000000000000000000000000000000000000000000000000000000000002602452",
+                "7f6e6f000000000000000000000000000000000000000000000000000000000000604452",
+                "60646000fd"), 0),
+        ];
+
+        let context = Context {
+            address: contract(),
+            caller: Address::default(),
+            code: Arc::new(accounts[0].code.clone()),
+            data: Arc::new(Vec::new()),
+            gas_limit: Gas::from(100000u64),
+            gas_price: Gas::zero(),
+            origin: Address::default(),
+            value: U256::zero(),
+            apprent_value: U256::zero(),
+            is_system: false,
+            is_static: false,
+        };
+        let mut vm = SeqContextVM::<VMTestPatch>::new(context, header());
+
+        let mut tracer = CallTracer::new(false);
+        answer_all(&accounts, &mut vm, |vm| {
+            loop {
+                tracer.record(vm.current_machine());
+                if vm.status() != VMStatus::Running {
+                    return Ok(());
+                }
+                vm.step()?;
+            }
+        }, |vm, commitment| vm.commit_account(commitment));
+
+        let root = tracer.finish().unwrap();
+        assert_eq!(root.error, None);
+        assert_eq!(root.calls.len(), 1);
+        assert_eq!(root.calls[0].address, callee);
+        assert_eq!(root.calls[0].error, Some(OnChainError::Revert));
+        assert_eq!(root.calls[0].revert_reason, Some(RevertReason::Error("no".to_string())));
+    }
+}
@@ -3,13 +3,13 @@
 #[cfg(not(feature = "std"))]
 use alloc::vec::Vec;
 
-#[cfg(not(feature = "std"))] use alloc::rc::Rc;
-#[cfg(feature = "std")] use std::rc::Rc;
+#[cfg(not(feature = "std"))] use alloc::sync::Arc;
+#[cfg(feature = "std")] use std::sync::Arc;
 
-#[cfg(feature = "std")] use std::collections::{HashSet as Set, hash_map as map};
+#[cfg(feature = "std")] use std::collections::{HashSet as Set, HashMap as Map, hash_map as map};
 #[cfg(feature = "std")] use std::cmp::min;
 #[cfg(feature = "std")] use std::ops::Deref;
-#[cfg(not(feature = "std"))] use alloc::{collections::BTreeSet as Set, collections::btree_map as map};
+#[cfg(not(feature = "std"))] use alloc::{collections::BTreeSet as Set, collections::BTreeMap as Map, collections::btree_map as map};
 #[cfg(not(feature = "std"))] use core::cmp::min;
 #[cfg(not(feature = "std"))] use core::ops::Deref;
 use bigint::{U256, H256, Address, Gas};
@@ -18,7 +18,8 @@ use super::errors::{RequireError, CommitError, PreExecutionError};
 use super::{State, Machine, Context, ContextVM, VM, AccountState,
             BlockhashState, Patch, HeaderParams, Memory, VMStatus,
             AccountCommitment, Log, AccountChange,
-            Instruction, Opcode};
+            Instruction, Opcode, AccessListItem, CodeCache, ExecutionMode};
+use super::access_list::access_list_gas;
 
 use block_core::TransactionAction;
 #[cfg(feature = "std")]
@@ -32,6 +33,13 @@ static SYSTEM_ADDRESS: [u8; 20] = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf
                                    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
                                    0xff, 0xff, 0xff, 0xff];
 
+#[cfg(feature = "std")]
+/// Order of the secp256k1 curve.
+static SECP256K1N: [u8; 32] = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                               0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+                               0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b,
+                               0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41];
+
 macro_rules! system_address {
     () => {
         Address::from(SYSTEM_ADDRESS.as_ref())
@@ -52,7 +60,7 @@ pub struct UntrustedTransaction {
     /// Value sent with this transaction.
     pub value: U256,
     /// Transaction input.
-    pub input: Rc<Vec<u8>>,
+    pub input: Arc<Vec<u8>>,
 }
 
 impl UntrustedTransaction {
@@ -99,6 +107,37 @@ impl UntrustedTransaction {
     }
 }
 
+#[cfg(feature = "std")]
+impl UntrustedTransaction {
+    /// Recover the caller of a block transaction. The signature must
+    /// have `r` and `s` values in range, a low `s` value if the patch
+    /// requires it, and a `v` value of 27 or 28, or an EIP155 value of
+    /// the patch's chain id if the patch accepts them.
+    pub fn caller_of<P: Patch>(transaction: &Transaction) -> Result<Address, PreExecutionError> {
+        let signature = &transaction.signature;
+        let n = U256::from(&SECP256K1N[..]);
+        let r = U256::from(&signature.r[..]);
+        let s = U256::from(&signature.s[..]);
+        if r.is_zero() || r >= n || s.is_zero() || s >= n {
+            return Err(PreExecutionError::InvalidCaller);
+        }
+        if P::has_low_s_signature() && s > n / U256::from(2u64) {
+            return Err(PreExecutionError::InvalidCaller);
+        }
+
+        let v = signature.v;
+        let valid_v = v == 27 || v == 28 || (P::has_eip155_signature() && v >= 35 && match P::chain_id() {
+            Some(chain_id) => v == chain_id * 2 + 35 || v == chain_id * 2 + 36,
+            None => true,
+        });
+        if !valid_v {
+            return Err(PreExecutionError::InvalidCaller);
+        }
+
+        transaction.caller().map_err(|_| PreExecutionError::InvalidCaller)
+    }
+}
+
 #[derive(Debug, Clone)]
 /// Represents an Ethereum transaction.
 ///
@@ -127,7 +166,7 @@ pub struct ValidTransaction {
     /// Value of this transaction.
     pub value: U256,
     /// Data or init associated with this transaction.
-    pub input: Rc<Vec<u8>>,
+    pub input: Arc<Vec<u8>>,
     /// Nonce of the transaction.
     pub nonce: U256,
 }
@@ -135,13 +174,14 @@ pub struct ValidTransaction {
 #[cfg(feature = "std")]
 impl ValidTransaction {
     /// Create a valid transaction from a block transaction. Caller is
-    /// always Some.
+    /// always Some, and its signature is checked with
+    /// `UntrustedTransaction::caller_of`.
     pub fn from_transaction<P: Patch>(
         transaction: &Transaction, account_state: &AccountState<P::Account>
     ) -> Result<Result<ValidTransaction, PreExecutionError>, RequireError> {
-        let caller = match transaction.caller() {
+        let caller = match UntrustedTransaction::caller_of::<P>(transaction) {
             Ok(val) => val,
-            Err(_) => return Ok(Err(PreExecutionError::InvalidCaller)),
+            Err(err) => return Ok(Err(err)),
         };
 
         let nonce = account_state.nonce(caller)?;
@@ -155,7 +195,7 @@ impl ValidTransaction {
             gas_limit: transaction.gas_limit,
             action: transaction.action,
             value: transaction.value,
-            input: Rc::new(transaction.input.clone()),
+            input: Arc::new(transaction.input.clone()),
             nonce,
         };
 
@@ -255,7 +295,7 @@ impl ValidTransaction {
                     gas_price: self.gas_price,
                     value: self.value,
                     gas_limit: self.gas_limit - upfront,
-                    data: Rc::new(Vec::new()),
+                    data: Arc::new(Vec::new()),
                     code: self.input,
                     origin: origin.unwrap_or(self.caller.unwrap_or(system_address!())),
                     apprent_value: self.value,
@@ -285,15 +325,50 @@ enum TransactionVMState<M, P: Patch> {
     Constructing {
         transaction: ValidTransaction,
         block: HeaderParams,
+        access_list_gas: Gas,
 
         account_state: AccountState<P::Account>,
         blockhash_state: BlockhashState,
+        code_cache: Option<Arc<CodeCache<P>>>,
+        execution_mode: ExecutionMode,
     },
 }
 
+impl<M: Clone, P: Patch> Clone for TransactionVMState<M, P> {
+    fn clone(&self) -> Self {
+        match *self {
+            TransactionVMState::Running {
+                ref vm, intrinsic_gas, preclaimed_value, finalized, code_deposit, ref fresh_account_state,
+            } => TransactionVMState::Running {
+                vm: vm.clone(),
+                intrinsic_gas, preclaimed_value, finalized, code_deposit,
+                fresh_account_state: fresh_account_state.clone(),
+            },
+            TransactionVMState::Constructing {
+                ref transaction, ref block, access_list_gas,
+                ref account_state, ref blockhash_state, ref code_cache, execution_mode,
+            } => TransactionVMState::Constructing {
+                transaction: transaction.clone(),
+                block: block.clone(),
+                access_list_gas,
+                account_state: account_state.clone(),
+                blockhash_state: blockhash_state.clone(),
+                code_cache: code_cache.clone(),
+                execution_mode,
+            },
+        }
+    }
+}
+
 /// A VM that executes using a transaction and block information.
 pub struct TransactionVM<M, P: Patch>(TransactionVMState<M, P>);
 
+impl<M: Clone, P: Patch> Clone for TransactionVM<M, P> {
+    fn clone(&self) -> Self {
+        TransactionVM(self.0.clone())
+    }
+}
+
 impl<M: Memory + Default, P: Patch> TransactionVM<M, P> {
     /// Create a VM from an untrusted transaction. It can be any
     /// transaction and the VM will return an error if it has errors.
@@ -302,8 +377,11 @@ impl<M: Memory + Default, P: Patch> TransactionVM<M, P> {
         let mut vm = TransactionVM(TransactionVMState::Constructing {
             transaction: valid,
             block,
+            access_list_gas: Gas::zero(),
             account_state: AccountState::default(),
             blockhash_state: BlockhashState::default(),
+            code_cache: None,
+            execution_mode: ExecutionMode::PerInstruction,
         });
         vm.commit_account(transaction.caller).unwrap();
         Ok(vm)
@@ -315,24 +393,68 @@ impl<M: Memory + Default, P: Patch> TransactionVM<M, P> {
         TransactionVM(TransactionVMState::Constructing {
             transaction,
             block,
+            access_list_gas: Gas::zero(),
             account_state: AccountState::default(),
             blockhash_state: BlockhashState::default(),
+            code_cache: None,
+            execution_mode: ExecutionMode::PerInstruction,
+        })
+    }
+
+    /// Create a new VM using the given transaction, block header and
+    /// the account and blockhash states committed beforehand.
+    pub fn with_states(
+        transaction: ValidTransaction, block: HeaderParams,
+        account_state: AccountState<P::Account>, blockhash_state: BlockhashState
+    ) -> Self {
+        TransactionVM(TransactionVMState::Constructing {
+            transaction,
+            block,
+            access_list_gas: Gas::zero(),
+            account_state,
+            blockhash_state,
+            code_cache: None,
+            execution_mode: ExecutionMode::PerInstruction,
+        })
+    }
+
+    /// Create a new VM like `with_states`, charging the EIP-2930
+    /// access list as part of the intrinsic gas. The VM has no
+    /// warm and cold accesses, so this is the only effect of the
+    /// access list.
+    pub fn with_access_list(
+        transaction: ValidTransaction, block: HeaderParams, access_list: &[AccessListItem],
+        account_state: AccountState<P::Account>, blockhash_state: BlockhashState
+    ) -> Self {
+        TransactionVM(TransactionVMState::Constructing {
+            transaction,
+            block,
+            access_list_gas: access_list_gas(access_list),
+            account_state,
+            blockhash_state,
+            code_cache: None,
+            execution_mode: ExecutionMode::PerInstruction,
         })
     }
 
     /// Create a new VM with the result of the previous VM. This is
-    /// usually used by transaction for chaining them.
+    /// usually used by transaction for chaining them. If the previous
+    /// VM is still running, the new VM starts from the account state
+    /// of its current call, including changes that may still be
+    /// reverted. The code cache and execution mode of the previous VM
+    /// are kept.
     pub fn with_previous(
         transaction: ValidTransaction, block: HeaderParams, vm: &TransactionVM<M, P>
     ) -> Self {
         TransactionVM(TransactionVMState::Constructing {
             transaction,
             block,
+            access_list_gas: Gas::zero(),
             account_state: match vm.0 {
                 TransactionVMState::Constructing { ref account_state, .. } =>
                     account_state.clone(),
                 TransactionVMState::Running { ref vm, .. } =>
-                    vm.machines[0].state().account_state.clone(),
+                    vm.current_state().account_state.clone(),
             },
             blockhash_state: match vm.0 {
                 TransactionVMState::Constructing { ref blockhash_state, .. } =>
@@ -340,9 +462,38 @@ impl<M: Memory + Default, P: Patch> TransactionVM<M, P> {
                 TransactionVMState::Running { ref vm, .. } =>
                     vm.runtime.blockhash_state.clone(),
             },
+            code_cache: match vm.0 {
+                TransactionVMState::Constructing { ref code_cache, .. } =>
+                    code_cache.clone(),
+                TransactionVMState::Running { ref vm, .. } =>
+                    vm.code_cache().cloned(),
+            },
+            execution_mode: match vm.0 {
+                TransactionVMState::Constructing { execution_mode, .. } =>
+                    execution_mode,
+                TransactionVMState::Running { ref vm, .. } =>
+                    vm.execution_mode(),
+            },
         })
     }
 
+    /// Use the cache for the code run by this VM. The cache can be
+    /// shared with other VMs running with the same patch.
+    pub fn set_code_cache(&mut self, cache: Arc<CodeCache<P>>) {
+        match self.0 {
+            TransactionVMState::Running { ref mut vm, .. } => vm.set_code_cache(cache),
+            TransactionVMState::Constructing { ref mut code_cache, .. } => *code_cache = Some(cache),
+        }
+    }
+
+    /// Set how gas and stack bounds are checked.
+    pub fn set_execution_mode(&mut self, mode: ExecutionMode) {
+        match self.0 {
+            TransactionVMState::Running { ref mut vm, .. } => vm.set_execution_mode(mode),
+            TransactionVMState::Constructing { ref mut execution_mode, .. } => *execution_mode = mode,
+        }
+    }
+
     /// Returns the current state of the VM.
     pub fn current_state(&self) -> Option<&State<M, P>> {
         self.current_machine().map(|m| m.state())
@@ -407,6 +558,8 @@ impl<M: Memory + Default, P: Patch> VM for TransactionVM<M, P> {
         let cblock: HeaderParams;
         let caccount_state: AccountState<P::Account>;
         let cblockhash_state: BlockhashState;
+        let ccode_cache: Option<Arc<CodeCache<P>>>;
+        let cexecution_mode: ExecutionMode;
         let ccode_deposit: bool;
         let cpreclaimed_value: U256;
 
@@ -448,8 +601,9 @@ impl<M: Memory + Default, P: Patch> VM for TransactionVM<M, P> {
                 }
             }
             TransactionVMState::Constructing {
-                ref transaction, ref block,
-                ref mut account_state, ref blockhash_state } => {
+                ref transaction, ref block, access_list_gas,
+                ref mut account_state, ref blockhash_state, ref code_cache,
+                execution_mode } => {
 
                 let address = transaction.address();
                 account_state.require(address)?;
@@ -458,12 +612,14 @@ impl<M: Memory + Default, P: Patch> VM for TransactionVM<M, P> {
                     TransactionAction::Create | TransactionAction::Create2(..) => true,
                     TransactionAction::Call(_) => false,
                 };
-                cgas = transaction.intrinsic_gas::<P>();
+                cgas = transaction.intrinsic_gas::<P>() + access_list_gas;
                 cpreclaimed_value = transaction.preclaimed_value();
                 ccontext = transaction.clone().into_context::<P>(cgas, None, account_state, false, false)?;
                 cblock = block.clone();
                 caccount_state = account_state.clone();
                 cblockhash_state = blockhash_state.clone();
+                ccode_cache = code_cache.clone();
+                cexecution_mode = execution_mode;
             }
         }
 
@@ -473,6 +629,10 @@ impl<M: Memory + Default, P: Patch> VM for TransactionVM<M, P> {
             account_state.clone(),
             cblockhash_state,
             |vm| {
+                if let Some(cache) = ccode_cache {
+                    vm.set_code_cache(cache);
+                }
+                vm.set_execution_mode(cexecution_mode);
                 if ccode_deposit {
                     vm.machines[0].initialize_create(cpreclaimed_value).unwrap();
                 } else {
@@ -506,6 +666,13 @@ impl<M: Memory + Default, P: Patch> VM for TransactionVM<M, P> {
         }
     }
 
+    fn used_storage(&self) -> Map<Address, Set<U256>> {
+        match self.0 {
+            TransactionVMState::Running { ref vm, .. } => vm.used_storage(),
+            TransactionVMState::Constructing { ref account_state, .. } => account_state.used_storage(),
+        }
+    }
+
     fn out(&self) -> &[u8] {
         match self.0 {
             TransactionVMState::Running { ref vm, .. } => vm.out(),
@@ -558,9 +725,120 @@ impl<M: Memory + Default, P: Patch> VM for TransactionVM<M, P> {
 mod tests {
     use ::*;
     use bigint::*;
-    use block::TransactionAction;
+    use block::{Transaction, TransactionAction};
+    use hexutil::read_hex;
+    use rlp;
     use std::str::FromStr;
-    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::thread;
+    use test_util::{account, contract, transaction, header, answer_all};
+
+    #[test]
+    fn vm_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<SeqTransactionVM<EmbeddedPatch>>();
+        assert_send::<SeqContextVM<EmbeddedPatch>>();
+        assert_send::<AccountChange>();
+        assert_send::<CodeCache<EmbeddedPatch>>();
+    }
+
+    #[test]
+    fn run_on_other_thread() {
+        let transaction = ValidTransaction {
+            caller: None,
+            gas_price: Gas::zero(),
+            gas_limit: Gas::from(100000u64),
+            action: TransactionAction::Call(Address::default()),
+            value: U256::zero(),
+            input: Arc::new(Vec::new()),
+            nonce: U256::zero(),
+        };
+        let cache = Arc::new(CodeCache::<EmbeddedPatch>::new(16));
+        let mut vm = SeqTransactionVM::<EmbeddedPatch>::new(transaction, HeaderParams {
+            beneficiary: Address::default(),
+            timestamp: 0,
+            number: U256::zero(),
+            difficulty: U256::zero(),
+            gas_limit: Gas::zero(),
+        });
+        vm.set_code_cache(cache.clone());
+
+        let status = thread::spawn(move || {
+            vm.commit_account(AccountCommitment::Full {
+                nonce: U256::zero(),
+                address: Address::default(),
+                balance: U256::zero(),
+                code: Arc::new(vec![0x00]),
+            }).unwrap();
+            vm.fire().unwrap();
+            vm.status()
+        }).join().unwrap();
+
+        assert_eq!(status, VMStatus::ExitedOk);
+        assert_eq!(cache.misses(), 1);
+    }
+
+    #[test]
+    fn with_previous_during_call() {
+        // The contract calls the other account, which loops forever.
+        let accounts = [
+            account("0x2000000000000000000000000000000000000000",
+                    concat!("0x6000600060006000600073",
+                            "3000000000000000000000000000000000000000", "5af100"), 0),
+            account("0x3000000000000000000000000000000000000000", "0x5b600056", 0),
+        ];
+        let mut vm = SeqTransactionVM::<EmbeddedPatch>::new(
+            transaction(contract(), Gas::from(100000u64)), header());
+        answer_all(&accounts, &mut vm, |vm| {
+            while vm.current_state().map(|state| state.depth) != Some(2) {
+                vm.step()?;
+            }
+            Ok(())
+        }, |vm, commitment| vm.commit_account(commitment));
+
+        let next = SeqTransactionVM::<EmbeddedPatch>::with_previous(
+            transaction(contract(), Gas::from(100000u64)), header(), &vm);
+        assert!(vm.accounts().count() > 0);
+        assert_eq!(next.accounts().count(), vm.accounts().count());
+    }
+
+    #[test]
+    fn with_previous_keeps_code_cache() {
+        let accounts = [account("0x2000000000000000000000000000000000000000", "0x00", 0)];
+        let cache = Arc::new(CodeCache::<EmbeddedPatch>::new(16));
+        let mut vm = SeqTransactionVM::<EmbeddedPatch>::new(
+            transaction(contract(), Gas::from(100000u64)), header());
+        vm.set_code_cache(cache.clone());
+        vm.set_execution_mode(ExecutionMode::BasicBlock);
+        answer_all(&accounts, &mut vm, |vm| vm.fire(), |vm, commitment| vm.commit_account(commitment));
+
+        let mut next = SeqTransactionVM::<EmbeddedPatch>::with_previous(
+            transaction(contract(), Gas::from(100000u64)), header(), &vm);
+        answer_all(&accounts, &mut next, |vm| vm.fire(), |vm, commitment| vm.commit_account(commitment));
+
+        assert_eq!(next.status(), VMStatus::ExitedOk);
+        assert_eq!(cache.misses(), 1);
+        assert_eq!(cache.hits(), 1);
+    }
+
+    #[test]
+    fn signature_rules() {
+        // The example of EIP155, signed for chain id 1.
+        let raw = read_hex(concat!("0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0",
+                                   "b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620",
+                                   "aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")).unwrap();
+        let transaction: Transaction = rlp::decode(&raw);
+        let sender = Address::from_str("0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap();
+        assert_eq!(UntrustedTransaction::caller_of::<EmbeddedPatch>(&transaction).unwrap(), sender);
+
+        let mut zero_r = transaction.clone();
+        zero_r.signature.r = H256::default();
+        assert!(UntrustedTransaction::caller_of::<EmbeddedPatch>(&zero_r).is_err());
+
+        let mut invalid_v = transaction.clone();
+        invalid_v.signature.v = 29;
+        assert!(UntrustedTransaction::caller_of::<EmbeddedPatch>(&invalid_v).is_err());
+    }
 
     #[test]
     fn system_transaction() {
@@ -570,7 +848,7 @@ mod tests {
             gas_limit: Gas::from_str("0xffffffffffffffff").unwrap(),
             action: TransactionAction::Call(Address::default()),
             value: U256::from_str("0xffffffffffffffff").unwrap(),
-            input: Rc::new(Vec::new()),
+            input: Arc::new(Vec::new()),
             nonce: U256::zero(),
         };
         let mut vm = SeqTransactionVM::<EmbeddedPatch>::new(transaction, HeaderParams {
@@ -607,7 +885,7 @@ mod tests {
             gas_limit: Gas::from_str("0xffffffffffffffff").unwrap(),
             action: TransactionAction::Call(Address::default()),
             value: U256::from_str("0xffffffffffffffff").unwrap(),
-            input: Rc::new(Vec::new()),
+            input: Arc::new(Vec::new()),
             nonce: U256::zero(),
         };
         let mut vm = SeqTransactionVM::<EmbeddedPatch>::new(transaction, HeaderParams {
@@ -641,8 +919,8 @@ mod tests {
         let context = Context {
             address: Address::default(),
             caller: Address::default(),
-            code: Rc::new(read_hex("6c726576657274656420646174616000557f726576657274206d657373616765000000000000000000000000000000000000600052600e6000fd").unwrap()),
-            data: Rc::new(Vec::new()),
+            code: Arc::new(read_hex("6c726576657274656420646174616000557f726576657274206d657373616765000000000000000000000000000000000000600052600e6000fd").unwrap()),
+            data: Arc::new(Vec::new()),
             gas_limit: Gas::from(100000usize),
             gas_price: Gas::from(0usize),
             origin: Address::default(),
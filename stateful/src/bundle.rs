@@ -0,0 +1,208 @@
+//! Simulation of ordered transaction bundles.
+
+use bigint::{H256, U256, M256, Gas, Address};
+use sputnikvm::{ValidTransaction, HeaderParams, Memory, VM, VMStatus, Patch, AccountChange,
+                Log, RevertReason, CodeCache};
+use trie::DatabaseOwned;
+use block::Account;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use Stateful;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What to do with a transaction of the bundle that does not exit
+/// successfully.
+pub enum RevertPolicy {
+    /// Keep its effects, including the fee, as in a block.
+    Apply,
+    /// Drop its effects and continue with the next transaction.
+    Skip,
+    /// Drop its effects and do not run the rest of the bundle.
+    Stop,
+}
+
+#[derive(Debug, Clone)]
+/// Change of a single account made by a transaction.
+pub struct AccountDiff {
+    /// Address of the account.
+    pub address: Address,
+    /// The account before the transaction, if it existed.
+    pub before: Option<Account>,
+    /// The account after the transaction, if it exists.
+    pub after: Option<Account>,
+    /// Changed storage, as index, value before and value after.
+    pub storage: Vec<(U256, M256, M256)>,
+}
+
+#[derive(Debug, Clone)]
+/// Result of a transaction in a bundle.
+pub struct BundleTransactionResult {
+    /// Status the VM exited with.
+    pub status: VMStatus,
+    /// Whether the effects of the transaction are kept.
+    pub applied: bool,
+    /// Gas used by the transaction.
+    pub gas_used: Gas,
+    /// Returned data.
+    pub out: Vec<u8>,
+    /// Decoded revert reason, if the transaction reverted.
+    pub revert_reason: Option<RevertReason>,
+    /// Logs appended by the transaction.
+    pub logs: Vec<Log>,
+    /// Accounts changed by the transaction. Empty if it is not
+    /// applied.
+    pub state_diff: Vec<AccountDiff>,
+    /// Increase of the balance of the block beneficiary, including
+    /// the fee and direct transfers. Zero if it is not applied.
+    pub coinbase_payment: U256,
+}
+
+#[derive(Debug, Clone)]
+/// Result of a bundle.
+pub struct BundleResult {
+    /// Results of the transactions that were run, in order.
+    pub results: Vec<BundleTransactionResult>,
+    /// Total gas used by the applied transactions.
+    pub gas_used: Gas,
+    /// Total payment to the block beneficiary.
+    pub coinbase_payment: U256,
+    /// State root after the bundle.
+    pub state_root: H256,
+}
+
+fn same_account(a: &Option<Account>, b: &Option<Account>) -> bool {
+    match (a, b) {
+        (&Some(ref a), &Some(ref b)) =>
+            a.nonce == b.nonce && a.balance == b.balance &&
+            a.storage_root == b.storage_root && a.code_hash == b.code_hash,
+        (&None, &None) => true,
+        _ => false,
+    }
+}
+
+impl<'b, D: DatabaseOwned> Stateful<'b, D> {
+    fn storage_value(&self, account: &Option<Account>, index: U256) -> M256 {
+        match *account {
+            Some(ref account) => self.storage_state_of(account.storage_root)
+                .get(&H256::from(index)).unwrap_or_else(M256::zero),
+            None => M256::zero(),
+        }
+    }
+
+    fn state_diff(&self, before: H256, after: H256, changes: &[AccountChange]) -> Vec<AccountDiff> {
+        let mut diffs = Vec::new();
+        let mut seen = HashSet::new();
+
+        for change in changes {
+            let address = change.address();
+            if !seen.insert(address) {
+                continue;
+            }
+
+            let before_account: Option<Account> = self.state_of(before).get(&address);
+            let after_account: Option<Account> = self.state_of(after).get(&address);
+
+            let indexes: Vec<U256> = match *change {
+                AccountChange::Full { ref changing_storage, .. } =>
+                    changing_storage.indexes().cloned().collect(),
+                AccountChange::Create { ref storage, .. } =>
+                    storage.indexes().cloned().collect(),
+                _ => Vec::new(),
+            };
+            let mut storage = Vec::new();
+            for index in indexes {
+                let value_before = self.storage_value(&before_account, index);
+                let value_after = self.storage_value(&after_account, index);
+                if value_before != value_after {
+                    storage.push((index, value_before, value_after));
+                }
+            }
+            storage.sort_by(|a, b| a.0.cmp(&b.0));
+
+            if !same_account(&before_account, &after_account) || !storage.is_empty() {
+                diffs.push(AccountDiff {
+                    address,
+                    before: before_account,
+                    after: after_account,
+                    storage,
+                });
+            }
+        }
+
+        diffs
+    }
+
+    fn balance_at(&self, root: H256, address: Address) -> U256 {
+        let account: Option<Account> = self.state_of(root).get(&address);
+        account.map(|account| account.balance).unwrap_or_else(U256::zero)
+    }
+
+    /// Run the transactions in order, each one on the state left by
+    /// the previous ones, starting from the current root. The root of
+    /// this state is not changed; the root after the bundle is
+    /// returned in the result. The code cache, if given, is shared by
+    /// all transactions.
+    pub fn simulate_bundle<M: Memory + Default, P: Patch>(
+        &self, transactions: &[ValidTransaction], block: &HeaderParams,
+        most_recent_block_hashes: &[H256], policy: RevertPolicy,
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> BundleResult {
+        let mut stateful = Stateful::new(self.database, self.root);
+        let mut results = Vec::new();
+        let mut gas_used = Gas::zero();
+        let mut coinbase_payment = U256::zero();
+
+        for transaction in transactions {
+            let vm = stateful.call_with_cache::<M, P>(transaction.clone(), block, most_recent_block_hashes,
+                                                      code_cache.clone());
+            let status = vm.status();
+            let succeeded = status == VMStatus::ExitedOk;
+            let applied = match status {
+                VMStatus::ExitedOk => true,
+                VMStatus::ExitedErr(_) => policy == RevertPolicy::Apply,
+                VMStatus::ExitedNotSupported(_) | VMStatus::Running => false,
+            };
+
+            let mut result = BundleTransactionResult {
+                revert_reason: vm.revert_reason(),
+                status,
+                applied,
+                gas_used: vm.used_gas(),
+                out: vm.out().to_vec(),
+                logs: vm.logs().to_vec(),
+                state_diff: Vec::new(),
+                coinbase_payment: U256::zero(),
+            };
+
+            if applied {
+                let before = stateful.root();
+                let changes: Vec<AccountChange> = vm.accounts().cloned().collect();
+                stateful.transit(&changes);
+                let after = stateful.root();
+
+                result.state_diff = stateful.state_diff(before, after, &changes);
+                let balance_before = stateful.balance_at(before, block.beneficiary);
+                let balance_after = stateful.balance_at(after, block.beneficiary);
+                if balance_after > balance_before {
+                    result.coinbase_payment = balance_after - balance_before;
+                }
+
+                gas_used = gas_used + result.gas_used;
+                coinbase_payment = coinbase_payment + result.coinbase_payment;
+            }
+            results.push(result);
+
+            if !succeeded && policy == RevertPolicy::Stop {
+                break;
+            }
+        }
+
+        BundleResult {
+            results,
+            gas_used,
+            coinbase_payment,
+            state_root: stateful.root(),
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Execution of whole blocks.
+
+use bigint::{H256, U256, M256, Gas, Address};
+use sputnikvm::{HeaderParams, Memory, VM, VMStatus, Patch, AccountChange, ValidTransaction, Log,
+                CodeCache};
+use sputnikvm::errors::PreExecutionError;
+use trie::DatabaseOwned;
+use block::{Account, Block, Transaction};
+use rlp;
+use std::collections::{HashMap, HashSet};
+use std::cmp::max;
+use std::sync::Arc;
+use std::thread;
+
+use receipt::{Bloom, Receipt, ReceiptOutcome, receipts_root};
+use reward::RewardPatch;
+use Stateful;
+
+#[derive(Debug, Clone)]
+/// Errors when executing a block.
+pub enum BlockError {
+    /// The transaction at the index cannot be applied.
+    InvalidTransaction(usize, PreExecutionError),
+    /// The transaction at the index exceeds the block gas limit.
+    BlockGasLimitExceeded(usize),
+    /// The transaction at the index requires an unsupported
+    /// operation.
+    NotSupported(usize),
+    /// Gas used does not match the header.
+    GasUsedMismatch,
+    /// Logs bloom does not match the header.
+    LogsBloomMismatch,
+    /// Receipts root does not match the header.
+    ReceiptsRootMismatch,
+    /// State root does not match the header.
+    StateRootMismatch,
+}
+
+#[derive(Debug, Clone)]
+/// Result of executing a block.
+pub struct BlockResult {
+    /// Receipts of all transactions in the block.
+    pub receipts: Vec<Receipt>,
+    /// Total gas used by the block.
+    pub gas_used: Gas,
+    /// Bloom of all logs in the block.
+    pub logs_bloom: Bloom,
+    /// Root of the receipts trie.
+    pub receipts_root: H256,
+    /// State root after the block and its rewards.
+    pub state_root: H256,
+}
+
+/// Outcome of a transaction executed on its own, with what it read.
+struct Execution {
+    valid: ValidTransaction,
+    status: VMStatus,
+    used_gas: Gas,
+    logs: Vec<Log>,
+    accounts: Vec<AccountChange>,
+    used_addresses: HashSet<Address>,
+    used_storage: HashMap<Address, HashSet<U256>>,
+}
+
+#[derive(Default)]
+/// State written by the transactions applied so far in a block.
+struct Writes {
+    /// Accounts whose existence, nonce, balance or code changed.
+    accounts: HashSet<Address>,
+    /// Accounts created or removed, whose code and storage changed.
+    recreated: HashSet<Address>,
+    /// Storage values changed.
+    storage: HashSet<(Address, U256)>,
+}
+
+impl Writes {
+    /// Whether the execution read state written since it started, so
+    /// that running it on the current state may give another result.
+    fn conflicts(&self, execution: &Execution) -> bool {
+        for address in &execution.used_addresses {
+            if self.recreated.contains(address) {
+                return true;
+            }
+        }
+        for account in &execution.accounts {
+            match *account {
+                // The balance is increased without being read.
+                AccountChange::IncreaseBalance(_, _) => (),
+                _ => if self.accounts.contains(&account.address()) {
+                    return true;
+                },
+            }
+        }
+        for (address, indexes) in &execution.used_storage {
+            for index in indexes {
+                if self.storage.contains(&(*address, *index)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<'b, D: DatabaseOwned> Stateful<'b, D> {
+    fn run_block<M: Memory + Default, P: Patch, R: RewardPatch>(
+        &mut self, block: &Block, most_recent_block_hashes: &[H256],
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> Result<BlockResult, BlockError> {
+        let header = &block.header;
+        let params = HeaderParams::from(header);
+
+        let mut receipts = Vec::new();
+        let mut gas_used = Gas::zero();
+        let mut logs_bloom = Bloom::default();
+
+        for (i, transaction) in block.transactions.iter().enumerate() {
+            let valid = self.to_valid::<P>(transaction)
+                .map_err(|err| BlockError::InvalidTransaction(i, err))?;
+            if gas_used + valid.gas_limit > header.gas_limit {
+                return Err(BlockError::BlockGasLimitExceeded(i));
+            }
+
+            let vm = self.execute_with_cache::<M, P>(valid, &params, most_recent_block_hashes,
+                                                     code_cache.clone());
+            let status = match vm.status() {
+                VMStatus::ExitedOk => true,
+                VMStatus::ExitedErr(_) => false,
+                VMStatus::ExitedNotSupported(_) | VMStatus::Running =>
+                    return Err(BlockError::NotSupported(i)),
+            };
+
+            gas_used = gas_used + vm.used_gas();
+            let receipt = self.receipt::<R>(block, status, gas_used, vm.logs());
+            logs_bloom |= &receipt.logs_bloom;
+            receipts.push(receipt);
+        }
+
+        Ok(self.finish_block::<R>(block, receipts, gas_used, logs_bloom))
+    }
+
+    fn receipt<R: RewardPatch>(
+        &self, block: &Block, status: bool, cumulative_gas_used: Gas, logs: &[Log]
+    ) -> Receipt {
+        Receipt {
+            outcome: if R::has_receipt_status(block.header.number) {
+                ReceiptOutcome::Status(status)
+            } else {
+                ReceiptOutcome::StateRoot(self.root)
+            },
+            cumulative_gas_used,
+            logs_bloom: Bloom::from_logs(logs),
+            logs: logs.to_vec(),
+        }
+    }
+
+    fn finish_block<R: RewardPatch>(
+        &mut self, block: &Block, receipts: Vec<Receipt>, gas_used: Gas, logs_bloom: Bloom
+    ) -> BlockResult {
+        let header = &block.header;
+        let mut rewards = vec![AccountChange::IncreaseBalance(
+            header.beneficiary,
+            R::block_reward(header.number) +
+                R::inclusion_reward(header.number) * U256::from(block.ommers.len())
+        )];
+        for ommer in &block.ommers {
+            rewards.push(AccountChange::IncreaseBalance(
+                ommer.beneficiary, R::uncle_reward(header.number, ommer.number)
+            ));
+        }
+        self.transit(&rewards);
+
+        BlockResult {
+            receipts_root: receipts_root(&receipts),
+            receipts,
+            gas_used,
+            logs_bloom,
+            state_root: self.root,
+        }
+    }
+
+    /// Apply all transactions in the block and pay the rewards. If
+    /// any transaction cannot be applied, the state is left unchanged.
+    /// The code cache, if given, is shared by all transactions.
+    pub fn apply_block<M: Memory + Default, P: Patch, R: RewardPatch>(
+        &mut self, block: &Block, most_recent_block_hashes: &[H256],
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> Result<BlockResult, BlockError> {
+        let root = self.root;
+        let result = self.run_block::<M, P, R>(block, most_recent_block_hashes, code_cache);
+        if result.is_err() {
+            self.root = root;
+        }
+        result
+    }
+
+    /// Apply the block, and check gas used, logs bloom, receipts root
+    /// and state root against its header. If the check fails, the
+    /// state is left unchanged.
+    pub fn execute_block<M: Memory + Default, P: Patch, R: RewardPatch>(
+        &mut self, block: &Block, most_recent_block_hashes: &[H256],
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> Result<BlockResult, BlockError> {
+        let root = self.root;
+        let result = self.apply_block::<M, P, R>(block, most_recent_block_hashes, code_cache)?;
+        let header = &block.header;
+
+        let error = if result.gas_used != header.gas_used {
+            Some(BlockError::GasUsedMismatch)
+        } else if rlp::encode(&result.logs_bloom)[..] != rlp::encode(&header.logs_bloom)[..] {
+            Some(BlockError::LogsBloomMismatch)
+        } else if result.receipts_root != header.receipts_root {
+            Some(BlockError::ReceiptsRootMismatch)
+        } else if result.state_root != header.state_root {
+            Some(BlockError::StateRootMismatch)
+        } else {
+            None
+        };
+
+        match error {
+            Some(error) => {
+                self.root = root;
+                Err(error)
+            },
+            None => Ok(result),
+        }
+    }
+
+    fn speculate<M: Memory + Default, P: Patch>(
+        &self, transaction: &Transaction, params: &HeaderParams, most_recent_block_hashes: &[H256],
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> Result<Execution, PreExecutionError> {
+        let valid = self.to_valid::<P>(transaction)?;
+        let vm = self.call_with_cache::<M, P>(valid.clone(), params, most_recent_block_hashes, code_cache);
+
+        Ok(Execution {
+            valid,
+            status: vm.status(),
+            used_gas: vm.used_gas(),
+            logs: vm.logs().to_vec(),
+            accounts: vm.accounts().cloned().collect(),
+            used_addresses: vm.used_addresses(),
+            used_storage: vm.used_storage(),
+        })
+    }
+
+    /// Record the state the changes write, comparing them with the
+    /// current state. Must be called before the changes are applied.
+    fn record_writes(&self, accounts: &[AccountChange], writes: &mut Writes) {
+        let state = self.state();
+
+        for change in accounts {
+            let address = change.address();
+            match *change {
+                AccountChange::Full { nonce, balance, ref changing_storage, .. } => {
+                    let account: Account = state.get(&address).unwrap();
+                    if account.nonce != nonce || account.balance != balance {
+                        writes.accounts.insert(address);
+                    }
+
+                    let storage = self.storage_state_of(account.storage_root);
+                    let changing_storage: HashMap<U256, M256> = changing_storage.clone().into();
+                    for (index, value) in changing_storage {
+                        let current = storage.get(&H256::from(index)).unwrap_or_else(M256::zero);
+                        if current != value {
+                            writes.storage.insert((address, index));
+                        }
+                    }
+                },
+                AccountChange::IncreaseBalance(_, _) => {
+                    writes.accounts.insert(address);
+                },
+                AccountChange::Create { .. } | AccountChange::Nonexist(_) => {
+                    writes.accounts.insert(address);
+                    writes.recreated.insert(address);
+                },
+            }
+        }
+    }
+
+    fn run_block_parallel<M: Memory + Default, P: Patch, R: RewardPatch>(
+        &mut self, block: &Block, most_recent_block_hashes: &[H256], threads: usize,
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> Result<(BlockResult, Vec<usize>), BlockError> where D: Sync {
+        let header = &block.header;
+        let params = HeaderParams::from(header);
+        let threads = max(threads, 1);
+
+        // Run every transaction on the state before the block.
+        let mut speculations: Vec<Option<Execution>> = {
+            let stateful: &Self = self;
+            let transactions = &block.transactions;
+            let params = &params;
+            let code_cache = &code_cache;
+
+            thread::scope(|scope| {
+                let workers: Vec<_> = (0..threads).map(|worker| scope.spawn(move || {
+                    transactions.iter().enumerate()
+                        .filter(|&(i, _)| i % threads == worker)
+                        .map(|(i, transaction)| {
+                            (i, stateful.speculate::<M, P>(transaction, params, most_recent_block_hashes,
+                                                               code_cache.clone()).ok())
+                        })
+                        .collect::<Vec<_>>()
+                })).collect();
+
+                let mut speculations: Vec<Option<Execution>> = transactions.iter().map(|_| None).collect();
+                for worker in workers {
+                    for (i, execution) in worker.join().unwrap() {
+                        speculations[i] = execution;
+                    }
+                }
+                speculations
+            })
+        };
+
+        // Apply them in order, running again the ones that read state
+        // written by a previous transaction.
+        let mut receipts = Vec::new();
+        let mut gas_used = Gas::zero();
+        let mut logs_bloom = Bloom::default();
+        let mut writes = Writes::default();
+        let mut reexecuted = Vec::new();
+
+        for (i, transaction) in block.transactions.iter().enumerate() {
+            let execution = match speculations[i].take() {
+                Some(ref execution) if writes.conflicts(execution) => None,
+                execution => execution,
+            };
+            let execution = match execution {
+                Some(execution) => execution,
+                None => {
+                    reexecuted.push(i);
+                    self.speculate::<M, P>(transaction, &params, most_recent_block_hashes,
+                                           code_cache.clone())
+                        .map_err(|err| BlockError::InvalidTransaction(i, err))?
+                },
+            };
+
+            if gas_used + execution.valid.gas_limit > header.gas_limit {
+                return Err(BlockError::BlockGasLimitExceeded(i));
+            }
+            let status = match execution.status {
+                VMStatus::ExitedOk => true,
+                VMStatus::ExitedErr(_) => false,
+                VMStatus::ExitedNotSupported(_) | VMStatus::Running =>
+                    return Err(BlockError::NotSupported(i)),
+            };
+
+            self.record_writes(&execution.accounts, &mut writes);
+            self.transit(&execution.accounts);
+
+            gas_used = gas_used + execution.used_gas;
+            let receipt = self.receipt::<R>(block, status, gas_used, &execution.logs);
+            logs_bloom |= &receipt.logs_bloom;
+            receipts.push(receipt);
+        }
+
+        Ok((self.finish_block::<R>(block, receipts, gas_used, logs_bloom), reexecuted))
+    }
+
+    /// Apply the block like `apply_block`, but first run its
+    /// transactions in parallel on `threads` threads, each on the state
+    /// before the block. The results are then applied in order, and a
+    /// transaction that read accounts or storage written by a previous
+    /// one is run again, so that the post-state is the same as
+    /// sequential execution. Also returns the indexes of the
+    /// transactions that were run again. The code cache, if given, is
+    /// shared by all threads.
+    pub fn apply_block_parallel<M: Memory + Default, P: Patch, R: RewardPatch>(
+        &mut self, block: &Block, most_recent_block_hashes: &[H256], threads: usize,
+        code_cache: Option<Arc<CodeCache<P>>>
+    ) -> Result<(BlockResult, Vec<usize>), BlockError> where D: Sync {
+        let root = self.root;
+        let result = self.run_block_parallel::<M, P, R>(block, most_recent_block_hashes, threads,
+                                                         code_cache);
+        if result.is_err() {
+            self.root = root;
+        }
+        result
+    }
+}
@@ -9,18 +9,40 @@ extern crate sha3;
 extern crate block;
 extern crate rlp;
 extern crate bigint;
+extern crate hexutil;
+#[macro_use]
+extern crate serde_json;
 
 use bigint::{H256, U256, M256, Address};
 use sputnikvm::{ValidTransaction, HeaderParams, Memory, TransactionVM, VM,
-                AccountCommitment, Patch, AccountState, AccountChange};
+                AccountCommitment, Patch, AccountState, AccountChange, CodeCache};
 use sputnikvm::errors::{PreExecutionError, RequireError};
 use sha3::{Keccak256, Digest};
 use trie::{FixedSecureTrie, DatabaseGuard, MemoryDatabase, Database, DatabaseOwned};
 use block::{Account, Transaction};
 use std::collections::HashMap;
 use std::cmp::min;
-use std::rc::Rc;
+use std::sync::Arc;
 use std::ops::Deref;
+use std::io;
+
+mod bundle;
+mod execution;
+mod persistent;
+mod proof;
+mod receipt;
+mod reward;
+mod stateless;
+mod witness;
+
+pub use bundle::{RevertPolicy, AccountDiff, BundleTransactionResult, BundleResult};
+pub use execution::{BlockError, BlockResult};
+pub use persistent::{FileDatabase, FileDatabaseGuard};
+pub use proof::{ProofError, AccountProof, StorageProof, verify_proof, verify_account_proof};
+pub use receipt::{Bloom, Receipt, ReceiptOutcome, receipts_root};
+pub use reward::{RewardPatch, FoundationRewardPatch, ClassicRewardPatch};
+pub use stateless::{Stateless, StatelessError};
+pub use witness::{Witness, RecordingDatabase, RecordingDatabaseGuard};
 
 pub struct LiteralAccount {
     pub nonce: U256,
@@ -94,7 +116,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
                                 nonce: account.nonce,
                                 address,
                                 balance: account.balance,
-                                code: Rc::new(code),
+                                code: Arc::new(code),
                             }).unwrap();
                         },
                         None => {
@@ -115,7 +137,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
 
                             vm.commit_account(AccountCommitment::Code {
                                 address,
-                                code: Rc::new(code),
+                                code: Arc::new(code),
                             }).unwrap();
                         },
                         None => {
@@ -171,7 +193,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
                             nonce: account.nonce,
                             address,
                             balance: account.balance,
-                            code: Rc::new(code),
+                            code: Arc::new(code),
                         }
                     },
                     None => {
@@ -192,7 +214,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
 
                         AccountCommitment::Code {
                             address,
-                            code: Rc::new(code),
+                            code: Arc::new(code),
                         }
                     },
                     None => {
@@ -225,11 +247,23 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
     pub fn call<M: Memory + Default, P: Patch>(
         &self, transaction: ValidTransaction, block: &HeaderParams,
         most_recent_block_hashes: &[H256]
+    ) -> TransactionVM<M, P> {
+        self.call_with_cache(transaction, block, most_recent_block_hashes, None)
+    }
+
+    /// Call the transaction like `call`, looking up the analysis of
+    /// the code it runs in the cache if one is given.
+    pub fn call_with_cache<M: Memory + Default, P: Patch>(
+        &self, transaction: ValidTransaction, block: &HeaderParams,
+        most_recent_block_hashes: &[H256], code_cache: Option<Arc<CodeCache<P>>>
     ) -> TransactionVM<M, P> {
         assert!(U256::from(most_recent_block_hashes.len()) >=
                 min(block.number, U256::from(256)));
 
         let mut vm = TransactionVM::new(transaction, block.clone());
+        if let Some(cache) = code_cache {
+            vm.set_code_cache(cache);
+        }
         let state = self.database.create_fixed_secure_trie(self.root);
         let code_hashes = self.database.create_guard();
 
@@ -251,7 +285,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
                                 nonce: account.nonce,
                                 address,
                                 balance: account.balance,
-                                code: Rc::new(code),
+                                code: Arc::new(code),
                             }).unwrap();
                         },
                         None => {
@@ -272,7 +306,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
 
                             vm.commit_account(AccountCommitment::Code {
                                 address,
-                                code: Rc::new(code),
+                                code: Arc::new(code),
                             }).unwrap();
                         },
                         None => {
@@ -427,7 +461,16 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
         &mut self, transaction: ValidTransaction, block: &HeaderParams,
         most_recent_block_hashes: &[H256]
     ) -> TransactionVM<M, P> {
-        let vm = self.call::<_, P>(transaction, &block, most_recent_block_hashes);
+        self.execute_with_cache(transaction, block, most_recent_block_hashes, None)
+    }
+
+    /// Execute the transaction like `execute`, looking up the analysis
+    /// of the code it runs in the cache if one is given.
+    pub fn execute_with_cache<M: Memory + Default, P: Patch>(
+        &mut self, transaction: ValidTransaction, block: &HeaderParams,
+        most_recent_block_hashes: &[H256], code_cache: Option<Arc<CodeCache<P>>>
+    ) -> TransactionVM<M, P> {
+        let vm = self.call_with_cache::<_, P>(transaction, &block, most_recent_block_hashes, code_cache);
         let mut accounts = Vec::new();
         for account in vm.accounts() {
             accounts.push(account.clone());
@@ -436,6 +479,31 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
         vm
     }
 
+    pub fn call_with_witness<M: Memory + Default, P: Patch>(
+        &self, transaction: ValidTransaction, block: &HeaderParams,
+        most_recent_block_hashes: &[H256]
+    ) -> (TransactionVM<M, P>, Witness) {
+        let recording = RecordingDatabase::new(self.database);
+        let vm = Stateful::new(&recording, self.root)
+            .call::<_, P>(transaction, block, most_recent_block_hashes);
+        (vm, recording.witness(self.root))
+    }
+
+    pub fn execute_with_witness<M: Memory + Default, P: Patch>(
+        &mut self, transaction: ValidTransaction, block: &HeaderParams,
+        most_recent_block_hashes: &[H256]
+    ) -> (TransactionVM<M, P>, Witness) {
+        let recording = RecordingDatabase::new(self.database);
+        let (vm, root) = {
+            let mut stateful = Stateful::new(&recording, self.root);
+            let vm = stateful.execute::<_, P>(transaction, block, most_recent_block_hashes);
+            (vm, stateful.root())
+        };
+        let witness = recording.witness(self.root);
+        self.root = root;
+        (vm, witness)
+    }
+
     pub fn to_valid<P: Patch>(
         &self, transaction: &Transaction,
     ) -> Result<ValidTransaction, PreExecutionError> {
@@ -461,7 +529,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
                                 nonce: account.nonce,
                                 address,
                                 balance: account.balance,
-                                code: Rc::new(code),
+                                code: Arc::new(code),
                             }).unwrap();
                         },
                         None => {
@@ -482,7 +550,7 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
 
                             account_state.commit(AccountCommitment::Code {
                                 address,
-                                code: Rc::new(code),
+                                code: Arc::new(code),
                             }).unwrap();
                         },
                         None => {
@@ -543,6 +611,74 @@ impl<'b, D: DatabaseOwned> Stateful<'b, D> {
             None => None,
         }
     }
+
+    fn trie_proof(&self, root: H256, key: &[u8]) -> Vec<Vec<u8>> {
+        let guard = self.database.create_guard();
+        let mut nodes = Vec::new();
+
+        proof::walk(root, key, |hash| {
+            let node = guard.get(hash).ok_or(ProofError::MissingNode(hash))?;
+            nodes.push(node.clone());
+            Ok(node)
+        }).unwrap();
+
+        nodes
+    }
+
+    pub fn proof_of<'a>(&'a self, root: H256, address: Address, keys: &[U256]) -> AccountProof {
+        // Specify the full type as a workaround for https://github.com/rust-lang/rust/issues/55756
+        let state: FixedSecureTrie<<D as Database<'a>>::Guard, Address, Account> = self.state_of(root);
+        let account = state.get(&address).unwrap_or_else(|| Account {
+            nonce: U256::zero(),
+            balance: U256::zero(),
+            storage_root: proof::empty_trie_root(),
+            code_hash: proof::empty_code_hash(),
+        });
+        let storage: FixedSecureTrie<<D as Database<'a>>::Guard, H256, M256> =
+            self.storage_state_of(account.storage_root);
+
+        let storage_proof = keys.iter().map(|key| {
+            let index = H256::from(*key);
+
+            StorageProof {
+                key: *key,
+                value: storage.get(&index).unwrap_or_else(M256::zero),
+                proof: self.trie_proof(account.storage_root, &index[..]),
+            }
+        }).collect();
+
+        AccountProof {
+            address,
+            balance: account.balance,
+            code_hash: account.code_hash,
+            nonce: account.nonce,
+            storage_hash: account.storage_root,
+            account_proof: self.trie_proof(root, &address[..]),
+            storage_proof,
+        }
+    }
+
+    pub fn proof(&self, address: Address, keys: &[U256]) -> AccountProof {
+        self.proof_of(self.root(), address, keys)
+    }
+}
+
+impl<'a> Stateful<'a, FileDatabase> {
+    /// Open the stateful at the last committed root of the database,
+    /// or an empty state if nothing has been committed yet.
+    pub fn open(database: &'a FileDatabase) -> Self {
+        match database.root() {
+            Some(root) => Self::new(database, root),
+            None => Self::empty(database),
+        }
+    }
+
+    /// Atomically persist the current state root and all trie nodes
+    /// written since the last commit.
+    pub fn commit(&self) -> io::Result<()> {
+        self.database.commit(self.root)
+    }
 }
 
 pub type MemoryStateful<'a> = Stateful<'a, MemoryDatabase>;
+pub type FileStateful<'a> = Stateful<'a, FileDatabase>;
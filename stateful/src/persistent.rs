@@ -0,0 +1,238 @@
+//! Append-only, file-backed trie database.
+//!
+//! Every value written through a guard is kept in memory until
+//! `commit` is called. A commit appends all pending values to the log
+//! file, followed by a commit record holding the new state root and a
+//! Keccak-256 digest of the batch, and then syncs the file. When the
+//! file is opened again, the log is replayed up to the last commit
+//! record whose digest matches, and anything after it (a torn write
+//! from a crash) is truncated.
+
+use bigint::H256;
+use sha3::{Keccak256, Digest};
+use trie::{Database, DatabaseGuard};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write, Seek, SeekFrom, BufReader, ErrorKind};
+use std::path::Path;
+use std::sync::Mutex;
+
+const TAG_ENTRY: u8 = 0x01;
+const TAG_COMMIT: u8 = 0x02;
+const ENTRY_HEADER_LEN: usize = 1 + 32 + 4;
+const COMMIT_LEN: usize = 1 + 32 + 32;
+
+struct Inner {
+    file: File,
+    len: u64,
+    root: Option<H256>,
+    index: HashMap<H256, (u64, usize)>,
+    pending: HashMap<H256, Vec<u8>>,
+}
+
+impl Inner {
+    fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut value = vec![0u8; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut value)?;
+        Ok(value)
+    }
+}
+
+/// A durable database storing trie nodes and code in a single log
+/// file.
+///
+/// `DatabaseGuard::get` cannot report errors, so reading a committed
+/// value panics if the file cannot be read.
+pub struct FileDatabase(Mutex<Inner>);
+
+/// Guard of a `FileDatabase`.
+pub struct FileDatabaseGuard<'a>(&'a FileDatabase);
+
+fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn append(file: &mut File, offset: u64, buffer: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(buffer)?;
+    file.sync_data()
+}
+
+fn replay(file: &mut File) -> io::Result<(HashMap<H256, (u64, usize)>, Option<H256>, u64)> {
+    let file_len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+
+    let mut index = HashMap::new();
+    let mut root = None;
+    let mut committed = 0u64;
+
+    let mut batch = Vec::new();
+    let mut hasher = Keccak256::default();
+    let mut offset = 0u64;
+
+    loop {
+        let mut tag = [0u8; 1];
+        if !read_or_eof(&mut reader, &mut tag)? {
+            break;
+        }
+
+        match tag[0] {
+            TAG_ENTRY => {
+                let mut header = [0u8; ENTRY_HEADER_LEN - 1];
+                if !read_or_eof(&mut reader, &mut header)? {
+                    break;
+                }
+                let len = ((header[32] as usize) << 24) | ((header[33] as usize) << 16) |
+                    ((header[34] as usize) << 8) | (header[35] as usize);
+                // A length running past the end of the file comes from
+                // a torn or corrupted header.
+                if offset + (ENTRY_HEADER_LEN + len) as u64 > file_len {
+                    break;
+                }
+                let mut value = vec![0u8; len];
+                if !read_or_eof(&mut reader, &mut value)? {
+                    break;
+                }
+
+                hasher.input(&tag);
+                hasher.input(&header);
+                hasher.input(&value);
+                batch.push((H256::from(&header[0..32]), offset + ENTRY_HEADER_LEN as u64, len));
+                offset += (ENTRY_HEADER_LEN + len) as u64;
+            },
+            TAG_COMMIT => {
+                let mut body = [0u8; COMMIT_LEN - 1];
+                if !read_or_eof(&mut reader, &mut body)? {
+                    break;
+                }
+
+                hasher.input(&body[0..32]);
+                let digest = ::std::mem::replace(&mut hasher, Keccak256::default()).result();
+                if digest.as_slice() != &body[32..64] {
+                    break;
+                }
+
+                for (hash, value_offset, len) in batch.drain(..) {
+                    index.insert(hash, (value_offset, len));
+                }
+                root = Some(H256::from(&body[0..32]));
+                offset += COMMIT_LEN as u64;
+                committed = offset;
+            },
+            _ => break,
+        }
+    }
+
+    Ok((index, root, committed))
+}
+
+impl FileDatabase {
+    /// Open or create a database at the given path. Uncommitted or
+    /// corrupted data at the end of the file is discarded.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
+        let (index, root, len) = replay(&mut file)?;
+        file.set_len(len)?;
+        file.sync_all()?;
+
+        Ok(FileDatabase(Mutex::new(Inner {
+            file, len, root, index,
+            pending: HashMap::new(),
+        })))
+    }
+
+    /// The state root of the last successful commit, if any.
+    pub fn root(&self) -> Option<H256> {
+        self.0.lock().unwrap().root
+    }
+
+    /// Whether there are values written since the last commit.
+    pub fn is_dirty(&self) -> bool {
+        !self.0.lock().unwrap().pending.is_empty()
+    }
+
+    /// Atomically persist all values written since the last commit,
+    /// recording `root` as the current state root.
+    pub fn commit(&self, root: H256) -> io::Result<()> {
+        let mut inner = self.0.lock().unwrap();
+        let start = inner.len;
+
+        let mut buffer = Vec::new();
+        let mut locations = Vec::new();
+        for (hash, value) in &inner.pending {
+            let len = value.len() as u32;
+            buffer.push(TAG_ENTRY);
+            buffer.extend_from_slice(&hash[..]);
+            buffer.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+            locations.push((*hash, start + buffer.len() as u64, value.len()));
+            buffer.extend_from_slice(value);
+        }
+
+        let mut hasher = Keccak256::default();
+        hasher.input(&buffer);
+        hasher.input(&root[..]);
+        let digest = hasher.result();
+
+        buffer.push(TAG_COMMIT);
+        buffer.extend_from_slice(&root[..]);
+        buffer.extend_from_slice(digest.as_slice());
+
+        if let Err(err) = append(&mut inner.file, start, &buffer) {
+            let _ = inner.file.set_len(start);
+            return Err(err);
+        }
+
+        for (hash, offset, len) in locations {
+            inner.index.insert(hash, (offset, len));
+        }
+        inner.pending.clear();
+        inner.len = start + buffer.len() as u64;
+        inner.root = Some(root);
+
+        Ok(())
+    }
+
+    /// Discard all values written since the last commit.
+    pub fn rollback(&self) {
+        self.0.lock().unwrap().pending.clear();
+    }
+}
+
+impl<'a> DatabaseGuard for FileDatabaseGuard<'a> {
+    fn get(&self, hash: H256) -> Option<Vec<u8>> {
+        let mut inner = (self.0).0.lock().unwrap();
+        if let Some(value) = inner.pending.get(&hash) {
+            return Some(value.clone());
+        }
+
+        let (offset, len) = match inner.index.get(&hash) {
+            Some(&location) => location,
+            None => return None,
+        };
+        // Panics, as documented on `FileDatabase`: the trait has no
+        // way to report an I/O error, and a missing node would
+        // silently turn into a wrong state.
+        Some(inner.read_at(offset, len).expect("failed to read database file"))
+    }
+
+    fn set(&mut self, hash: H256, value: Vec<u8>) {
+        let mut inner = (self.0).0.lock().unwrap();
+        if !inner.index.contains_key(&hash) {
+            inner.pending.insert(hash, value);
+        }
+    }
+}
+
+impl<'a> Database<'a> for FileDatabase {
+    type Guard = FileDatabaseGuard<'a>;
+
+    fn create_guard(&'a self) -> FileDatabaseGuard<'a> {
+        FileDatabaseGuard(self)
+    }
+}
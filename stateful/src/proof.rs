@@ -0,0 +1,235 @@
+//! Merkle proofs of accounts and storage slots, in the format of
+//! `eth_getProof`.
+//!
+//! A proof is the list of RLP-encoded trie nodes, referenced by hash,
+//! on the path from a root to the key. Nodes shorter than 32 bytes are
+//! embedded in their parent and are not listed separately. Proofs can
+//! be verified against a root alone, without access to a database.
+
+use bigint::{H256, U256, M256, Address};
+use sha3::{Keccak256, Digest};
+use rlp::UntrustedRlp;
+use block::Account;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Errors when walking or verifying a proof.
+pub enum ProofError {
+    /// A node referenced on the path is not in the proof.
+    MissingNode(H256),
+    /// A node or value in the proof is not valid RLP, or does not
+    /// have the shape of a trie node.
+    InvalidNode,
+    /// The proof does not match the values it claims to prove.
+    ValueMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Proof of a single storage slot.
+pub struct StorageProof {
+    /// Index of the storage slot.
+    pub key: U256,
+    /// Value of the storage slot, zero if it does not exist.
+    pub value: M256,
+    /// Nodes from the storage root to the slot.
+    pub proof: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Proof of an account and some of its storage slots, as returned by
+/// `eth_getProof`. A nonexistent account is proved with zero nonce
+/// and balance, the empty code hash and the empty storage root.
+pub struct AccountProof {
+    /// Address of the account.
+    pub address: Address,
+    /// Balance of the account.
+    pub balance: U256,
+    /// Hash of the account code.
+    pub code_hash: H256,
+    /// Nonce of the account.
+    pub nonce: U256,
+    /// Storage root of the account.
+    pub storage_hash: H256,
+    /// Nodes from the state root to the account.
+    pub account_proof: Vec<Vec<u8>>,
+    /// Proofs of the requested storage slots.
+    pub storage_proof: Vec<StorageProof>,
+}
+
+impl From<::rlp::DecoderError> for ProofError {
+    fn from(_: ::rlp::DecoderError) -> ProofError {
+        ProofError::InvalidNode
+    }
+}
+
+pub fn empty_trie_root() -> H256 {
+    H256::from(Keccak256::digest(&[0x80]).as_slice())
+}
+
+pub fn empty_code_hash() -> H256 {
+    H256::from(Keccak256::digest(&[]).as_slice())
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    let hashed = Keccak256::digest(key);
+    let mut ret = Vec::with_capacity(64);
+    for byte in hashed.as_slice() {
+        ret.push(byte >> 4);
+        ret.push(byte & 0x0f);
+    }
+    ret
+}
+
+/// Decode a hex-prefix encoded path, returning the nibbles and
+/// whether the node is a leaf.
+pub fn decode_path(encoded: &[u8]) -> Result<(Vec<u8>, bool), ProofError> {
+    if encoded.is_empty() {
+        return Err(ProofError::InvalidNode);
+    }
+
+    let flag = encoded[0] >> 4;
+    if flag > 3 {
+        return Err(ProofError::InvalidNode);
+    }
+
+    let mut path = Vec::new();
+    if flag & 1 == 1 {
+        path.push(encoded[0] & 0x0f);
+    }
+    for byte in &encoded[1..] {
+        path.push(byte >> 4);
+        path.push(byte & 0x0f);
+    }
+    Ok((path, flag & 2 == 2))
+}
+
+/// Walk the secure trie at `root` down to `key`, fetching hashed nodes
+/// with `fetch`. Returns the RLP-encoded value, or `None` if the key
+/// is not in the trie.
+pub fn walk<F>(root: H256, key: &[u8], fetch: F) -> Result<Option<Vec<u8>>, ProofError> where
+    F: FnMut(H256) -> Result<Vec<u8>, ProofError>
+{
+    walk_path(root, key, false, fetch)
+}
+
+/// Like `walk`, but when `siblings` is set, also fetch the other
+/// child of every branch on the path that has only two children. Those
+/// are the nodes needed to collapse the branch if `key` is removed.
+pub fn walk_path<F>(root: H256, key: &[u8], siblings: bool, mut fetch: F) -> Result<Option<Vec<u8>>, ProofError> where
+    F: FnMut(H256) -> Result<Vec<u8>, ProofError>
+{
+    if root == empty_trie_root() {
+        return Ok(None);
+    }
+
+    let path = nibbles(key);
+    let mut path = &path[..];
+    let mut current = fetch(root)?;
+
+    loop {
+        let next = {
+            let node = UntrustedRlp::new(&current);
+            let child = match node.item_count()? {
+                2 => {
+                    let (partial, is_leaf) = decode_path(node.at(0)?.data()?)?;
+                    if is_leaf {
+                        return Ok(if partial[..] == path[..] {
+                            Some(node.at(1)?.data()?.to_vec())
+                        } else {
+                            None
+                        });
+                    }
+                    if !path.starts_with(&partial) {
+                        return Ok(None);
+                    }
+                    path = &path[partial.len()..];
+                    node.at(1)?
+                },
+                17 => {
+                    if path.is_empty() {
+                        let value = node.at(16)?.data()?;
+                        return Ok(if value.is_empty() { None } else { Some(value.to_vec()) });
+                    }
+                    if siblings {
+                        let mut others = Vec::new();
+                        for i in 0..17 {
+                            if i != path[0] as usize && !node.at(i)?.is_empty() {
+                                others.push(i);
+                            }
+                        }
+                        if others.len() == 1 && others[0] < 16 {
+                            let other = node.at(others[0])?;
+                            if other.is_data() && other.data()?.len() == 32 {
+                                fetch(H256::from(other.data()?))?;
+                            }
+                        }
+                    }
+                    let child = node.at(path[0] as usize)?;
+                    path = &path[1..];
+                    child
+                },
+                _ => return Err(ProofError::InvalidNode),
+            };
+
+            if child.is_empty() {
+                return Ok(None);
+            } else if child.is_list() {
+                child.as_raw().to_vec()
+            } else {
+                let hash = child.data()?;
+                if hash.len() != 32 {
+                    return Err(ProofError::InvalidNode);
+                }
+                fetch(H256::from(hash))?
+            }
+        };
+        current = next;
+    }
+}
+
+/// Look up `key` in the secure trie at `root` using only the nodes in
+/// `proof`. Returns the RLP-encoded value, or `None` if the proof shows
+/// that the key does not exist.
+pub fn verify_proof(root: H256, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>, ProofError> {
+    let nodes: HashMap<H256, &Vec<u8>> = proof.iter()
+        .map(|node| (H256::from(Keccak256::digest(node).as_slice()), node))
+        .collect();
+
+    walk(root, key, |hash| {
+        nodes.get(&hash).map(|node| (*node).clone()).ok_or(ProofError::MissingNode(hash))
+    })
+}
+
+/// Verify an account proof, and all storage proofs in it, against the
+/// given state root.
+pub fn verify_account_proof(root: H256, proof: &AccountProof) -> Result<(), ProofError> {
+    let value = verify_proof(root, &proof.address[..], &proof.account_proof)?;
+
+    let matches = match value {
+        Some(value) => {
+            let account: Account = UntrustedRlp::new(&value).as_val()?;
+            account.nonce == proof.nonce && account.balance == proof.balance &&
+                account.code_hash == proof.code_hash && account.storage_root == proof.storage_hash
+        },
+        None => {
+            proof.nonce == U256::zero() && proof.balance == U256::zero() &&
+                proof.code_hash == empty_code_hash() && proof.storage_hash == empty_trie_root()
+        },
+    };
+    if !matches {
+        return Err(ProofError::ValueMismatch);
+    }
+
+    for storage in &proof.storage_proof {
+        let value = verify_proof(proof.storage_hash, &H256::from(storage.key)[..], &storage.proof)?;
+        let value: M256 = match value {
+            Some(value) => UntrustedRlp::new(&value).as_val()?,
+            None => M256::zero(),
+        };
+        if value != storage.value {
+            return Err(ProofError::ValueMismatch);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Transaction receipts and logs bloom.
+
+use bigint::{H256, U256, Gas};
+use sputnikvm::Log;
+use sha3::{Keccak256, Digest};
+use trie::{Database, MemoryDatabase};
+use rlp::{self, Encodable, RlpStream};
+use std::ops::BitOrAssign;
+
+#[derive(Clone)]
+/// 2048-bit logs bloom filter.
+pub struct Bloom(pub [u8; 256]);
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom([0u8; 256])
+    }
+}
+
+impl PartialEq for Bloom {
+    fn eq(&self, other: &Bloom) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for Bloom { }
+
+impl ::std::fmt::Debug for Bloom {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "Bloom(0x")?;
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Bloom {
+    /// Add a value, either an address or a topic, to the bloom.
+    pub fn accrue(&mut self, value: &[u8]) {
+        let hash = Keccak256::digest(value);
+        for i in 0..3 {
+            let bit = (((hash[i * 2] as usize) << 8) | (hash[i * 2 + 1] as usize)) & 2047;
+            self.0[255 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Add a log, its address and all its topics, to the bloom.
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(&log.address[..]);
+        for topic in &log.topics {
+            self.accrue(&topic[..]);
+        }
+    }
+
+    /// Whether the bloom may contain the value.
+    pub fn contains(&self, value: &[u8]) -> bool {
+        let mut other = Bloom::default();
+        other.accrue(value);
+        self.0.iter().zip(other.0.iter()).all(|(a, b)| a & b == *b)
+    }
+
+    pub fn from_logs(logs: &[Log]) -> Bloom {
+        let mut bloom = Bloom::default();
+        for log in logs {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+}
+
+impl<'a> BitOrAssign<&'a Bloom> for Bloom {
+    fn bitor_assign(&mut self, other: &'a Bloom) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a |= *b;
+        }
+    }
+}
+
+impl Encodable for Bloom {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.append(&self.0.to_vec());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Outcome recorded in a receipt. Receipts before Byzantium hold the
+/// intermediate state root, and receipts after it hold a status code.
+pub enum ReceiptOutcome {
+    /// State root after the transaction.
+    StateRoot(H256),
+    /// Whether the transaction succeeded.
+    Status(bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Receipt of a transaction in a block.
+pub struct Receipt {
+    /// State root or status after the transaction.
+    pub outcome: ReceiptOutcome,
+    /// Gas used in the block up to and including this transaction.
+    pub cumulative_gas_used: Gas,
+    /// Bloom of the logs.
+    pub logs_bloom: Bloom,
+    /// Logs emitted by the transaction.
+    pub logs: Vec<Log>,
+}
+
+impl Encodable for Receipt {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(4);
+        match self.outcome {
+            ReceiptOutcome::StateRoot(root) => s.append(&root),
+            ReceiptOutcome::Status(status) => s.append(&U256::from(if status { 1 } else { 0 })),
+        };
+        s.append(&self.cumulative_gas_used);
+        s.append(&self.logs_bloom);
+        s.append_list(&self.logs);
+    }
+}
+
+/// Root of the trie of receipts, keyed by the RLP-encoded index of
+/// each receipt.
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+    let database = MemoryDatabase::new();
+    let mut trie = database.create_empty();
+
+    for (i, receipt) in receipts.iter().enumerate() {
+        trie.insert_raw(rlp::encode(&U256::from(i)).to_vec(), rlp::encode(receipt).to_vec());
+    }
+
+    trie.root()
+}
@@ -0,0 +1,90 @@
+//! Block rewards and receipt rules of networks.
+
+use bigint::U256;
+
+/// Block reward and receipt rules of a network.
+pub trait RewardPatch {
+    /// Reward to the beneficiary of block `number`, excluding the
+    /// reward for including uncles.
+    fn block_reward(number: U256) -> U256;
+    /// Reward to the beneficiary of an uncle at `uncle_number`
+    /// included in block `number`.
+    fn uncle_reward(number: U256, uncle_number: U256) -> U256;
+    /// Additional reward to the beneficiary of block `number` for each
+    /// uncle included.
+    fn inclusion_reward(number: U256) -> U256 {
+        Self::block_reward(number) / U256::from(32)
+    }
+    /// Whether receipts of block `number` hold a status code instead
+    /// of the intermediate state root.
+    fn has_receipt_status(number: U256) -> bool;
+}
+
+fn ether(value: u64) -> U256 {
+    U256::from(value) * U256::from(1_000_000_000_000_000_000u64)
+}
+
+fn frontier_uncle_reward(reward: U256, number: U256, uncle_number: U256) -> U256 {
+    if uncle_number + U256::from(8) < number {
+        return U256::zero();
+    }
+    (uncle_number + U256::from(8) - number) * reward / U256::from(8)
+}
+
+/// Rewards of the Ethereum Foundation network.
+pub struct FoundationRewardPatch;
+impl RewardPatch for FoundationRewardPatch {
+    fn block_reward(number: U256) -> U256 {
+        if number >= U256::from(7_280_000) {
+            ether(2)
+        } else if number >= U256::from(4_370_000) {
+            ether(3)
+        } else {
+            ether(5)
+        }
+    }
+
+    fn uncle_reward(number: U256, uncle_number: U256) -> U256 {
+        frontier_uncle_reward(Self::block_reward(number), number, uncle_number)
+    }
+
+    fn has_receipt_status(number: U256) -> bool {
+        number >= U256::from(4_370_000)
+    }
+}
+
+/// Rewards of the Ethereum Classic network, with the monetary policy
+/// of ECIP-1017.
+pub struct ClassicRewardPatch;
+impl ClassicRewardPatch {
+    fn era(number: U256) -> U256 {
+        if number == U256::zero() {
+            U256::zero()
+        } else {
+            (number - U256::one()) / U256::from(5_000_000)
+        }
+    }
+}
+impl RewardPatch for ClassicRewardPatch {
+    fn block_reward(number: U256) -> U256 {
+        let mut reward = ether(5);
+        let mut era = Self::era(number);
+        while era > U256::zero() {
+            reward = reward * U256::from(4) / U256::from(5);
+            era = era - U256::one();
+        }
+        reward
+    }
+
+    fn uncle_reward(number: U256, uncle_number: U256) -> U256 {
+        if Self::era(number) == U256::zero() {
+            frontier_uncle_reward(Self::block_reward(number), number, uncle_number)
+        } else {
+            Self::block_reward(number) / U256::from(32)
+        }
+    }
+
+    fn has_receipt_status(number: U256) -> bool {
+        number >= U256::from(8_772_000)
+    }
+}
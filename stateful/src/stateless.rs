@@ -0,0 +1,239 @@
+//! Execution against a block witness instead of a database.
+//!
+//! A witness is a state root together with the trie nodes and contract
+//! code that a block touches. Every `RequireError` raised by the VM is
+//! answered by walking the witness, and the post-state root is computed
+//! from the witness alone. If the witness lacks a node or code that is
+//! needed, a `StatelessError::WitnessIncomplete` is returned.
+
+use bigint::{H256, U256, M256, Address};
+use sputnikvm::{ValidTransaction, HeaderParams, Memory, TransactionVM, VM,
+                AccountCommitment, Patch, AccountState, AccountChange};
+use sputnikvm::errors::{PreExecutionError, RequireError};
+use sha3::{Keccak256, Digest};
+use trie::{Database, DatabaseGuard, MemoryDatabase};
+use rlp::UntrustedRlp;
+use block::{Account, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use proof::{self, ProofError};
+use witness::Witness;
+use Stateful;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Errors when executing against a witness.
+pub enum StatelessError {
+    /// A trie node or code with the given hash is needed but is not
+    /// in the witness.
+    WitnessIncomplete(H256),
+    /// A node or account in the witness cannot be decoded.
+    InvalidWitness,
+    /// The hash of the block with the given number is needed but was
+    /// not given.
+    MissingBlockhash(U256),
+}
+
+impl From<ProofError> for StatelessError {
+    fn from(val: ProofError) -> StatelessError {
+        match val {
+            ProofError::MissingNode(hash) => StatelessError::WitnessIncomplete(hash),
+            ProofError::InvalidNode | ProofError::ValueMismatch => StatelessError::InvalidWitness,
+        }
+    }
+}
+
+/// Driver that executes transactions using only a witness.
+pub struct Stateless {
+    database: MemoryDatabase,
+    root: H256,
+}
+
+impl Stateless {
+    pub fn new(witness: &Witness) -> Self {
+        let database = MemoryDatabase::default();
+        {
+            let mut guard = database.create_guard();
+            for value in witness.nodes.iter().chain(witness.codes.iter()) {
+                guard.set(H256::from(Keccak256::digest(value).as_slice()), value.clone());
+            }
+        }
+
+        Self {
+            database,
+            root: witness.root,
+        }
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    fn fetch(&self, hash: H256) -> Result<Vec<u8>, ProofError> {
+        self.database.create_guard().get(hash).ok_or(ProofError::MissingNode(hash))
+    }
+
+    fn account(&self, address: Address) -> Result<Option<Account>, StatelessError> {
+        match proof::walk(self.root, &address[..], |hash| self.fetch(hash))? {
+            Some(value) => Ok(Some(UntrustedRlp::new(&value).as_val()
+                                   .map_err(|_| StatelessError::InvalidWitness)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn code(&self, account: &Account) -> Result<Vec<u8>, StatelessError> {
+        if account.code_hash == proof::empty_code_hash() {
+            Ok(Vec::new())
+        } else {
+            Ok(self.fetch(account.code_hash)?)
+        }
+    }
+
+    fn storage(&self, account: &Account, index: U256) -> Result<M256, StatelessError> {
+        match proof::walk(account.storage_root, &H256::from(index)[..], |hash| self.fetch(hash))? {
+            Some(value) => UntrustedRlp::new(&value).as_val().map_err(|_| StatelessError::InvalidWitness),
+            None => Ok(M256::zero()),
+        }
+    }
+
+    /// Answer an account requirement from the witness. Block hashes
+    /// are not part of the witness, and requiring one returns
+    /// `StatelessError::MissingBlockhash`.
+    pub fn require_to_commit(
+        &self, require: &RequireError
+    ) -> Result<AccountCommitment, StatelessError> {
+        let address = match *require {
+            RequireError::Account(address) |
+            RequireError::AccountCode(address) |
+            RequireError::AccountStorage(address, _) => address,
+            RequireError::Blockhash(number) => return Err(StatelessError::MissingBlockhash(number)),
+        };
+
+        let account = match self.account(address)? {
+            Some(account) => account,
+            None => return Ok(AccountCommitment::Nonexist(address)),
+        };
+
+        Ok(match *require {
+            RequireError::Account(address) => AccountCommitment::Full {
+                nonce: account.nonce,
+                address,
+                balance: account.balance,
+                code: Arc::new(self.code(&account)?),
+            },
+            RequireError::AccountCode(address) => AccountCommitment::Code {
+                address,
+                code: Arc::new(self.code(&account)?),
+            },
+            RequireError::AccountStorage(address, index) => AccountCommitment::Storage {
+                address,
+                index,
+                value: self.storage(&account, index)?,
+            },
+            RequireError::Blockhash(_) => unreachable!(),
+        })
+    }
+
+    pub fn call<M: Memory + Default, P: Patch>(
+        &self, transaction: ValidTransaction, block: &HeaderParams,
+        most_recent_block_hashes: &[H256]
+    ) -> Result<TransactionVM<M, P>, StatelessError> {
+        let mut vm = TransactionVM::new(transaction, block.clone());
+
+        loop {
+            match vm.fire() {
+                Ok(()) => break,
+                Err(RequireError::Blockhash(number)) => {
+                    let hash = if number < block.number {
+                        most_recent_block_hashes.get((block.number - number).as_usize())
+                    } else {
+                        None
+                    };
+                    match hash {
+                        Some(hash) => vm.commit_blockhash(number, *hash).unwrap(),
+                        None => return Err(StatelessError::MissingBlockhash(number)),
+                    }
+                },
+                Err(require) => {
+                    vm.commit_account(self.require_to_commit(&require)?).unwrap();
+                },
+            }
+        }
+
+        Ok(vm)
+    }
+
+    /// Check that the witness holds every node needed to apply the
+    /// changes, so that updating the tries cannot run into a missing
+    /// node.
+    fn check_transit(&self, accounts: &[AccountChange]) -> Result<(), StatelessError> {
+        for account in accounts {
+            let (address, removing) = match *account {
+                AccountChange::Full { address, .. } |
+                AccountChange::IncreaseBalance(address, _) |
+                AccountChange::Create { address, .. } => (address, false),
+                AccountChange::Nonexist(address) => (address, true),
+            };
+            let value = proof::walk_path(self.root, &address[..], removing, |hash| self.fetch(hash))?;
+
+            if let AccountChange::Full { ref changing_storage, .. } = *account {
+                let account: Account = match value {
+                    Some(value) => UntrustedRlp::new(&value).as_val()
+                        .map_err(|_| StatelessError::InvalidWitness)?,
+                    None => return Err(StatelessError::InvalidWitness),
+                };
+                let changing_storage: HashMap<U256, M256> = changing_storage.clone().into();
+
+                for (key, value) in changing_storage {
+                    proof::walk_path(account.storage_root, &H256::from(key)[..],
+                                     value == M256::zero(), |hash| self.fetch(hash))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn transit(
+        &mut self, accounts: &[AccountChange]
+    ) -> Result<(), StatelessError> {
+        self.check_transit(accounts)?;
+
+        let root = {
+            let mut stateful = Stateful::new(&self.database, self.root);
+            stateful.transit(accounts);
+            stateful.root()
+        };
+        self.root = root;
+
+        Ok(())
+    }
+
+    pub fn execute<M: Memory + Default, P: Patch>(
+        &mut self, transaction: ValidTransaction, block: &HeaderParams,
+        most_recent_block_hashes: &[H256]
+    ) -> Result<TransactionVM<M, P>, StatelessError> {
+        let vm = self.call::<_, P>(transaction, &block, most_recent_block_hashes)?;
+        let mut accounts = Vec::new();
+        for account in vm.accounts() {
+            accounts.push(account.clone());
+        }
+        self.transit(&accounts)?;
+        Ok(vm)
+    }
+
+    pub fn to_valid<P: Patch>(
+        &self, transaction: &Transaction,
+    ) -> Result<Result<ValidTransaction, PreExecutionError>, StatelessError> {
+        let mut account_state = AccountState::default();
+
+        loop {
+            match ValidTransaction::from_transaction::<P>(transaction, &account_state) {
+                Ok(val) => return Ok(val),
+                Err(require) => {
+                    account_state.commit(self.require_to_commit(&require)?).unwrap();
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Block witnesses, and recording them from a database.
+//!
+//! `RecordingDatabase` wraps another database and remembers every
+//! value read through it that was not written in the same session.
+//! After running a transaction on top of it, the values read form the
+//! witness needed to replay that transaction with `Stateless`.
+
+use bigint::H256;
+use trie::{Database, DatabaseGuard, DatabaseOwned};
+use rlp::{self, Encodable, Decodable, RlpStream, UntrustedRlp, DecoderError};
+use block::Account;
+use hexutil::{read_hex, to_hex};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use proof;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// State root, trie nodes and code needed to execute a block.
+pub struct Witness {
+    /// State root the witness is rooted at.
+    pub root: H256,
+    /// RLP-encoded trie nodes, of both the state trie and storage
+    /// tries.
+    pub nodes: Vec<Vec<u8>>,
+    /// Code of the accounts touched.
+    pub codes: Vec<Vec<u8>>,
+}
+
+fn from_hex_list(value: &Value) -> Option<Vec<Vec<u8>>> {
+    let mut ret = Vec::new();
+    for item in value.as_array()? {
+        ret.push(read_hex(item.as_str()?).ok()?);
+    }
+    Some(ret)
+}
+
+fn collect_code_hashes(
+    node: &[u8], values: &HashMap<H256, Vec<u8>>, hashes: &mut HashSet<H256>
+) {
+    let node = UntrustedRlp::new(node);
+    let children = match node.item_count() {
+        Ok(2) => {
+            let is_leaf = match node.at(0).and_then(|path| path.data()) {
+                Ok(path) => match proof::decode_path(path) {
+                    Ok((_, is_leaf)) => is_leaf,
+                    Err(_) => return,
+                },
+                Err(_) => return,
+            };
+            if is_leaf {
+                let account: Result<Account, DecoderError> = node.at(1)
+                    .and_then(|value| value.data().map(|value| value.to_vec()))
+                    .and_then(|value| UntrustedRlp::new(&value).as_val());
+                if let Ok(account) = account {
+                    hashes.insert(account.code_hash);
+                }
+                return;
+            }
+            1..2
+        },
+        Ok(17) => 0..16,
+        _ => return,
+    };
+
+    for i in children {
+        let child = match node.at(i) {
+            Ok(child) => child,
+            Err(_) => continue,
+        };
+        if child.is_list() {
+            collect_code_hashes(child.as_raw(), values, hashes);
+        } else if let Ok(hash) = child.data() {
+            if hash.len() == 32 {
+                if let Some(value) = values.get(&H256::from(hash)) {
+                    collect_code_hashes(value, values, hashes);
+                }
+            }
+        }
+    }
+}
+
+impl Witness {
+    /// Build a witness from the values read from a database, sorting
+    /// them into trie nodes and code by walking the state trie at
+    /// `root`.
+    pub fn from_values(root: H256, values: HashMap<H256, Vec<u8>>) -> Self {
+        let mut code_hashes = HashSet::new();
+        if let Some(node) = values.get(&root) {
+            collect_code_hashes(node, &values, &mut code_hashes);
+        }
+
+        let mut values: Vec<(H256, Vec<u8>)> = values.into_iter().collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut nodes = Vec::new();
+        let mut codes = Vec::new();
+        for (hash, value) in values {
+            if code_hashes.contains(&hash) {
+                codes.push(value);
+            } else {
+                nodes.push(value);
+            }
+        }
+
+        Witness { root, nodes, codes }
+    }
+
+    pub fn to_rlp(&self) -> Vec<u8> {
+        rlp::encode(self).to_vec()
+    }
+
+    pub fn from_rlp(value: &[u8]) -> Result<Self, DecoderError> {
+        UntrustedRlp::new(value).as_val()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "root": format!("0x{:x}", self.root),
+            "nodes": self.nodes.iter().map(|node| to_hex(node)).collect::<Vec<_>>(),
+            "codes": self.codes.iter().map(|code| to_hex(code)).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let root = read_hex(value.get("root")?.as_str()?).ok()?;
+        if root.len() != 32 {
+            return None;
+        }
+
+        Some(Witness {
+            root: H256::from(root.as_slice()),
+            nodes: from_hex_list(value.get("nodes")?)?,
+            codes: from_hex_list(value.get("codes")?)?,
+        })
+    }
+}
+
+impl Encodable for Witness {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        s.append(&self.root);
+        s.begin_list(self.nodes.len());
+        for node in &self.nodes {
+            s.append(node);
+        }
+        s.begin_list(self.codes.len());
+        for code in &self.codes {
+            s.append(code);
+        }
+    }
+}
+
+impl Decodable for Witness {
+    fn decode(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+        Ok(Witness {
+            root: rlp.val_at(0)?,
+            nodes: rlp.list_at(1)?,
+            codes: rlp.list_at(2)?,
+        })
+    }
+}
+
+/// Database wrapper that records every value read through it.
+pub struct RecordingDatabase<'a, D: 'a> {
+    database: &'a D,
+    read: Mutex<HashMap<H256, Vec<u8>>>,
+    written: Mutex<HashSet<H256>>,
+}
+
+/// Guard of a `RecordingDatabase`.
+pub struct RecordingDatabaseGuard<'a, G> {
+    guard: G,
+    read: &'a Mutex<HashMap<H256, Vec<u8>>>,
+    written: &'a Mutex<HashSet<H256>>,
+}
+
+impl<'a, D> RecordingDatabase<'a, D> {
+    pub fn new(database: &'a D) -> Self {
+        RecordingDatabase {
+            database,
+            read: Mutex::new(HashMap::new()),
+            written: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Values read so far, keyed by their hash.
+    pub fn values(&self) -> HashMap<H256, Vec<u8>> {
+        self.read.lock().unwrap().clone()
+    }
+
+    /// The witness of everything read so far, rooted at `root`.
+    pub fn witness(&self, root: H256) -> Witness {
+        Witness::from_values(root, self.values())
+    }
+}
+
+impl<'a, G: DatabaseGuard> DatabaseGuard for RecordingDatabaseGuard<'a, G> {
+    fn get(&self, hash: H256) -> Option<Vec<u8>> {
+        let value = self.guard.get(hash);
+        if let Some(ref value) = value {
+            if !self.written.lock().unwrap().contains(&hash) {
+                self.read.lock().unwrap().insert(hash, value.clone());
+            }
+        }
+        value
+    }
+
+    fn set(&mut self, hash: H256, value: Vec<u8>) {
+        if !self.read.lock().unwrap().contains_key(&hash) {
+            self.written.lock().unwrap().insert(hash);
+        }
+        self.guard.set(hash, value)
+    }
+}
+
+impl<'a, 'b, D: DatabaseOwned> Database<'a> for RecordingDatabase<'b, D> {
+    type Guard = RecordingDatabaseGuard<'a, <D as Database<'a>>::Guard>;
+
+    fn create_guard(&'a self) -> Self::Guard {
+        RecordingDatabaseGuard {
+            guard: self.database.create_guard(),
+            read: &self.read,
+            written: &self.written,
+        }
+    }
+}
@@ -0,0 +1,390 @@
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+extern crate sputnikvm_stateful;
+extern crate secp256k1;
+extern crate block;
+extern crate trie;
+extern crate rlp;
+extern crate sha3;
+extern crate bigint;
+
+mod common;
+
+use bigint::{H256, H64, U256, Address, Gas};
+use sputnikvm::{ValidTransaction, HeaderParams, SeqMemory, SeqTransactionVM, TransactionAction, CodeCache};
+use sputnikvm::errors::PreExecutionError;
+use sputnikvm_network_classic::MainnetByzantiumPatch;
+use sputnikvm_stateful::{MemoryStateful, Bloom, RewardPatch, FoundationRewardPatch, ClassicRewardPatch,
+                         BlockError, BlockResult, ReceiptOutcome, receipts_root};
+use secp256k1::SECP256K1;
+use secp256k1::key::{SecretKey, PublicKey};
+use block::{Block, Header, Transaction, UnsignedTransaction, GlobalSignaturePatch};
+use trie::MemoryDatabase;
+use rlp::RlpStream;
+use sha3::{Digest, Keccak256};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use common::{address, account};
+
+type Memory = SeqMemory<MainnetByzantiumPatch>;
+
+/// Key of the sender of most transactions.
+const KEY: u8 = 0x42;
+/// Keys of other senders.
+const OTHER_KEYS: [u8; 2] = [0x43, 0x44];
+
+fn secret(key: u8) -> SecretKey {
+    SecretKey::from_slice(&SECP256K1, &[key; 32]).unwrap()
+}
+
+fn sender(key: u8) -> Address {
+    let public = PublicKey::from_secret_key(&SECP256K1, &secret(key)).unwrap();
+    let public = public.serialize_vec(&SECP256K1, false);
+    Address::from(&Keccak256::digest(&public[1..65])[12..])
+}
+
+/// Address of the contract created by `sender` with `nonce`.
+fn created(sender: Address, nonce: u64) -> Address {
+    let mut stream = RlpStream::new_list(2);
+    stream.append(&sender);
+    stream.append(&U256::from(nonce));
+    Address::from(&Keccak256::digest(&stream.out())[12..])
+}
+
+/// A transaction with a gas price of one.
+fn sign(key: u8, nonce: u64, action: TransactionAction, value: u64, input: Vec<u8>) -> Transaction {
+    UnsignedTransaction {
+        nonce: U256::from(nonce),
+        gas_price: Gas::from(1u64),
+        gas_limit: Gas::from(100_000u64),
+        action,
+        value: U256::from(value),
+        input,
+    }.sign::<GlobalSignaturePatch>(&secret(key))
+}
+
+/// A call from `sender(KEY)` with a gas price of one.
+fn signed(to: u64, nonce: u64) -> Transaction {
+    sign(KEY, nonce, TransactionAction::Call(address(to)), 10, Vec::new())
+}
+
+fn valid(to: u64, nonce: u64) -> ValidTransaction {
+    let mut transaction = common::transaction(0, to, nonce);
+    transaction.caller = Some(sender(KEY));
+    transaction.gas_price = Gas::from(1u64);
+    transaction.value = U256::from(10u64);
+    transaction
+}
+
+/// A block without ommers, whose header only sets the beneficiary,
+/// number and gas limit. Roots, gas used and bloom are empty.
+fn block(number: u64, transactions: Vec<Transaction>) -> Block {
+    let mut stream = RlpStream::new_list(15);
+    stream.append(&H256::default()); // parent hash
+    stream.append(&H256::default()); // ommers hash
+    stream.append(&address(0x40)); // beneficiary
+    stream.append(&H256::default()); // state root
+    stream.append(&H256::default()); // transactions root
+    stream.append(&H256::default()); // receipts root
+    stream.append(&Bloom::default()); // logs bloom
+    stream.append(&U256::zero()); // difficulty
+    stream.append(&U256::from(number)); // number
+    stream.append(&Gas::from(1_000_000u64)); // gas limit
+    stream.append(&Gas::zero()); // gas used
+    stream.append(&0u64); // timestamp
+    stream.append_empty_data(); // extra data
+    stream.append(&H256::default()); // mix hash
+    stream.append(&H64::default()); // nonce
+    let header: Header = rlp::decode(&stream.out());
+
+    Block {
+        header,
+        transactions,
+        ommers: Vec::new(),
+    }
+}
+
+/// Senders with some ether, an account that stores 1 at index 0,
+/// an account that reverts, and the contracts of the parallel tests.
+fn populate(stateful: &mut MemoryStateful) {
+    // SSTORE(0, EXTCODESIZE(created(sender(KEY), 0)))
+    let mut extcodesize = vec![0x73];
+    extcodesize.extend_from_slice(&created(sender(KEY), 0)[..]);
+    extcodesize.extend_from_slice(&[0x3b, 0x60, 0x00, 0x55, 0x00]);
+
+    stateful.sets(&[
+        (sender(KEY), account(1_000_000, Vec::new())),
+        (sender(OTHER_KEYS[0]), account(1_000_000, Vec::new())),
+        (sender(OTHER_KEYS[1]), account(1_000_000, Vec::new())),
+        // SSTORE(0, 1)
+        (address(0x20), account(0, vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x00])),
+        // REVERT(0, 0)
+        (address(0x30), account(0, vec![0x60, 0x00, 0x60, 0x00, 0xfd])),
+        // SSTORE(0, SLOAD(0) + 1)
+        (address(0x60), account(0, vec![0x60, 0x00, 0x54, 0x60, 0x01, 0x01, 0x60, 0x00, 0x55, 0x00])),
+        // SSTORE(0, BALANCE(COINBASE))
+        (address(0x61), account(0, vec![0x41, 0x31, 0x60, 0x00, 0x55, 0x00])),
+        (address(0x62), account(0, extcodesize)),
+        // With call data, SSTORE(0, 1). Without, REVERT(0, 0) if
+        // SLOAD(0) is not zero.
+        (address(0x63), account(0, vec![0x36, 0x60, 0x10, 0x57, 0x60, 0x00, 0x54, 0x60, 0x0b, 0x57, 0x00,
+                                         0x5b, 0x60, 0x00, 0x80, 0xfd,
+                                         0x5b, 0x60, 0x01, 0x60, 0x00, 0x55, 0x00])),
+    ]);
+}
+
+fn apply(stateful: &mut MemoryStateful, block: &Block) -> Result<BlockResult, BlockError> {
+    stateful.apply_block::<Memory, MainnetByzantiumPatch, FoundationRewardPatch>(block, &[], None)
+}
+
+fn execute(stateful: &mut MemoryStateful, block: &Block) -> Result<BlockResult, BlockError> {
+    stateful.execute_block::<Memory, MainnetByzantiumPatch, FoundationRewardPatch>(block, &[], None)
+}
+
+fn apply_parallel(stateful: &mut MemoryStateful, block: &Block) -> Result<(BlockResult, Vec<usize>), BlockError> {
+    stateful.apply_block_parallel::<Memory, MainnetByzantiumPatch, FoundationRewardPatch>(block, &[], 2, None)
+}
+
+/// Apply the block on the populated state both sequentially and in
+/// parallel, check that the results are the same, and return the
+/// indexes of the transactions run again.
+fn reexecuted(block: &Block) -> Vec<usize> {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let mut parallel = MemoryStateful::new(&database, stateful.root());
+
+    let expected = apply(&mut stateful, block).unwrap();
+    let (result, reexecuted) = apply_parallel(&mut parallel, block).unwrap();
+    assert_eq!(result.receipts, expected.receipts);
+    assert_eq!(result.state_root, expected.state_root);
+    assert_eq!(parallel.root(), stateful.root());
+    reexecuted
+}
+
+fn ether(value: u64) -> U256 {
+    U256::from(value) * U256::from(1_000_000_000_000_000_000u64)
+}
+
+#[test]
+fn foundation_rewards() {
+    assert_eq!(FoundationRewardPatch::block_reward(U256::from(1)), ether(5));
+    assert_eq!(FoundationRewardPatch::block_reward(U256::from(4_370_000)), ether(3));
+    assert_eq!(FoundationRewardPatch::block_reward(U256::from(7_280_000)), ether(2));
+    assert_eq!(FoundationRewardPatch::uncle_reward(U256::from(100), U256::from(99)), ether(35) / U256::from(8));
+    assert_eq!(FoundationRewardPatch::uncle_reward(U256::from(100), U256::from(80)), U256::zero());
+    assert_eq!(FoundationRewardPatch::inclusion_reward(U256::from(100)), ether(5) / U256::from(32));
+}
+
+#[test]
+fn classic_rewards() {
+    assert_eq!(ClassicRewardPatch::block_reward(U256::from(5_000_000)), ether(5));
+    assert_eq!(ClassicRewardPatch::block_reward(U256::from(5_000_001)), ether(4));
+    assert_eq!(ClassicRewardPatch::block_reward(U256::from(10_000_001)), ether(16) / U256::from(5));
+    assert_eq!(ClassicRewardPatch::uncle_reward(U256::from(5_000_001), U256::from(5_000_000)), ether(4) / U256::from(32));
+    assert!(!ClassicRewardPatch::has_receipt_status(U256::from(8_771_999)));
+    assert!(ClassicRewardPatch::has_receipt_status(U256::from(8_772_000)));
+}
+
+#[test]
+fn bloom() {
+    let address = Address::from_str("0x0f572e5295c57f15886f9b263e2f6d2d6c7b5ec6").unwrap();
+    let mut bloom = Bloom::default();
+    assert!(!bloom.contains(&address[..]));
+
+    bloom.accrue(&address[..]);
+    assert!(bloom.contains(&address[..]));
+    let bits: u32 = bloom.0.iter().map(|byte| byte.count_ones()).sum();
+    assert!(bits > 0 && bits <= 3);
+}
+
+#[test]
+fn empty_receipts_root() {
+    assert_eq!(receipts_root(&[]),
+               H256::from_str("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421").unwrap());
+}
+
+#[test]
+fn block_receipts_before_byzantium() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let root = stateful.root();
+
+    let result = apply(&mut stateful, &block(1, vec![signed(0x50, 0), signed(0x20, 1)])).unwrap();
+    assert_eq!(result.gas_used, Gas::from(62006u64));
+    assert_eq!(result.receipts.len(), 2);
+    assert_eq!(result.receipts[0].cumulative_gas_used, Gas::from(21000u64));
+    assert_eq!(result.receipts[1].cumulative_gas_used, Gas::from(62006u64));
+    assert_eq!(result.receipts_root, receipts_root(&result.receipts));
+    assert_eq!(result.logs_bloom, Bloom::default());
+    assert_eq!(result.state_root, stateful.root());
+
+    // Receipts hold the state root after each transaction, before the
+    // rewards.
+    let params = HeaderParams::from(&block(1, Vec::new()).header);
+    let mut sequential = MemoryStateful::new(&database, root);
+    let _: SeqTransactionVM<MainnetByzantiumPatch> = sequential.execute(valid(0x50, 0), &params, &[]);
+    assert_eq!(result.receipts[0].outcome, ReceiptOutcome::StateRoot(sequential.root()));
+    let _: SeqTransactionVM<MainnetByzantiumPatch> = sequential.execute(valid(0x20, 1), &params, &[]);
+    assert_eq!(result.receipts[1].outcome, ReceiptOutcome::StateRoot(sequential.root()));
+    assert!(sequential.root() != result.state_root);
+
+    let beneficiary = stateful.state().get(&address(0x40)).unwrap();
+    let fees: U256 = result.gas_used.into();
+    assert_eq!(beneficiary.balance, FoundationRewardPatch::block_reward(U256::one()) + fees);
+}
+
+#[test]
+fn block_receipts_after_byzantium() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+
+    let result = apply(&mut stateful, &block(4_370_000, vec![signed(0x20, 0), signed(0x30, 1)])).unwrap();
+    assert_eq!(result.receipts[0].outcome, ReceiptOutcome::Status(true));
+    assert_eq!(result.receipts[1].outcome, ReceiptOutcome::Status(false));
+    assert_eq!(result.receipts[0].cumulative_gas_used, Gas::from(41006u64));
+    assert_eq!(result.receipts[1].cumulative_gas_used, Gas::from(62012u64));
+    assert_eq!(result.gas_used, Gas::from(62012u64));
+}
+
+#[test]
+fn block_shares_code_cache() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let mut cached = MemoryStateful::new(&database, stateful.root());
+    let mut parallel = MemoryStateful::new(&database, stateful.root());
+
+    let block = block(4_370_000, vec![signed(0x20, 0), signed(0x20, 1)]);
+    let expected = apply(&mut stateful, &block).unwrap();
+
+    let cache = Arc::new(CodeCache::<MainnetByzantiumPatch>::new(16));
+    let result = cached.apply_block::<Memory, MainnetByzantiumPatch, FoundationRewardPatch>(
+        &block, &[], Some(cache.clone())).unwrap();
+    assert_eq!(result.state_root, expected.state_root);
+    assert!(cache.hits() > 0);
+
+    let cache = Arc::new(CodeCache::<MainnetByzantiumPatch>::new(16));
+    let (result, _) = parallel.apply_block_parallel::<Memory, MainnetByzantiumPatch, FoundationRewardPatch>(
+        &block, &[], 2, Some(cache.clone())).unwrap();
+    assert_eq!(result.state_root, expected.state_root);
+    assert!(cache.hits() + cache.misses() >= 2);
+}
+
+#[test]
+fn invalid_block_is_rolled_back() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let root = stateful.root();
+
+    // The first transaction is applied before the second one is found
+    // to reuse its nonce.
+    match apply(&mut stateful, &block(1, vec![signed(0x20, 0), signed(0x20, 0)])) {
+        Err(BlockError::InvalidTransaction(1, PreExecutionError::InvalidNonce)) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(stateful.root(), root);
+
+    let mut block = block(1, vec![signed(0x20, 0), signed(0x20, 1)]);
+    block.header.gas_limit = Gas::from(120_000u64);
+    match apply(&mut stateful, &block) {
+        Err(BlockError::BlockGasLimitExceeded(1)) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(stateful.root(), root);
+}
+
+#[test]
+fn execute_block_checks_header() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let root = stateful.root();
+
+    let mut block = block(1, vec![signed(0x20, 0), signed(0x50, 1)]);
+    let expected = apply(&mut MemoryStateful::new(&database, root), &block).unwrap();
+
+    match execute(&mut stateful, &block) {
+        Err(BlockError::GasUsedMismatch) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(stateful.root(), root);
+
+    block.header.gas_used = expected.gas_used;
+    match execute(&mut stateful, &block) {
+        Err(BlockError::ReceiptsRootMismatch) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(stateful.root(), root);
+
+    block.header.receipts_root = expected.receipts_root;
+    match execute(&mut stateful, &block) {
+        Err(BlockError::StateRootMismatch) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(stateful.root(), root);
+
+    block.header.state_root = expected.state_root;
+    let result = execute(&mut stateful, &block).unwrap();
+    assert_eq!(result.receipts, expected.receipts);
+    assert_eq!(stateful.root(), expected.state_root);
+}
+
+#[test]
+fn parallel_same_sender() {
+    // The second transaction has an invalid nonce on the state before
+    // the block.
+    assert_eq!(reexecuted(&block(1, vec![signed(0x20, 0), signed(0x50, 1)])), vec![1]);
+}
+
+#[test]
+fn parallel_storage_read_after_write() {
+    let increment = |key| sign(key, 0, TransactionAction::Call(address(0x60)), 0, Vec::new());
+    let block = block(1, vec![
+        increment(KEY),
+        increment(OTHER_KEYS[0]),
+        sign(OTHER_KEYS[1], 0, TransactionAction::Call(address(0x20)), 0, Vec::new()),
+    ]);
+    assert_eq!(reexecuted(&block), vec![1]);
+}
+
+#[test]
+fn parallel_coinbase_balance() {
+    // The fees of the first transaction are paid to the coinbase.
+    let block = block(1, vec![
+        signed(0x20, 0),
+        sign(OTHER_KEYS[0], 0, TransactionAction::Call(address(0x61)), 0, Vec::new()),
+    ]);
+    assert_eq!(reexecuted(&block), vec![1]);
+}
+
+#[test]
+fn parallel_created_account() {
+    // MSTORE8(0, 0), RETURN(0, 1)
+    let init = vec![0x60, 0x00, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3];
+    let block = block(1, vec![
+        sign(KEY, 0, TransactionAction::Create, 0, init),
+        sign(OTHER_KEYS[0], 0, TransactionAction::Call(address(0x62)), 0, Vec::new()),
+    ]);
+    assert_eq!(reexecuted(&block), vec![1]);
+}
+
+#[test]
+fn parallel_revert_after_sload() {
+    let block = block(4_370_000, vec![
+        sign(KEY, 0, TransactionAction::Call(address(0x63)), 0, vec![0x01]),
+        sign(OTHER_KEYS[0], 0, TransactionAction::Call(address(0x63)), 0, Vec::new()),
+    ]);
+    assert_eq!(reexecuted(&block), vec![1]);
+
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let (result, _) = apply_parallel(&mut stateful, &block).unwrap();
+    assert_eq!(result.receipts[0].outcome, ReceiptOutcome::Status(true));
+    assert_eq!(result.receipts[1].outcome, ReceiptOutcome::Status(false));
+}
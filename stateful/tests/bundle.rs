@@ -0,0 +1,93 @@
+extern crate sputnikvm;
+extern crate sputnikvm_stateful;
+extern crate sputnikvm_network_classic;
+extern crate trie;
+extern crate bigint;
+
+mod common;
+
+use bigint::{U256, M256, Gas};
+use sputnikvm::{ValidTransaction, SeqMemory, VMStatus, OnChainError, RevertReason};
+use sputnikvm_stateful::{MemoryStateful, RevertPolicy, BundleResult};
+use sputnikvm_network_classic::MainnetByzantiumPatch;
+use trie::MemoryDatabase;
+
+use common::{address, account};
+
+fn populate(stateful: &mut MemoryStateful) {
+    stateful.sets(&[
+        (address(0x10), account(1_000_000, Vec::new())),
+        // SSTORE(0, 1)
+        (address(0x20), account(0, vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x00])),
+        // REVERT(0, 0)
+        (address(0x30), account(0, vec![0x60, 0x00, 0x60, 0x00, 0xfd])),
+    ]);
+}
+
+fn transaction(to: u64, nonce: u64) -> ValidTransaction {
+    let mut transaction = common::transaction(0x10, to, nonce);
+    transaction.gas_price = Gas::from(1u64);
+    transaction
+}
+
+fn simulate(stateful: &MemoryStateful, transactions: &[ValidTransaction], policy: RevertPolicy) -> BundleResult {
+    let mut block = common::header();
+    block.beneficiary = address(0x40);
+    block.gas_limit = Gas::from(1_000_000u64);
+    stateful.simulate_bundle::<SeqMemory<MainnetByzantiumPatch>, MainnetByzantiumPatch>(
+        transactions, &block, &[], policy, None)
+}
+
+#[test]
+fn bundle_applies_reverted_transaction() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let root = stateful.root();
+
+    let result = simulate(&stateful, &[transaction(0x20, 0), transaction(0x30, 1), transaction(0x20, 2)],
+                          RevertPolicy::Apply);
+    assert_eq!(stateful.root(), root);
+    assert_eq!(result.results.len(), 3);
+
+    let first = &result.results[0];
+    assert_eq!(first.status, VMStatus::ExitedOk);
+    assert_eq!(first.gas_used, Gas::from(41006u64));
+    assert_eq!(first.coinbase_payment, U256::from(41006u64));
+    let diff = first.state_diff.iter().find(|diff| diff.address == address(0x20)).unwrap();
+    assert_eq!(diff.storage, vec![(U256::zero(), M256::zero(), M256::from(1u64))]);
+
+    let second = &result.results[1];
+    assert_eq!(second.status, VMStatus::ExitedErr(OnChainError::Revert));
+    assert_eq!(second.revert_reason, Some(RevertReason::Empty));
+    assert!(second.applied);
+
+    let gas_used = result.results.iter().fold(Gas::zero(), |gas, result| gas + result.gas_used);
+    let payment: U256 = gas_used.into();
+    assert_eq!(result.gas_used, gas_used);
+    assert_eq!(result.coinbase_payment, payment);
+}
+
+#[test]
+fn bundle_stops_on_revert() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+
+    let stopped = simulate(&stateful, &[transaction(0x20, 0), transaction(0x30, 1), transaction(0x20, 1)],
+                           RevertPolicy::Stop);
+    assert_eq!(stopped.results.len(), 2);
+    assert!(!stopped.results[1].applied);
+    assert!(stopped.results[1].state_diff.is_empty());
+
+    let single = simulate(&stateful, &[transaction(0x20, 0)], RevertPolicy::Stop);
+    assert_eq!(stopped.state_root, single.state_root);
+
+    let skipped = simulate(&stateful, &[transaction(0x20, 0), transaction(0x30, 1), transaction(0x20, 1)],
+                           RevertPolicy::Skip);
+    assert_eq!(skipped.results.len(), 3);
+    assert!(!skipped.results[1].applied);
+    assert!(skipped.results[2].applied);
+    // SSTORE on a slot that is already set.
+    assert_eq!(skipped.results[2].gas_used, Gas::from(26006u64));
+}
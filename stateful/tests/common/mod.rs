@@ -0,0 +1,58 @@
+//! Fixtures shared by the stateful integration tests. Each test
+//! binary uses a different subset of them.
+
+#![allow(dead_code)]
+
+use bigint::{U256, M256, Address, Gas};
+use sputnikvm::{ValidTransaction, HeaderParams, TransactionAction};
+use sputnikvm_stateful::{MemoryStateful, LiteralAccount};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn address(value: u64) -> Address {
+    Address::from(M256::from(value))
+}
+
+pub fn account(balance: u64, code: Vec<u8>) -> LiteralAccount {
+    LiteralAccount {
+        nonce: U256::zero(),
+        balance: U256::from(balance),
+        storage: HashMap::new(),
+        code,
+    }
+}
+
+/// Accounts `1` to `19`, where account `i` has a balance of `i *
+/// 1000` and holds `i` at storage index `0`.
+pub fn populate(stateful: &mut MemoryStateful) {
+    let accounts: Vec<(Address, LiteralAccount)> = (1..20u64).map(|i| {
+        let mut account = account(i * 1000, Vec::new());
+        account.storage.insert(U256::zero(), M256::from(i));
+        (address(i), account)
+    }).collect();
+
+    stateful.sets(&accounts);
+}
+
+/// A call without value and gas price.
+pub fn transaction(caller: u64, to: u64, nonce: u64) -> ValidTransaction {
+    ValidTransaction {
+        caller: Some(address(caller)),
+        gas_price: Gas::zero(),
+        gas_limit: Gas::from(100_000u64),
+        action: TransactionAction::Call(address(to)),
+        value: U256::zero(),
+        input: Arc::new(Vec::new()),
+        nonce: U256::from(nonce),
+    }
+}
+
+pub fn header() -> HeaderParams {
+    HeaderParams {
+        beneficiary: Address::default(),
+        timestamp: 0,
+        number: U256::zero(),
+        difficulty: U256::zero(),
+        gas_limit: Gas::max_value(),
+    }
+}
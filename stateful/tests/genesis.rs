@@ -22,7 +22,7 @@ use block::TransactionAction;
 use trie::{Database, MemoryDatabase};
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::rc::Rc;
+use std::sync::Arc;
 use rand::Rng;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -88,7 +88,7 @@ fn genesis_state_root() {
 
     let mut accounts: Vec<(&String, &JSONAccount)> = GENESIS_ACCOUNTS.iter().collect();
     rng.shuffle(&mut accounts);
-    let empty_input = Rc::new(Vec::new());
+    let empty_input = Arc::new(Vec::new());
 
     for (key, value) in accounts {
         let address = Address::from_str(key).unwrap();
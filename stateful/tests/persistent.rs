@@ -0,0 +1,108 @@
+extern crate sputnikvm;
+extern crate sputnikvm_stateful;
+extern crate bigint;
+
+mod common;
+
+use bigint::U256;
+use sputnikvm_stateful::{FileDatabase, FileStateful};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use common::{address, account};
+
+fn temp_path(name: &str) -> PathBuf {
+    let mut path = ::std::env::temp_dir();
+    path.push(format!("sputnikvm-stateful-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn reopen_committed() {
+    let path = temp_path("reopen");
+    let target = address(1);
+
+    let root = {
+        let database = FileDatabase::open(&path).unwrap();
+        let mut stateful = FileStateful::open(&database);
+        stateful.sets(&[(target, account(100, Vec::new()))]);
+        stateful.commit().unwrap();
+        assert!(!database.is_dirty());
+        stateful.root()
+    };
+
+    let database = FileDatabase::open(&path).unwrap();
+    assert_eq!(database.root(), Some(root));
+    let stateful = FileStateful::open(&database);
+    assert_eq!(stateful.root(), root);
+    assert_eq!(stateful.state().get(&target).unwrap().balance, U256::from(100));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn recover_torn_write() {
+    let path = temp_path("torn");
+    let first = address(1);
+    let second = address(2);
+
+    let root = {
+        let database = FileDatabase::open(&path).unwrap();
+        let mut stateful = FileStateful::open(&database);
+        stateful.sets(&[(first, account(100, Vec::new()))]);
+        stateful.commit().unwrap();
+        let root = stateful.root();
+
+        stateful.sets(&[(second, account(200, Vec::new()))]);
+        assert!(database.is_dirty());
+        database.rollback();
+        root
+    };
+
+    let len = fs::metadata(&path).unwrap().len();
+    {
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0x01, 0xde, 0xad, 0xbe, 0xef]).unwrap();
+    }
+
+    let database = FileDatabase::open(&path).unwrap();
+    assert_eq!(database.root(), Some(root));
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    let stateful = FileStateful::open(&database);
+    assert!(stateful.state().get(&first).is_some());
+    assert!(stateful.state().get(&second).is_none());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn recover_corrupted_length() {
+    let path = temp_path("length");
+    let target = address(1);
+
+    let root = {
+        let database = FileDatabase::open(&path).unwrap();
+        let mut stateful = FileStateful::open(&database);
+        stateful.sets(&[(target, account(100, Vec::new()))]);
+        stateful.commit().unwrap();
+        stateful.root()
+    };
+
+    // An entry header claiming a value of almost 4 GiB.
+    let len = fs::metadata(&path).unwrap().len();
+    {
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        let mut header = vec![0x01];
+        header.extend_from_slice(&[0u8; 32]);
+        header.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        file.write_all(&header).unwrap();
+    }
+
+    let database = FileDatabase::open(&path).unwrap();
+    assert_eq!(database.root(), Some(root));
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+
+    fs::remove_file(&path).unwrap();
+}
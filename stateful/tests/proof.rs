@@ -0,0 +1,71 @@
+extern crate sputnikvm;
+extern crate sputnikvm_stateful;
+extern crate trie;
+extern crate bigint;
+
+mod common;
+
+use bigint::{H256, U256, M256};
+use sputnikvm_stateful::{MemoryStateful, ProofError, verify_account_proof};
+use trie::MemoryDatabase;
+
+use common::{address, account, populate};
+
+#[test]
+fn existing_account_proof() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let mut seventh = account(700, vec![7; 7]);
+    seventh.nonce = U256::from(7);
+    seventh.storage.insert(U256::from(7), M256::from(14));
+    seventh.storage.insert(U256::from(1007), M256::from(21));
+    stateful.sets(&[(address(7), seventh)]);
+
+    let proof = stateful.proof(address(7), &[U256::from(7), U256::from(1007), U256::from(8)]);
+
+    assert_eq!(proof.balance, U256::from(700));
+    assert_eq!(proof.nonce, U256::from(7));
+    assert_eq!(proof.storage_proof[0].value, M256::from(14));
+    assert_eq!(proof.storage_proof[1].value, M256::from(21));
+    assert_eq!(proof.storage_proof[2].value, M256::zero());
+    assert!(!proof.account_proof.is_empty());
+    assert_eq!(verify_account_proof(stateful.root(), &proof), Ok(()));
+}
+
+#[test]
+fn nonexistent_account_proof() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+
+    let proof = stateful.proof(address(1000), &[U256::from(1)]);
+
+    assert_eq!(proof.balance, U256::zero());
+    assert_eq!(proof.storage_proof[0].value, M256::zero());
+    assert_eq!(verify_account_proof(stateful.root(), &proof), Ok(()));
+}
+
+#[test]
+fn tampered_proof() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+
+    let target = address(3);
+
+    let mut proof = stateful.proof(target, &[U256::zero()]);
+    proof.balance = U256::from(1);
+    assert_eq!(verify_account_proof(stateful.root(), &proof), Err(ProofError::ValueMismatch));
+
+    let mut proof = stateful.proof(target, &[U256::zero()]);
+    proof.storage_proof[0].value = M256::from(1);
+    assert_eq!(verify_account_proof(stateful.root(), &proof), Err(ProofError::ValueMismatch));
+
+    let mut proof = stateful.proof(target, &[]);
+    proof.account_proof.remove(0);
+    assert_eq!(verify_account_proof(stateful.root(), &proof), Err(ProofError::MissingNode(stateful.root())));
+
+    let proof = stateful.proof(target, &[]);
+    assert!(verify_account_proof(H256::from(U256::from(1)), &proof).is_err());
+}
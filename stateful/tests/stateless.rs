@@ -0,0 +1,92 @@
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+extern crate sputnikvm_stateful;
+extern crate trie;
+extern crate bigint;
+
+mod common;
+
+use bigint::{U256, Address};
+use sputnikvm::{ValidTransaction, SeqTransactionVM, VM, VMStatus};
+use sputnikvm_network_classic::MainnetEIP160Patch;
+use sputnikvm_stateful::{MemoryStateful, Stateless, StatelessError, Witness};
+use trie::MemoryDatabase;
+
+use common::{address, account, populate, header};
+
+fn transaction() -> ValidTransaction {
+    let mut transaction = common::transaction(1, 1000, 0);
+    transaction.value = U256::from(500);
+    transaction
+}
+
+fn witness(stateful: &MemoryStateful, codes: Vec<Vec<u8>>) -> Witness {
+    let mut witness = Witness {
+        root: stateful.root(),
+        nodes: Vec::new(),
+        codes,
+    };
+    for touched in &[address(1), address(1000), Address::default()] {
+        witness.nodes.extend(stateful.proof(*touched, &[]).account_proof);
+    }
+    witness
+}
+
+#[test]
+fn execute_from_witness() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+
+    let mut stateless = Stateless::new(&witness(&stateful, Vec::new()));
+    let vm: SeqTransactionVM<MainnetEIP160Patch> =
+        stateless.execute(transaction(), &header(), &[]).unwrap();
+    match vm.status() {
+        VMStatus::ExitedOk => (),
+        _ => panic!(),
+    }
+
+    let _: SeqTransactionVM<MainnetEIP160Patch> = stateful.execute(transaction(), &header(), &[]);
+    assert_eq!(stateless.root(), stateful.root());
+}
+
+#[test]
+fn incomplete_witness() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+
+    let stateless = Stateless::new(&Witness {
+        root: stateful.root(),
+        nodes: Vec::new(),
+        codes: Vec::new(),
+    });
+    let result = stateless.call::<sputnikvm::SeqMemory<MainnetEIP160Patch>, MainnetEIP160Patch>(
+        transaction(), &header(), &[]);
+
+    match result {
+        Err(StatelessError::WitnessIncomplete(hash)) => assert_eq!(hash, stateful.root()),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn missing_blockhash() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    // BLOCKHASH(0) and STOP.
+    let code = vec![0x60, 0x00, 0x40, 0x00];
+    stateful.sets(&[(address(1000), account(0, code.clone()))]);
+
+    let mut block = header();
+    block.number = U256::one();
+    let stateless = Stateless::new(&witness(&stateful, vec![code]));
+    let result = stateless.call::<sputnikvm::SeqMemory<MainnetEIP160Patch>, MainnetEIP160Patch>(
+        transaction(), &block, &[]);
+
+    match result {
+        Err(StatelessError::MissingBlockhash(number)) => assert_eq!(number, U256::zero()),
+        _ => panic!(),
+    }
+}
@@ -0,0 +1,52 @@
+extern crate sputnikvm;
+extern crate sputnikvm_network_classic;
+extern crate sputnikvm_stateful;
+extern crate trie;
+extern crate bigint;
+
+mod common;
+
+use bigint::{U256, M256};
+use sputnikvm::{ValidTransaction, SeqTransactionVM, VM, VMStatus};
+use sputnikvm_network_classic::MainnetEIP160Patch;
+use sputnikvm_stateful::{MemoryStateful, Stateless, Witness};
+use trie::MemoryDatabase;
+
+use common::{address, account, populate, header};
+
+// PUSH1 0x2a PUSH1 0x00 SSTORE
+const CODE: [u8; 5] = [0x60, 0x2a, 0x60, 0x00, 0x55];
+
+fn transaction() -> ValidTransaction {
+    let mut transaction = common::transaction(1, 2, 0);
+    transaction.value = U256::from(500);
+    transaction
+}
+
+#[test]
+fn recorded_witness_replays() {
+    let database = MemoryDatabase::default();
+    let mut stateful = MemoryStateful::empty(&database);
+    populate(&mut stateful);
+    let mut contract = account(2000, CODE.to_vec());
+    contract.storage.insert(U256::zero(), M256::from(2u64));
+    stateful.sets(&[(address(2), contract)]);
+    let root = stateful.root();
+
+    let (vm, witness): (SeqTransactionVM<MainnetEIP160Patch>, Witness) =
+        stateful.execute_with_witness(transaction(), &header(), &[]);
+    match vm.status() {
+        VMStatus::ExitedOk => (),
+        _ => panic!(),
+    }
+    assert_eq!(witness.root, root);
+    assert_eq!(witness.codes, vec![CODE.to_vec()]);
+
+    let witness = Witness::from_rlp(&witness.to_rlp()).unwrap();
+    let witness = Witness::from_json(&witness.to_json()).unwrap();
+
+    let mut stateless = Stateless::new(&witness);
+    let _: SeqTransactionVM<MainnetEIP160Patch> =
+        stateless.execute(transaction(), &header(), &[]).unwrap();
+    assert_eq!(stateless.root(), stateful.root());
+}